@@ -0,0 +1,49 @@
+use std::{
+    env,
+    error::Error,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Compiled with protox instead of protoc, so building doesn't need anything installed
+    let descriptors = protox::compile(["proto/mcstatus.proto"], ["proto"])?;
+    tonic_build::configure()
+        .build_client(false)
+        .compile_fds(descriptors)?;
+
+    // What `/version` reports. Builds without the repository, like in Docker, can pass the commit
+    let commit = env::var("MCSTATUS_GIT_COMMIT")
+        .ok()
+        .or_else(git_commit)
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=MCSTATUS_GIT_COMMIT={commit}");
+    // Reproducible builds set the time themselves
+    let built_at = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    println!("cargo:rustc-env=MCSTATUS_BUILD_TIMESTAMP={built_at}");
+    let mut features = env::vars()
+        .filter_map(|(name, _)| {
+            let feature = name.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_lowercase().replace('_', "-"))
+        })
+        .collect::<Vec<_>>();
+    features.sort();
+    println!("cargo:rustc-env=MCSTATUS_FEATURES={}", features.join(","));
+    Ok(())
+}
+
+fn git_commit() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    let commit = String::from_utf8(output.stdout).ok()?;
+    output.status.success().then(|| commit.trim().to_owned())
+}
@@ -0,0 +1,136 @@
+//! Bedrock edition servers, which answer a `RakNet` unconnected ping over UDP with a `;` separated
+//! description of themselves.
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::net::UdpSocket;
+
+use crate::{Error, Exit, MonitorOutput, Outbound, ServerStatus};
+
+/// The port Bedrock servers listen on unless told otherwise.
+pub const DEFAULT_PORT: u16 = 19132;
+
+/// How long to wait for the pong unless told otherwise. UDP gives no other sign of nobody
+/// listening.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Marks `RakNet` offline messages.
+const MAGIC: [u8; 16] = [
+    0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+];
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PONG: u8 = 0x1c;
+
+/// Gets the status of the Bedrock edition server at `url` from where `outbound` says, waiting up to
+/// `timeout` for it to answer. Servers that don't answer get a status with [`ServerStatus::error`]
+/// set and an exit code of 1, like `mc-monitor` reports them.
+///
+/// # Errors
+///
+/// [`Error::Backend`] if no local UDP socket could be opened.
+pub async fn fetch_status(
+    url: &SocketAddr,
+    timeout: Duration,
+    outbound: &Outbound,
+) -> Result<ServerStatus, Error> {
+    let socket = outbound
+        .bind_udp(url)
+        .await
+        .map_err(|e| Error::Backend(format!("Failed opening a UDP socket: {e}")))?;
+
+    let (output, error) = match tokio::time::timeout(timeout, ping(&socket, url)).await {
+        Ok(Ok(output)) => (Some(output), None),
+        Ok(Err(e)) => (None, Some(e)),
+        Err(_) => (None, Some(format!("No pong within {timeout:?}"))),
+    };
+
+    Ok(ServerStatus::new(
+        *url,
+        Exit::Code(i32::from(error.is_some())),
+        output,
+        error,
+    ))
+}
+
+async fn ping(socket: &UdpSocket, url: &SocketAddr) -> Result<MonitorOutput, String> {
+    socket
+        .connect(url)
+        .await
+        .map_err(|e| format!("Failed connecting to {url}: {e}"))?;
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_millis()).unwrap_or(u64::MAX));
+    let mut ping = vec![UNCONNECTED_PING];
+    ping.extend_from_slice(&millis.to_be_bytes());
+    ping.extend_from_slice(&MAGIC);
+    // Client GUID, which servers don't care about
+    ping.extend_from_slice(&0u64.to_be_bytes());
+    socket
+        .send(&ping)
+        .await
+        .map_err(|e| format!("Failed sending ping: {e}"))?;
+
+    let mut buffer = [0; 1500];
+    let length = socket
+        .recv(&mut buffer)
+        .await
+        .map_err(|e| format!("Failed receiving pong: {e}"))?;
+    parse_pong(&buffer[..length])
+}
+
+/// The pong is the packet id, the ping's time, the server's GUID, the magic, and then the length
+/// prefixed server id string, like
+/// `MCPE;Dedicated Server;589;1.20.1;2;10;1325386089;Bedrock level;Survival;1;19132;19133;`.
+fn parse_pong(pong: &[u8]) -> Result<MonitorOutput, String> {
+    const HEADER_LENGTH: usize = 1 + 8 + 8 + 16;
+
+    if pong.first() != Some(&UNCONNECTED_PONG) {
+        return Err("Answer was not an unconnected pong".to_owned());
+    }
+    if pong.get(17..HEADER_LENGTH) != Some(&MAGIC[..]) {
+        return Err("Pong did not contain the RakNet magic".to_owned());
+    }
+    let length = pong
+        .get(HEADER_LENGTH..HEADER_LENGTH + 2)
+        .map(|l| usize::from(u16::from_be_bytes([l[0], l[1]])))
+        .ok_or("Pong ended before the server id")?;
+    let server_id = pong
+        .get(HEADER_LENGTH + 2..HEADER_LENGTH + 2 + length)
+        .ok_or("Server id in the pong was cut off")?;
+    let server_id = std::str::from_utf8(server_id)
+        .map_err(|e| format!("Server id in the pong was not utf-8: {e}"))?;
+
+    let fields = server_id.split(';').collect::<Vec<_>>();
+    let field = |i: usize, name: &str| {
+        fields
+            .get(i)
+            .copied()
+            .ok_or_else(|| format!("Server id had no {name}: {server_id}"))
+    };
+    let count = |i: usize, name: &str| {
+        field(i, name)?
+            .parse::<u16>()
+            .map_err(|e| format!("Failed parsing {name} in {server_id}: {e}"))
+    };
+
+    let mut motd = field(1, "MOTD")?.to_owned();
+    // The second line is the level name on vanilla servers
+    if let Some(line) = fields.get(7).filter(|l| !l.is_empty()) {
+        motd.push('\n');
+        motd.push_str(line);
+    }
+
+    Ok(MonitorOutput {
+        version: field(3, "version")?.to_owned(),
+        protocol: field(2, "protocol").ok().and_then(|p| p.parse().ok()),
+        online_player_count: count(4, "online player count")?,
+        max_player_count: count(5, "max player count")?,
+        motd,
+        description_json: None,
+        enforces_secure_chat: None,
+        previews_chat: None,
+        chat_reporting: None,
+    })
+}
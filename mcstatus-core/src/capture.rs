@@ -0,0 +1,215 @@
+//! Annotated dumps of the packets exchanged in a Server List Ping, for servers whose status breaks
+//! somewhere on the way, like behind a proxy or plugin that mangles the handshake.
+
+use serde::Serialize;
+use std::{
+    fmt::Write,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Everything sent and received during one ping.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capture {
+    pub packets: Vec<Packet>,
+    /// Why the ping failed, if it did.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Packet {
+    pub direction: Direction,
+    /// What the packet should be, going by its place in the exchange, like `handshake`.
+    pub name: &'static str,
+    pub hex: String,
+    /// The packet split up into its fields, in order.
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Field {
+    pub name: &'static str,
+    pub hex: String,
+    /// What the bytes decode to, unset for those that didn't parse.
+    pub value: Option<String>,
+}
+
+/// The bytes of a ping as they went over the wire.
+#[derive(Debug, Default)]
+pub(crate) struct Transcript {
+    pub sent: Vec<u8>,
+    pub received: Vec<u8>,
+}
+
+impl Capture {
+    pub(crate) fn new(transcript: &Transcript, error: Option<String>) -> Self {
+        let sent = split(&transcript.sent).map(|bytes| (Direction::Sent, bytes));
+        let received = split(&transcript.received).map(|bytes| (Direction::Received, bytes));
+        let mut counts = [0, 0];
+        let packets = sent
+            .chain(received)
+            .map(|(direction, bytes)| {
+                let index = &mut counts[usize::from(direction == Direction::Received)];
+                *index += 1;
+                annotate(direction, *index - 1, bytes)
+            })
+            .collect();
+        Self { packets, error }
+    }
+}
+
+/// Splits `bytes` into length prefixed packets, the last one cut short if the length was more
+/// than what is there.
+fn split(mut bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        if bytes.is_empty() {
+            return None;
+        }
+        let end = decode_var_int(bytes).map_or(bytes.len(), |(length, prefix)| {
+            usize::try_from(length).map_or(bytes.len(), |length| {
+                prefix.saturating_add(length).min(bytes.len())
+            })
+        });
+        let (packet, rest) = bytes.split_at(end);
+        bytes = rest;
+        Some(packet)
+    })
+}
+
+fn annotate(direction: Direction, index: usize, bytes: &[u8]) -> Packet {
+    let mut fields = Fields {
+        bytes,
+        fields: Vec::new(),
+    };
+    let name = match (direction, index) {
+        (Direction::Sent, 0) => "handshake",
+        (Direction::Sent, 1) => "status request",
+        (Direction::Received, 0) => "status response",
+        _ => "unexpected",
+    };
+    // Stops at the first field that doesn't parse, which takes the rest of the bytes
+    let _: Option<()> = (|| {
+        fields.var_int("length")?;
+        fields.var_int("packet id")?;
+        match (direction, index) {
+            (Direction::Sent, 0) => {
+                fields.var_int("protocol version")?;
+                fields.string("host length", "host")?;
+                fields.u16("port")?;
+                fields.var_int("next state")?;
+            }
+            (Direction::Received, 0) => fields.string("json length", "json")?,
+            _ => {}
+        }
+        Some(())
+    })();
+    fields.rest();
+
+    Packet {
+        direction,
+        name,
+        hex: hex(bytes),
+        fields: fields.fields,
+    }
+}
+
+struct Fields<'a> {
+    bytes: &'a [u8],
+    fields: Vec<Field>,
+}
+
+impl Fields<'_> {
+    fn var_int(&mut self, name: &'static str) -> Option<i32> {
+        let (value, length) = decode_var_int(self.bytes)?;
+        self.take(name, length, value.to_string());
+        Some(value)
+    }
+
+    fn u16(&mut self, name: &'static str) -> Option<()> {
+        let value = self.bytes.get(..2)?;
+        let value = u16::from_be_bytes([value[0], value[1]]);
+        self.take(name, 2, value.to_string());
+        Some(())
+    }
+
+    fn string(&mut self, length_name: &'static str, name: &'static str) -> Option<()> {
+        let length = usize::try_from(self.var_int(length_name)?).ok()?;
+        let value = self.bytes.get(..length)?;
+        let value = String::from_utf8_lossy(value).into_owned();
+        self.take(name, length, value);
+        Some(())
+    }
+
+    /// Whatever is left, which the packet should not have.
+    fn rest(&mut self) {
+        if !self.bytes.is_empty() {
+            self.fields.push(Field {
+                name: "unparsed",
+                hex: hex(self.bytes),
+                value: None,
+            });
+            self.bytes = &[];
+        }
+    }
+
+    fn take(&mut self, name: &'static str, length: usize, value: String) {
+        let (field, rest) = self.bytes.split_at(length);
+        self.fields.push(Field {
+            name,
+            hex: hex(field),
+            value: Some(value),
+        });
+        self.bytes = rest;
+    }
+}
+
+/// The value and how many bytes it took, `None` if `bytes` ends first or it is too long.
+#[allow(clippy::cast_possible_wrap)]
+fn decode_var_int(bytes: &[u8]) -> Option<(i32, usize)> {
+    let mut value = 0u32;
+    for (i, byte) in bytes.iter().take(5).enumerate() {
+        value |= u32::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value as i32, i + 1));
+        }
+    }
+    None
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Copies whatever is read from `inner`.
+pub(crate) struct Tee<'a, R> {
+    pub inner: R,
+    pub copy: Option<&'a mut Vec<u8>>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Tee<'_, R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Some(copy) = &mut this.copy {
+            copy.extend_from_slice(&buf.filled()[before..]);
+        }
+        poll
+    }
+}
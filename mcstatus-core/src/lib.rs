@@ -0,0 +1,354 @@
+//! Querying the status of Minecraft servers, independent of the HTTP server in `mcstatus-http`, so
+//! anything else that needs to ping servers can reuse it.
+
+#![deny(
+    clippy::enum_glob_use,
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::unwrap_used
+)]
+
+pub mod bedrock;
+pub mod capture;
+pub mod mc_monitor;
+mod outbound;
+mod resolver;
+pub mod slp;
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{debug_span, Instrument};
+
+pub use mc_monitor::MonitorOutput;
+pub use outbound::{Outbound, Socks5Proxy};
+pub use resolver::{Resolver, TlsNameServer};
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// The address was malformed or could not be resolved.
+    InvalidAddress(String),
+    /// The backend itself failed, as opposed to the server not answering.
+    Backend(String),
+    /// The backend took too long and was given up on.
+    Timeout(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress(e) | Self::Backend(e) | Self::Timeout(e) => f.write_str(e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServerAddr {
+    pub domain_name: Option<DomainName>,
+    pub address: SocketAddr,
+    /// The other addresses the host name resolved to, which the native Java backend falls back to
+    /// when [`ServerAddr::address`] is slow to connect, in the order they are tried.
+    pub fallbacks: Vec<SocketAddr>,
+    /// Where the SRV record of the host name pointed, if it was followed.
+    pub srv_target: Option<SrvTarget>,
+    /// How long looking up the host name took, unset for IP addresses.
+    pub resolved_in: Option<Duration>,
+}
+
+/// A host name in both of its forms, as internationalized domain names are resolved in ASCII.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DomainName {
+    /// Like `bücher.example`, normalized to lowercase.
+    pub unicode: String,
+    /// Like `xn--bcher-kva.example`, the same as [`DomainName::unicode`] for plain ASCII names.
+    pub ascii: String,
+}
+
+impl ServerAddr {
+    /// How the host name resolved to [`ServerAddr::address`], unset for IP addresses.
+    #[must_use]
+    pub fn resolution(&self) -> Option<Resolution> {
+        Some(Resolution {
+            name: self.domain_name.as_ref()?.unicode.clone(),
+            srv_target: self.srv_target.clone(),
+            address: self.address,
+        })
+    }
+}
+
+/// The steps from the host name a server was asked for by to the address that answered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resolution {
+    /// Like `mc.example.com`.
+    pub name: String,
+    /// Where the SRV record of the name pointed, if it was followed.
+    pub srv_target: Option<SrvTarget>,
+    /// What the name, or the target of its SRV record, resolved to.
+    pub address: SocketAddr,
+}
+
+/// The host and port a `_minecraft._tcp` SRV record points to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SrvTarget {
+    pub host: String,
+    pub port: u16,
+}
+
+// The flags are independent of each other, filled in by whatever learns about them
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerStatus {
+    pub requested_url: SocketAddr,
+    pub exit: Exit,
+    pub output: Option<MonitorOutput>,
+    pub error: Option<String>,
+    /// Whether Bedrock players can join the server too, through Geyser. Left to whoever fetched
+    /// the status to find out, the backends always leave it unset.
+    pub crossplay: bool,
+    /// Port Bedrock players connect to, when [`ServerStatus::crossplay`] is set.
+    pub bedrock_port: Option<u16>,
+    /// The name the server was asked for by, filled in per request like [`ServerStatus::cache`]
+    /// as the same status is shared by every name of a server.
+    pub domain_name: Option<DomainName>,
+    /// How the name the server was asked for by led to [`ServerStatus::requested_url`], filled in
+    /// like [`ServerStatus::domain_name`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<Resolution>,
+    /// Whatever the operator tagged the server with, like its region. Left to whoever knows about
+    /// the server, the backends always leave it empty.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Where the server's address is, when a `GeoIP` database was configured. Left to whoever
+    /// fetched the status, like [`ServerStatus::crossplay`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geo: Option<Geo>,
+    /// Round trip of an ICMP echo to the server's address, to tell the network's latency apart
+    /// from the server's. Left to whoever fetched the status, like [`ServerStatus::crossplay`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icmp_rtt_ms: Option<f64>,
+    /// Whether the server is in a maintenance window, and expected to be down. Filled in per
+    /// request like [`ServerStatus::cache`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub maintenance: bool,
+    /// How long each stage of the ping took, only measured by the native Java backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Timings>,
+    /// Which of the [`ServerAddr::fallbacks`] the native Java backend got the status from, when it
+    /// connected before [`ServerStatus::requested_url`] did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connected_to: Option<SocketAddr>,
+    /// Whether the server keeps going up and down, filled in like [`ServerStatus::maintenance`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub flapping: bool,
+    /// Whether this is an earlier status served because fetching a new one failed, filled in like
+    /// [`ServerStatus::cache`].
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+    /// Why fetching a new status failed, when [`ServerStatus::stale`] is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_error: Option<String>,
+    /// What the server sent, only kept by the native Java backend.
+    #[serde(skip)]
+    pub raw: Option<RawStatus>,
+    /// Filled in by whatever caches statuses when it serves one, never stored in a cache itself.
+    pub cache: Option<CacheInfo>,
+    /// When the server answered, as RFC 3339. Left to whoever fetched the status, like
+    /// [`ServerStatus::crossplay`].
+    pub checked_at: Option<String>,
+    /// When the status was put in the cache it is served from, as RFC 3339. Later than
+    /// [`ServerStatus::checked_at`] for statuses that were reused, like those fetched by another
+    /// replica. Left to whatever caches statuses.
+    pub cached_at: Option<String>,
+    /// Deserialized statuses count as just fetched, whoever stored them has to keep their age.
+    #[serde(skip, default = "Instant::now")]
+    pub fetched_at: Instant,
+}
+
+impl ServerStatus {
+    /// A status as a backend fetched it, with everything that others fill in left unset.
+    #[must_use]
+    pub fn new(
+        requested_url: SocketAddr,
+        exit: Exit,
+        output: Option<MonitorOutput>,
+        error: Option<String>,
+    ) -> Self {
+        Self {
+            requested_url,
+            exit,
+            output,
+            error,
+            crossplay: false,
+            bedrock_port: None,
+            domain_name: None,
+            resolution: None,
+            labels: BTreeMap::new(),
+            geo: None,
+            icmp_rtt_ms: None,
+            maintenance: false,
+            flapping: false,
+            stale: false,
+            refresh_error: None,
+            timings: None,
+            connected_to: None,
+            raw: None,
+            cache: None,
+            checked_at: None,
+            cached_at: None,
+            fetched_at: Instant::now(),
+        }
+    }
+}
+
+/// How the backend finished. The native backends don't run anything, and report a code of 1 for
+/// servers that didn't answer like `mc-monitor` does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Exit {
+    Code(i32),
+    /// Killed by this signal, like by the OOM killer.
+    Signal(i32),
+    /// Killed for taking too long.
+    TimedOut,
+}
+
+/// What the `GeoIP` databases know about an address. Fields the databases don't have are unset.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Geo {
+    /// ISO 3166-1 code, like `DE`.
+    pub country: Option<String>,
+    /// Number of the autonomous system the address is announced by.
+    pub asn: Option<u32>,
+    /// Name of whoever runs that autonomous system, usually the hosting provider.
+    pub as_organization: Option<String>,
+}
+
+/// How long the stages of a ping took, to tell a slow resolver, a slow network and a slow server
+/// apart.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Timings {
+    /// Looking up the host name, filled in per request like [`ServerStatus::domain_name`] by
+    /// whoever resolved it, and unset for IP addresses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dns_ms: Option<f64>,
+    /// Establishing the TCP connection, through the proxy if there is one.
+    pub connect_ms: f64,
+    /// From sending the handshake and status request to the first byte of the response, a round
+    /// trip like `connect_ms` plus however long the server takes to come up with its status. A lot
+    /// more of it than of `connect_ms` points at a lagging server rather than a slow network.
+    pub handshake_ms: f64,
+    /// Reading the rest of the response, which takes a while for big favicons over slow networks.
+    pub status_ms: f64,
+    /// The whole status exchange, `handshake_ms` and `status_ms` together.
+    #[serde(default)]
+    pub exchange_ms: f64,
+}
+
+/// The status JSON exactly as a server sent it, for fields that aren't modeled in
+/// [`MonitorOutput`], like the ones server list plugins add.
+#[derive(Debug, Clone)]
+pub struct RawStatus {
+    pub json: Arc<str>,
+    /// Time from sending the status request to having read the whole response.
+    pub latency: Duration,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheInfo {
+    pub hit: bool,
+    pub age_seconds: f64,
+    pub expires_in_seconds: f64,
+}
+
+impl CacheInfo {
+    #[must_use]
+    pub fn new(fetched_at: Instant, ttl: Duration, hit: bool) -> Self {
+        let age = fetched_at.elapsed();
+        Self {
+            hit,
+            age_seconds: age.as_secs_f64(),
+            expires_in_seconds: ttl.saturating_sub(age).as_secs_f64(),
+        }
+    }
+}
+
+/// Gets the status of the server at `url`, through `mc-monitor` or natively, giving up after
+/// `timeout`. Only the native backend sends from where `outbound` says, tells the server it was
+/// asked for by `host`, the [ASCII form](DomainName::ascii) of its name if it has one, and falls
+/// back to the other addresses of the server in `fallbacks`. A server that doesn't answer is not
+/// an error, but a status with [`ServerStatus::error`] set.
+///
+/// # Errors
+///
+/// [`Error::Backend`] if the backend failed to get a status at all or `mc-monitor` was killed, and
+/// [`Error::Timeout`] if `mc-monitor` ran for longer than `timeout`.
+pub async fn fetch_status(
+    url: &SocketAddr,
+    host: Option<&str>,
+    fallbacks: &[SocketAddr],
+    use_mc_monitor: bool,
+    mc_monitor_executable: &str,
+    timeout: Duration,
+    outbound: &Outbound,
+) -> Result<ServerStatus, Error> {
+    // FIXME: Make sure this url is actually valid
+    let url_str = format!("{ip}:{port}", ip = url.ip(), port = url.port());
+    if use_mc_monitor {
+        let span = debug_span!("mc_monitor_fetch", url = url_str);
+        let status = mc_monitor::fetch_status(url, mc_monitor_executable, timeout)
+            .instrument(span)
+            .await?;
+        killed_as_error(status)
+    } else {
+        let span = debug_span!("slp_fetch", url = url_str);
+        slp::fetch_status(url, host, fallbacks, timeout, outbound)
+            .instrument(span)
+            .await
+    }
+}
+
+/// Like [`fetch_status`] for the Bedrock edition server at `url`, through
+/// `mc-monitor status-bedrock` or natively.
+///
+/// # Errors
+///
+/// The same as [`fetch_status`], and [`Error::Backend`] if the native backend couldn't open a UDP
+/// socket.
+pub async fn fetch_bedrock_status(
+    url: &SocketAddr,
+    use_mc_monitor: bool,
+    mc_monitor_executable: &str,
+    timeout: Duration,
+    outbound: &Outbound,
+) -> Result<ServerStatus, Error> {
+    let url_str = format!("{ip}:{port}", ip = url.ip(), port = url.port());
+    if use_mc_monitor {
+        let span = debug_span!("mc_monitor_bedrock_fetch", url = url_str);
+        let status = mc_monitor::fetch_bedrock_status(url, mc_monitor_executable, timeout)
+            .instrument(span)
+            .await?;
+        killed_as_error(status)
+    } else {
+        let span = debug_span!("bedrock_fetch", url = url_str);
+        bedrock::fetch_status(url, timeout, outbound)
+            .instrument(span)
+            .await
+    }
+}
+
+/// Whatever `mc-monitor` printed before being killed is no status of the server.
+fn killed_as_error(status: ServerStatus) -> Result<ServerStatus, Error> {
+    let error = || status.error.clone().unwrap_or_default();
+    match status.exit {
+        Exit::Code(_) => Ok(status),
+        Exit::Signal(_) => Err(Error::Backend(error())),
+        Exit::TimedOut => Err(Error::Timeout(error())),
+    }
+}
@@ -0,0 +1,381 @@
+//! The `mc-monitor` backend, which shells out to [mc-monitor](https://github.com/itzg/mc-monitor)
+//! and parses what it prints.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    process::{ExitStatus, Output},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio::{
+    io::AsyncReadExt,
+    process::{Child, Command},
+};
+use tracing::{info, warn};
+
+use crate::{slp::ChatReporting, Error, Exit, ServerStatus};
+
+static SPAWNED: AtomicU64 = AtomicU64::new(0);
+static RUNNING: AtomicU64 = AtomicU64::new(0);
+
+/// The `mc-monitor` processes of this process.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Children {
+    /// Ever spawned.
+    pub spawned: u64,
+    /// Not done yet.
+    pub running: u64,
+}
+
+#[must_use]
+pub fn children() -> Children {
+    Children {
+        spawned: SPAWNED.load(Ordering::Relaxed),
+        running: RUNNING.load(Ordering::Relaxed),
+    }
+}
+
+/// Counts a child as running until it is dropped along with it.
+struct Running;
+
+impl Running {
+    fn start() -> Self {
+        SPAWNED.fetch_add(1, Ordering::Relaxed);
+        RUNNING.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonitorOutput {
+    pub version: String,
+    /// Protocol number of the version, like 765 for 1.20.4, which names don't always tell apart,
+    /// as proxies report names of their own. `mc-monitor` doesn't report it.
+    pub protocol: Option<i32>,
+    pub online_player_count: u16,
+    pub max_player_count: u16,
+    /// The MOTD as plain text, without colors or formatting.
+    pub motd: String,
+    /// The MOTD as the chat component tree the server sent, with `text`, `extra`, colors and
+    /// formatting, for rendering it the way the client does. Only the native Java backend has it.
+    pub description_json: Option<serde_json::Value>,
+    /// Whether only players with signed chat can join, sent by 1.19.1 and later. Only the
+    /// native Java backend has it.
+    pub enforces_secure_chat: Option<bool>,
+    /// Whether the server previews chat messages, sent by 1.19 to 1.19.2.
+    pub previews_chat: Option<bool>,
+    /// Whether chat messages can be reported, worked out from the secure chat flag and the marker
+    /// the No Chat Reports mod adds. Only the native Java backend has it.
+    pub chat_reporting: Option<ChatReporting>,
+}
+
+impl MonitorOutput {
+    /// Parses what `mc-monitor status` prints for a server that answered, like
+    /// `host:25565 : version=1.20.4 online=7 max=50 motd='A Minecraft Server'`.
+    ///
+    /// The fields are read by key, so their order doesn't matter and ones this doesn't know are
+    /// skipped. Values run until the next ` key=`, so versions like `Paper 1.20.4` may have spaces.
+    /// `mc-monitor` doesn't escape the quoted MOTD, which can have quotes, `=` and newlines of its
+    /// own, so it runs until the last quote that ends the output or is followed by another field.
+    ///
+    /// # Errors
+    ///
+    /// A description of what was wrong with `output` if it was not in the expected format.
+    pub fn parse(output: &str) -> Result<Self, String> {
+        Self::parse_fields(output, true)
+    }
+
+    /// Parses what `mc-monitor status-bedrock` prints for a server that answered, which is the same
+    /// as [`MonitorOutput::parse`] except that versions of `mc-monitor` without the MOTD in it get
+    /// an empty one.
+    ///
+    /// # Errors
+    ///
+    /// A description of what was wrong with `output` if it was not in the expected format.
+    pub fn parse_bedrock(output: &str) -> Result<Self, String> {
+        Self::parse_fields(output, false)
+    }
+
+    fn parse_fields(output: &str, motd_required: bool) -> Result<Self, String> {
+        let output = output.trim_end();
+        let Some((_host, rest)) = output.split_once(" : ") else {
+            return Err(format!(
+                "Expected the server's address and ` : ` before its status: {output}"
+            ));
+        };
+        let fields = Fields::parse(rest)?;
+
+        let count = |key| {
+            let count = fields.get(key)?;
+            count
+                .parse()
+                .map_err(|e| format!("Failed parsing `{key}` player count {count:?}: {e}"))
+        };
+        let motd = match fields.get("motd") {
+            Err(_) if !motd_required => "",
+            motd => motd?,
+        };
+        let motd = motd
+            .strip_prefix('\'')
+            .and_then(|m| m.strip_suffix('\''))
+            .unwrap_or(motd);
+
+        Ok(Self {
+            version: fields.get("version")?.to_owned(),
+            protocol: None,
+            online_player_count: count("online")?,
+            max_player_count: count("max")?,
+            motd: motd.to_owned(),
+            description_json: None,
+            enforces_secure_chat: None,
+            previews_chat: None,
+            chat_reporting: None,
+        })
+    }
+}
+
+/// The `key=value` pairs of a status, in the order they were printed.
+struct Fields<'a>(Vec<(&'a str, &'a str)>);
+
+impl<'a> Fields<'a> {
+    fn parse(mut rest: &'a str) -> Result<Self, String> {
+        let mut fields = Vec::new();
+        while !rest.is_empty() {
+            let Some(key) = key_at(rest) else {
+                return Err(format!("Expected a `key=value` field, found: {rest}"));
+            };
+            let value = &rest[key.len() + 1..];
+            let end = if value.starts_with('\'') {
+                // The last closing quote, as the value may have quotes of its own
+                value
+                    .char_indices()
+                    .skip(1)
+                    .filter(|&(i, c)| c == '\'' && is_field_end(&value[i + 1..]))
+                    .last()
+                    .map(|(i, _)| i + 1)
+                    .ok_or_else(|| format!("`{key}` was missing its closing quote: {value}"))?
+            } else {
+                value
+                    .match_indices(' ')
+                    .map(|(i, _)| i)
+                    .find(|&i| is_field_end(&value[i..]))
+                    .unwrap_or(value.len())
+            };
+            fields.push((key, &value[..end]));
+            rest = value[end..].trim_start_matches(' ');
+        }
+        Ok(Self(fields))
+    }
+
+    fn get(&self, key: &str) -> Result<&'a str, String> {
+        self.0
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| {
+                let found = self.0.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+                format!("Status was missing `{key}`, found {found:?}")
+            })
+    }
+}
+
+/// The key `s` starts with if it starts with `key=`.
+fn key_at(s: &str) -> Option<&str> {
+    let (key, _) = s.split_once('=')?;
+    (!key.is_empty() && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')).then_some(key)
+}
+
+/// Whether a value can end right before `rest`, which is either nothing or another field.
+fn is_field_end(rest: &str) -> bool {
+    rest.is_empty()
+        || rest
+            .strip_prefix(' ')
+            .is_some_and(|rest| key_at(rest.trim_start_matches(' ')).is_some())
+}
+
+/// Gets the status of the server at `url` by running `mc-monitor status` against it. If it takes
+/// longer than `timeout`, it is killed. Either way of being killed gets a status with
+/// [`ServerStatus::exit`] saying so, and what happened in [`ServerStatus::error`].
+///
+/// # Errors
+///
+/// [`Error::Backend`] if `mc-monitor` couldn't be run or printed something unexpected.
+pub async fn fetch_status(
+    url: &SocketAddr,
+    mc_monitor_executable: &str,
+    timeout: Duration,
+) -> Result<ServerStatus, Error> {
+    run(
+        "status",
+        MonitorOutput::parse,
+        url,
+        mc_monitor_executable,
+        timeout,
+    )
+    .await
+}
+
+/// Like [`fetch_status`] for the Bedrock edition server at `url`, running
+/// `mc-monitor status-bedrock` against it.
+///
+/// # Errors
+///
+/// [`Error::Backend`] if `mc-monitor` couldn't be run or printed something unexpected.
+pub async fn fetch_bedrock_status(
+    url: &SocketAddr,
+    mc_monitor_executable: &str,
+    timeout: Duration,
+) -> Result<ServerStatus, Error> {
+    run(
+        "status-bedrock",
+        MonitorOutput::parse_bedrock,
+        url,
+        mc_monitor_executable,
+        timeout,
+    )
+    .await
+}
+
+fn spawn(
+    subcommand: &str,
+    url: &SocketAddr,
+    mc_monitor_executable: &str,
+) -> Result<(Child, Running), Error> {
+    let child = Command::new(mc_monitor_executable)
+        .arg(subcommand)
+        .args([
+            "-host",
+            &url.ip().to_string(),
+            "-port",
+            &url.port().to_string(),
+        ])
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        // Also covers the request being dropped while the child is still running
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| Error::Backend(format!("Failed to spawn mc-monitor: {e}")))?;
+    Ok((child, Running::start()))
+}
+
+async fn run(
+    subcommand: &str,
+    parse: fn(&str) -> Result<MonitorOutput, String>,
+    url: &SocketAddr,
+    mc_monitor_executable: &str,
+    timeout: Duration,
+) -> Result<ServerStatus, Error> {
+    let (mut child, _running) = spawn(subcommand, url, mc_monitor_executable)?;
+    info!("Spawned mc_monitor");
+
+    let mut stdout_pipe = child.stdout.take();
+    let mut stderr_pipe = child.stderr.take();
+    let run = async {
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let (stdout_read, stderr_read, status) = tokio::join!(
+            read_pipe(stdout_pipe.as_mut(), &mut stdout),
+            read_pipe(stderr_pipe.as_mut(), &mut stderr),
+            child.wait(),
+        );
+        stdout_read.and(stderr_read)?;
+        Ok::<_, std::io::Error>(Output {
+            status: status?,
+            stdout,
+            stderr,
+        })
+    };
+    let Ok(output) = tokio::time::timeout(timeout, run).await else {
+        warn!(?timeout, "mc_monitor took too long, killing it");
+        if let Err(e) = child.kill().await {
+            warn!(%e, "Failed killing mc_monitor");
+        }
+        return Ok(killed(
+            url,
+            Exit::TimedOut,
+            format!("mc-monitor did not finish within {timeout:?}"),
+        ));
+    };
+    let output = output.map_err(|e| Error::Backend(format!("Failed running mc_monitor: {e}")))?;
+    info!("mc_monitor exited");
+
+    let exit = exit(output.status);
+    if let Exit::Signal(signal) = exit {
+        warn!(signal, "mc_monitor was killed");
+        return Ok(killed(
+            url,
+            exit,
+            format!("mc-monitor was killed by signal {signal}"),
+        ));
+    }
+
+    let stderr = output.stderr;
+    let stderr = String::from_utf8(stderr.clone()).map_err(|e| {
+        Error::Backend(format!(
+            "mc-monitor outputted {stderr:?} on stderr, which was not utf-8: {e}"
+        ))
+    })?;
+    let stderr = (!stderr.is_empty()).then_some(stderr);
+
+    if stderr.is_some() {
+        info!("mc_monitor returned an error");
+    } else {
+        info!("mc_monitor return successfully");
+    }
+
+    let stdout = output.stdout;
+    let stdout = String::from_utf8(stdout.clone()).map_err(|e| {
+        Error::Backend(format!(
+            "mc-monitor outputted {stdout:?} on stdin, which was not utf-8: {e}"
+        ))
+    })?;
+
+    let output = if stderr.is_none() {
+        let output = parse(&stdout)
+            .map_err(|e| Error::Backend(format!("Failed parsing mc_monitor output: {e}")))?;
+        Some(output)
+    } else {
+        None
+    };
+
+    Ok(ServerStatus::new(url.to_owned(), exit, output, stderr))
+}
+
+fn killed(url: &SocketAddr, exit: Exit, error: String) -> ServerStatus {
+    ServerStatus::new(url.to_owned(), exit, None, Some(error))
+}
+
+#[cfg(unix)]
+fn exit(status: ExitStatus) -> Exit {
+    use std::os::unix::process::ExitStatusExt;
+
+    // Exactly one of these is set for a process that was waited on
+    status
+        .code()
+        .map(Exit::Code)
+        .or_else(|| status.signal().map(Exit::Signal))
+        .unwrap_or(Exit::Code(-1))
+}
+
+#[cfg(not(unix))]
+fn exit(status: ExitStatus) -> Exit {
+    // Processes always exit with a code outside of unix, even when killed
+    Exit::Code(status.code().unwrap_or(-1))
+}
+
+async fn read_pipe(
+    pipe: Option<&mut (impl AsyncReadExt + Unpin + Send)>,
+    buffer: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    if let Some(pipe) = pipe {
+        pipe.read_to_end(buffer).await?;
+    }
+    Ok(())
+}
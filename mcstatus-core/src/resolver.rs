@@ -0,0 +1,211 @@
+//! Resolving the addresses users give like the Java client does, following the `_minecraft._tcp`
+//! SRV record of host names asked for without a port, which lets servers be moved to another host
+//! and port without players having to know.
+
+use hickory_resolver::{
+    config::{
+        LookupIpStrategy, NameServerConfig, Protocol, ResolverConfig, ResolverOpts,
+        ServerOrderingStrategy,
+    },
+    proto::rr::rdata::SRV,
+    system_conf, TokioAsyncResolver,
+};
+use rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Instant,
+};
+use tracing::{debug, warn};
+
+use crate::{DomainName, Error, ServerAddr, SrvTarget};
+
+/// A name server reached over DNS-over-TLS.
+#[derive(Debug, Clone)]
+pub struct TlsNameServer {
+    /// Like `1.1.1.1:853`.
+    pub address: SocketAddr,
+    /// Name its certificate is checked against and that is sent as SNI, like
+    /// `cloudflare-dns.com`.
+    pub tls_name: String,
+}
+
+#[derive(Clone)]
+pub struct Resolver {
+    dns: TokioAsyncResolver,
+}
+
+impl Resolver {
+    /// Resolves with the name servers and options of the system, like those in
+    /// `/etc/resolv.conf`, and its hosts file. Systems without any get public name servers.
+    #[must_use]
+    pub fn system() -> Self {
+        let (config, options) = system_conf::read_system_conf().unwrap_or_else(|e| {
+            warn!(%e, "Failed reading the system's DNS config, resolving with public name servers");
+            (ResolverConfig::default(), ResolverOpts::default())
+        });
+        Self::new(config, options)
+    }
+
+    /// Resolves with `servers` over DNS-over-TLS, for networks where plain DNS is blocked or can't
+    /// be trusted. They are tried in order, and the system's hosts file is still consulted first.
+    #[must_use]
+    pub fn dns_over_tls(servers: &[TlsNameServer]) -> Self {
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+        let mut tls = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        // Left out by default, but some servers pick the certificate to present by it
+        tls.enable_sni = true;
+
+        let mut config = ResolverConfig::new();
+        for server in servers {
+            let mut name_server = NameServerConfig::new(server.address, Protocol::Tls);
+            name_server.tls_dns_name = Some(server.tls_name.clone());
+            config.add_name_server(name_server);
+        }
+        config.set_tls_client_config(Arc::new(tls));
+        let mut options = ResolverOpts::default();
+        options.server_ordering_strategy = ServerOrderingStrategy::UserProvidedOrder;
+        Self::new(config, options)
+    }
+
+    fn new(config: ResolverConfig, mut options: ResolverOpts) -> Self {
+        // Both families, so the native backend can fall back from one to the other, see `resolve`
+        options.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+        Self {
+            dns: TokioAsyncResolver::tokio(config, options),
+        }
+    }
+
+    /// Resolves a `host[:port]` string as given by users, with `default_port` for addresses
+    /// without one. With `srv` set, host names without a port go wherever their SRV record points,
+    /// if they have one. Internationalized host names are converted to punycode first.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidAddress`] if the address is malformed or doesn't resolve to anything.
+    pub async fn resolve(
+        &self,
+        addr: &str,
+        default_port: u16,
+        srv: bool,
+    ) -> Result<ServerAddr, Error> {
+        let (host, port) = match addr.split_once(':') {
+            None => (addr, None),
+            Some((_, port)) if port.contains(':') => {
+                return Err(Error::InvalidAddress(format!(
+                    "Invalid address {addr} for server, had too many `:`"
+                )))
+            }
+            Some((host, port)) => {
+                let port = port.parse::<u16>().map_err(|e| {
+                    Error::InvalidAddress(format!("addr {addr} was invalid: invalid port {e}"))
+                })?;
+                (host, Some(port))
+            }
+        };
+        if host.is_empty() {
+            return Err(Error::InvalidAddress("Input url was empty".to_owned()));
+        }
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(ServerAddr {
+                domain_name: None,
+                address: SocketAddr::new(ip, port.unwrap_or(default_port)),
+                fallbacks: Vec::new(),
+                srv_target: None,
+                resolved_in: None,
+            });
+        }
+
+        let ascii = idna::domain_to_ascii(host)
+            .map_err(|e| Error::InvalidAddress(format!("{host} is not a valid host name: {e}")))?;
+        let (unicode, _) = idna::domain_to_unicode(&ascii);
+        let started = Instant::now();
+        let srv_target = match port {
+            None if srv => self.srv_target(&ascii).await,
+            _ => None,
+        };
+        let (lookup, port) = srv_target.as_ref().map_or_else(
+            || (ascii.as_str(), port.unwrap_or(default_port)),
+            |target| (target.host.as_str(), target.port),
+        );
+        let ips =
+            self.dns.lookup_ip(lookup).await.map_err(|e| {
+                Error::InvalidAddress(format!("addr {lookup}:{port} was invalid: {e}"))
+            })?;
+        let mut ips = ips.iter().collect::<Vec<_>>();
+        if ips.is_empty() {
+            return Err(Error::InvalidAddress(format!(
+                "{lookup}:{port} was addr, no addr was there"
+            )));
+        }
+        // IPv4 first, like before both families were looked up, as every backend connects to the
+        // address and hosts without IPv6 are still common. Only the native backend falls back
+        let primary = ips.iter().position(IpAddr::is_ipv4).unwrap_or(0);
+        let address = SocketAddr::new(ips.remove(primary), port);
+
+        Ok(ServerAddr {
+            domain_name: Some(DomainName { unicode, ascii }),
+            address,
+            fallbacks: interleave(ips.into_iter())
+                .map(|ip| SocketAddr::new(ip, port))
+                .collect(),
+            srv_target,
+            resolved_in: Some(started.elapsed()),
+        })
+    }
+
+    /// Where the SRV record of `host` points, the record with the lowest priority and the highest
+    /// weight of them if it has several. Names without one are connected to directly, like they
+    /// are when looking it up fails.
+    async fn srv_target(&self, host: &str) -> Option<SrvTarget> {
+        let name = format!("_minecraft._tcp.{host}.");
+        let records = match self.dns.srv_lookup(name.as_str()).await {
+            Ok(records) => records,
+            Err(e) => {
+                debug!(name, %e, "No SRV record");
+                return None;
+            }
+        };
+        let record = records
+            .iter()
+            .min_by_key(|r| (r.priority(), std::cmp::Reverse(r.weight())))?;
+        target(record)
+    }
+}
+
+/// Orders the fallbacks in `ips` the way Happy Eyeballs (RFC 8305) tries them, alternating between
+/// IPv6 and IPv4 starting with IPv6, so a family that doesn't work is given up on quickly.
+fn interleave(ips: impl Iterator<Item = IpAddr>) -> impl Iterator<Item = IpAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = ips.partition(IpAddr::is_ipv6);
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    let mut ipv6_next = true;
+    std::iter::from_fn(move || {
+        let (first, second) = if ipv6_next {
+            (&mut v6, &mut v4)
+        } else {
+            (&mut v4, &mut v6)
+        };
+        ipv6_next = !ipv6_next;
+        first.next().or_else(|| second.next())
+    })
+}
+
+fn target(record: &SRV) -> Option<SrvTarget> {
+    let host = record.target().to_ascii();
+    let host = host.trim_end_matches('.');
+    // A target of `.` means the service is decidedly not available there
+    (!host.is_empty()).then(|| SrvTarget {
+        host: host.to_owned(),
+        port: record.port(),
+    })
+}
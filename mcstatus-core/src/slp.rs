@@ -0,0 +1,326 @@
+//! The native backend for Java edition servers, speaking the
+//! [Server List Ping](https://wiki.vg/Server_List_Ping) protocol directly instead of going through
+//! `mc-monitor`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{
+    capture::{Capture, Tee, Transcript},
+    Error, Exit, MonitorOutput, Outbound, RawStatus, ServerStatus, Timings,
+};
+
+/// How long connecting and exchanging the status may take in total, unless told otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Status responses are a few kilobytes at most, even with a favicon.
+const MAX_RESPONSE_LENGTH: usize = 1 << 20;
+
+#[derive(Deserialize)]
+struct Response {
+    version: Version,
+    players: Players,
+    #[serde(default)]
+    description: Value,
+    #[serde(rename = "enforcesSecureChat")]
+    enforces_secure_chat: Option<bool>,
+    #[serde(rename = "previewsChat")]
+    previews_chat: Option<bool>,
+    /// Added by the No Chat Reports mod, which strips signatures so messages can't be reported.
+    #[serde(rename = "preventsChatReports")]
+    prevents_chat_reports: Option<bool>,
+}
+
+/// How a server treats chat reporting, for badging servers in lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatReporting {
+    /// Only players with signed, and so reportable, chat can join.
+    Enforced,
+    /// Signed chat is accepted but not required.
+    Optional,
+    /// No Chat Reports is installed, so nothing can be reported.
+    Disabled,
+}
+
+impl ChatReporting {
+    /// Unknown when the server sends neither flag, like before 1.19.1.
+    const fn detect(
+        enforces_secure_chat: Option<bool>,
+        prevents_chat_reports: Option<bool>,
+    ) -> Option<Self> {
+        match (prevents_chat_reports, enforces_secure_chat) {
+            (Some(true), _) => Some(Self::Disabled),
+            (_, Some(true)) => Some(Self::Enforced),
+            (_, Some(false)) => Some(Self::Optional),
+            (_, None) => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Version {
+    name: String,
+    protocol: Option<i32>,
+}
+
+/// Wider than the counts of [`MonitorOutput`], as big networks report more than fit and some
+/// servers report nonsense like negative counts, which is no reason to reject the whole status.
+#[derive(Deserialize)]
+struct Players {
+    max: i64,
+    online: i64,
+}
+
+impl Players {
+    /// `count` clamped into what [`MonitorOutput`] holds.
+    fn saturate(count: i64) -> u16 {
+        u16::try_from(count.max(0)).unwrap_or(u16::MAX)
+    }
+}
+
+/// Gets the status of the Java edition server at `url` from where `outbound` says, giving up after
+/// `timeout` altogether, or after the connect and read timeouts of `outbound` for those steps.
+/// Connecting to `url` is raced against connecting to its `fallbacks`, the other addresses its
+/// host name resolved to, and the status comes from the first to connect. Servers that can't be
+/// reached or answer with garbage get a status with [`ServerStatus::error`] set and an exit code
+/// of 1, like `mc-monitor` reports them. The handshake names `host` as what the server was asked
+/// for by, or the IP address connected to without one.
+///
+/// # Errors
+///
+/// Never at the moment, the signature matches the other backends.
+pub async fn fetch_status(
+    url: &SocketAddr,
+    host: Option<&str>,
+    fallbacks: &[SocketAddr],
+    timeout: Duration,
+    outbound: &Outbound,
+) -> Result<ServerStatus, Error> {
+    let urls = [std::slice::from_ref(url), fallbacks].concat();
+    let (output, raw, timings, connected_to, error) =
+        match tokio::time::timeout(timeout, ping(&urls, host, outbound, None)).await {
+            Ok(Ok((output, raw, timings, connected_to))) => (
+                Some(output),
+                Some(raw),
+                Some(timings),
+                Some(connected_to).filter(|c| c != url),
+                None,
+            ),
+            Ok(Err(e)) => (None, None, None, None, Some(e)),
+            Err(_) => (
+                None,
+                None,
+                None,
+                None,
+                Some(format!("Timed out after {timeout:?}")),
+            ),
+        };
+
+    Ok(ServerStatus {
+        timings,
+        connected_to,
+        raw,
+        ..ServerStatus::new(*url, Exit::Code(i32::from(error.is_some())), output, error)
+    })
+}
+
+/// Pings the Java edition server at `url` like [`fetch_status`], keeping an annotated dump of every
+/// packet sent and received, including what was read before the ping failed.
+pub async fn capture(
+    url: &SocketAddr,
+    host: Option<&str>,
+    timeout: Duration,
+    outbound: &Outbound,
+) -> Capture {
+    let mut transcript = Transcript::default();
+    let urls = [*url];
+    let error =
+        match tokio::time::timeout(timeout, ping(&urls, host, outbound, Some(&mut transcript)))
+            .await
+        {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => Some(e),
+            Err(_) => Some(format!("Timed out after {timeout:?}")),
+        };
+    Capture::new(&transcript, error)
+}
+
+/// Pings the first of `urls` to connect, returning which one that was along with its status.
+/// Virtual host proxies, like Velocity with forced hosts, pick the server behind them by the host
+/// in the handshake, so it is the name the server was asked for, and the IP address connected to
+/// only when there is none.
+async fn ping(
+    urls: &[SocketAddr],
+    host: Option<&str>,
+    outbound: &Outbound,
+    mut transcript: Option<&mut Transcript>,
+) -> Result<(MonitorOutput, RawStatus, Timings, SocketAddr), String> {
+    let started = Instant::now();
+    let (mut stream, url) = outbound.connect_tcp_racing(urls).await.map_err(|e| {
+        let urls = urls.iter().map(ToString::to_string).collect::<Vec<_>>();
+        format!("Failed connecting to {}: {e}", urls.join(", "))
+    })?;
+
+    let mut handshake = vec![0x00];
+    // -1 asks for whatever version the server is running
+    write_var_int(&mut handshake, -1);
+    let host = host.map_or_else(|| url.ip().to_string(), ToOwned::to_owned);
+    write_var_int(&mut handshake, host.len().try_into().unwrap_or(i32::MAX));
+    handshake.extend_from_slice(host.as_bytes());
+    handshake.extend_from_slice(&url.port().to_be_bytes());
+    // Next state: status
+    write_var_int(&mut handshake, 1);
+
+    let mut request = Vec::new();
+    write_packet(&mut request, &handshake);
+    write_packet(&mut request, &[0x00]);
+    if let Some(transcript) = transcript.as_deref_mut() {
+        transcript.sent.extend_from_slice(&request);
+    }
+    let sent_at = Instant::now();
+    let connecting = sent_at - started;
+    stream
+        .write_all(&request)
+        .await
+        .map_err(|e| format!("Failed sending status request: {e}"))?;
+
+    let mut stream = Tee {
+        inner: &mut stream,
+        copy: transcript.map(|transcript| &mut transcript.received),
+    };
+    let read = async {
+        // Timed apart from the rest, as it is only sent once the server has come up with a status
+        let first = stream
+            .read_u8()
+            .await
+            .map_err(|e| format!("Failed reading status response: {e}"))?;
+        let handshake = sent_at.elapsed();
+        let length = read_var_int(&mut [first].as_slice().chain(&mut stream)).await?;
+        let length = usize::try_from(length)
+            .ok()
+            .filter(|&l| l <= MAX_RESPONSE_LENGTH)
+            .ok_or_else(|| format!("Status response had an invalid length of {length}"))?;
+        let mut packet = vec![0; length];
+        stream
+            .read_exact(&mut packet)
+            .await
+            .map_err(|e| format!("Failed reading status response: {e}"))?;
+        Ok::<_, String>((packet, handshake))
+    };
+    let (packet, handshake) = match outbound.read_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, read)
+            .await
+            .map_err(|_| format!("No status response within {timeout:?}"))??,
+        None => read.await?,
+    };
+    let latency = sent_at.elapsed();
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let timings = Timings {
+        dns_ms: None,
+        connect_ms: ms(connecting),
+        handshake_ms: ms(handshake),
+        status_ms: ms(latency - handshake),
+        exchange_ms: ms(latency),
+    };
+
+    let mut packet = packet.as_slice();
+    let id = read_var_int(&mut packet).await?;
+    if id != 0x00 {
+        return Err(format!("Expected a status response, got packet {id:#04x}"));
+    }
+    let json_length = read_var_int(&mut packet).await?;
+    let json = usize::try_from(json_length)
+        .ok()
+        .and_then(|l| packet.get(..l))
+        .ok_or_else(|| format!("Status JSON had an invalid length of {json_length}"))?;
+
+    let json =
+        std::str::from_utf8(json).map_err(|e| format!("Status response was not utf-8: {e}"))?;
+    let response: Response =
+        serde_json::from_str(json).map_err(|e| format!("Failed parsing status response: {e}"))?;
+    let mut motd = String::new();
+    flatten_chat(&response.description, &mut motd);
+
+    let output = MonitorOutput {
+        version: response.version.name,
+        protocol: response.version.protocol,
+        online_player_count: Players::saturate(response.players.online),
+        max_player_count: Players::saturate(response.players.max),
+        motd,
+        description_json: Some(response.description),
+        enforces_secure_chat: response.enforces_secure_chat,
+        previews_chat: response.previews_chat,
+        chat_reporting: ChatReporting::detect(
+            response.enforces_secure_chat,
+            response.prevents_chat_reports,
+        ),
+    };
+    let raw = RawStatus {
+        json: json.into(),
+        latency,
+    };
+    Ok((output, raw, timings, url))
+}
+
+/// Appends the plain text of a chat component, which is either a string, an object with `text`
+/// and `extra`, or an array of components.
+fn flatten_chat(component: &Value, out: &mut String) {
+    match component {
+        Value::String(text) => out.push_str(text),
+        Value::Array(components) => {
+            for component in components {
+                flatten_chat(component, out);
+            }
+        }
+        Value::Object(object) => {
+            if let Some(Value::String(text)) = object.get("text") {
+                out.push_str(text);
+            }
+            if let Some(extra) = object.get("extra") {
+                flatten_chat(extra, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn write_packet(out: &mut Vec<u8>, packet: &[u8]) {
+    write_var_int(out, packet.len().try_into().unwrap_or(i32::MAX));
+    out.extend_from_slice(packet);
+}
+
+#[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+fn write_var_int(out: &mut Vec<u8>, value: i32) {
+    // Negative numbers are written as their two's complement, so they always take 5 bytes
+    let mut value = value as u32;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[allow(clippy::cast_possible_wrap)]
+async fn read_var_int(reader: &mut (impl AsyncReadExt + Unpin + Send)) -> Result<i32, String> {
+    let mut value = 0u32;
+    for i in 0..5 {
+        let byte = reader
+            .read_u8()
+            .await
+            .map_err(|e| format!("Failed reading status response: {e}"))?;
+        value |= u32::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err("VarInt in status response was longer than 5 bytes".to_owned())
+}
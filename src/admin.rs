@@ -0,0 +1,117 @@
+//! The bearer token in `MCSTATUS_ADMIN_TOKEN`, which gates what only operators should see, like
+//! packet dumps of pings. JWTs with the admin scope and operators logged in through OIDC
+//! are let through as well. Everything it gates is disabled while none of them are configured.
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Request, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::{
+    basic_auth::constant_time_eq,
+    client_ip::{self, ClientIp},
+    env_vars::Loader,
+    jwt::{self, Claims},
+    AppState,
+};
+
+pub struct Token(String);
+
+impl Token {
+    pub fn from_env(vars: &mut Loader) -> Option<Self> {
+        const ADMIN_TOKEN: &str = "ADMIN_TOKEN";
+
+        vars.secret(ADMIN_TOKEN).filter(|t| !t.is_empty()).map(Self)
+    }
+
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+
+    pub fn matches(&self, sent: &str) -> bool {
+        constant_time_eq(sent.trim().as_bytes(), self.0.as_bytes())
+    }
+}
+
+/// Lets the request through only if it sends `Authorization: Bearer <token>`, came with a JWT that
+/// has the admin scope, or from an operator logged in through OIDC.
+pub fn authorize(
+    state: &AppState,
+    claims: Option<&Claims>,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, String)> {
+    if claims.is_some_and(|c| c.has_scope(jwt::ADMIN_SCOPE))
+        || state.oidc.as_ref().is_some_and(|o| o.has_session(headers))
+    {
+        return Ok(());
+    }
+    if state.admin_token.is_none() && state.jwt.is_none() && state.oidc.is_none() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Admin access is disabled, as no admin token is configured".to_owned(),
+        ));
+    }
+    let sent = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|a| a.strip_prefix("Bearer "));
+    if sent.is_some_and(|sent| state.admin_token.as_ref().is_some_and(|t| t.matches(sent))) {
+        return Ok(());
+    }
+    if claims.is_some() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            format!("The token needs the {} scope", jwt::ADMIN_SCOPE),
+        ));
+    }
+
+    debug!("Rejected admin request without a valid token");
+    Err((
+        StatusCode::UNAUTHORIZED,
+        "A valid admin token is required".to_owned(),
+    ))
+}
+
+/// Reads `MCSTATUS_ADMIN_ALLOWED_IPS` as a comma separated list of CIDRs that the admin routes and
+/// packet dumps may be reached from, any address may when empty.
+pub fn allowed_ips_from_env(vars: &mut Loader) -> Vec<IpNet> {
+    const ADMIN_ALLOWED_IPS: &str = "ADMIN_ALLOWED_IPS";
+
+    vars.parse(ADMIN_ALLOWED_IPS, "", client_ip::parse_networks)
+}
+
+/// The admin routes, and the packet dumps of `/:url?debug=true`.
+fn restricted(uri: &Uri) -> bool {
+    uri.path().starts_with("/admin/")
+        || uri
+            .query()
+            .is_some_and(|query| query.split('&').any(|pair| pair == "debug=true"))
+}
+
+/// Turns away requests for the admin routes and packet dumps from outside the allowed networks,
+/// before they get to any authentication. Requests over a Unix socket are local, so always allowed.
+pub async fn restrict(
+    State(allowed): State<Arc<[IpNet]>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    if allowed.is_empty() || !restricted(request.uri()) {
+        return next.run(request).await;
+    }
+    let client = request.extensions().get::<ClientIp>().and_then(|c| c.0);
+    let Some(ip) = client else {
+        return next.run(request).await;
+    };
+    if allowed.iter().any(|net| net.contains(&ip)) {
+        return next.run(request).await;
+    }
+
+    debug!(%ip, path = request.uri().path(), "Rejected request from outside the allowed networks");
+    (StatusCode::FORBIDDEN, format!("Not allowed from {ip}")).into_response()
+}
@@ -0,0 +1,153 @@
+//! Alert rules from the config file, evaluated against every poll result. Alerts fire once when
+//! their condition starts holding and resolve once it stops, rather than on every poll. They don't
+//! fire during maintenance windows, and neither fire nor resolve while a server is flapping.
+
+use chrono::{DateTime, Utc};
+use std::{collections::HashMap, sync::Arc, time::SystemTime};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+use crate::{
+    config::{AlertCondition, AlertRule, Config},
+    maintenance,
+    notify::{EventKind, Notification, Notifier},
+    poller::PollResult,
+};
+
+#[derive(Default)]
+struct RuleState {
+    firing: bool,
+    /// When the server was first seen without players, for [`AlertCondition::NoPlayers`].
+    empty_since: Option<SystemTime>,
+}
+
+pub async fn run(
+    config: Arc<Config>,
+    notifier: Notifier,
+    mut poll_results: broadcast::Receiver<Arc<PollResult>>,
+) {
+    // Keyed by the index of the rule and the server
+    let mut states = HashMap::<(usize, String), RuleState>::new();
+
+    loop {
+        let result = match poll_results.recv().await {
+            Ok(result) => result,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(
+                    skipped,
+                    "Alert evaluation fell behind, skipped poll results"
+                );
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        for (i, rule) in config.alerts.iter().enumerate() {
+            if !applies_to(rule, &result.server) {
+                continue;
+            }
+            let state = states.entry((i, result.server.clone())).or_default();
+            let Some(met) = evaluate(&rule.condition, state, &result) else {
+                continue;
+            };
+            if met == state.firing {
+                continue;
+            }
+            // Not fired rather than forgotten, so it still fires if it holds past the window, or
+            // once the server settles. Flapping servers don't resolve either, the flapping
+            // notifications cover them
+            let polled_at = DateTime::<Utc>::from(result.polled_at);
+            if result.flapping || met && maintenance::active(&config, &result.server, polled_at) {
+                continue;
+            }
+            state.firing = met;
+
+            let (event, verb) = if met {
+                (EventKind::AlertFired, "fired")
+            } else {
+                (EventKind::AlertResolved, "resolved")
+            };
+            notifier.send(Notification::new(
+                event,
+                result.server.clone(),
+                format!("[{}] {}", rule.name, result.server),
+                format!(
+                    "Alert {} {verb} for {}: {}",
+                    rule.name,
+                    result.server,
+                    describe(&rule.condition, &result)
+                ),
+            ));
+        }
+    }
+}
+
+fn applies_to(rule: &AlertRule, server: &str) -> bool {
+    rule.servers
+        .as_ref()
+        .map_or(true, |servers| servers.iter().any(|s| s == server))
+}
+
+/// Whether the condition holds for `result`, or `None` if that can't be told, e.g. because the
+/// server was offline.
+fn evaluate(
+    condition: &AlertCondition,
+    state: &mut RuleState,
+    result: &PollResult,
+) -> Option<bool> {
+    let output = result.status.as_ref().ok().and_then(|s| s.output.as_ref());
+    let players = output.map(|o| (o.online_player_count, o.max_player_count));
+
+    let met = match condition {
+        AlertCondition::PlayersPercent { percent } => {
+            let (online, max) = players?;
+            max > 0 && f64::from(online) >= f64::from(max) * percent / 100.0
+        }
+        AlertCondition::PlayersAtLeast { count } => players?.0 >= *count,
+        AlertCondition::NoPlayers { duration } => {
+            if players?.0 > 0 {
+                state.empty_since = None;
+                false
+            } else {
+                let since = *state.empty_since.get_or_insert(result.polled_at);
+                result
+                    .polled_at
+                    .duration_since(since)
+                    .is_ok_and(|empty_for| empty_for >= *duration)
+            }
+        }
+        AlertCondition::Offline => !result.online,
+    };
+    Some(met)
+}
+
+fn describe(condition: &AlertCondition, result: &PollResult) -> String {
+    let players = result
+        .status
+        .as_ref()
+        .ok()
+        .and_then(|s| s.output.as_ref())
+        .map_or_else(
+            || "unknown".to_owned(),
+            |o| format!("{}/{}", o.online_player_count, o.max_player_count),
+        );
+    match condition {
+        AlertCondition::PlayersPercent { percent } => {
+            format!("{players} players online, threshold is {percent}% of the slots")
+        }
+        AlertCondition::PlayersAtLeast { count } => {
+            format!("{players} players online, threshold is {count}")
+        }
+        AlertCondition::NoPlayers { duration } => {
+            format!("{players} players online, threshold is nobody for {duration:?}")
+        }
+        AlertCondition::Offline => match &result.status {
+            Ok(status) if status.output.is_some() => format!("back up, {players} players online"),
+            Ok(status) => format!(
+                "offline, {}",
+                status.error.as_deref().unwrap_or("no answer")
+            ),
+            Err(e) => format!("offline, {e}"),
+        },
+    }
+}
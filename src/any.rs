@@ -0,0 +1,108 @@
+//! `/any/:url`, for servers whose edition the caller doesn't know. Java and Bedrock are pinged at
+//! the same time, and whichever answers first wins. Both are cached like any other status, and go
+//! through `mc-monitor` when that is the backend.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use mcstatus_core::{ServerAddr, ServerStatus};
+use serde::Serialize;
+use std::net::SocketAddr;
+use tracing::debug;
+
+use crate::{config::Backend, status_error, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Edition {
+    Java,
+    Bedrock,
+}
+
+#[derive(Serialize)]
+pub struct EditionStatus {
+    edition: Edition,
+    #[serde(flatten)]
+    status: ServerStatus,
+}
+
+type Outcome = (Edition, Result<ServerStatus, (StatusCode, String)>);
+
+pub async fn handler(
+    Path(addr): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<EditionStatus>, (StatusCode, String)> {
+    debug!(%addr, "Requested from api, edition unknown");
+
+    let has_port = addr.contains(':');
+
+    let addr = state.resolve(&addr).await.map_err(status_error)?;
+    let bedrock = if *state.use_mc_monitor {
+        Backend::McMonitorBedrock
+    } else {
+        Backend::Bedrock
+    };
+
+    // Statuses are cached by address, so one with a port is one server of one edition: the one
+    // it's configured as, or Bedrock on the Bedrock port and Java on any other
+    if has_port {
+        let (edition, backend) = match state.overrides.get(addr.address).and_then(|s| s.backend) {
+            Some(backend) if backend.is_bedrock() => (Edition::Bedrock, None),
+            None if addr.address.port() == state.overrides.ports().bedrock => {
+                (Edition::Bedrock, Some(bedrock))
+            }
+            _ => (Edition::Java, None),
+        };
+        return respond((edition, state.cached_status_as(addr, backend).await));
+    }
+
+    // Without a port each edition is pinged on its own default one, both through the cache
+    let bedrock_addr = ServerAddr {
+        address: SocketAddr::new(addr.address.ip(), state.overrides.ports().bedrock),
+        // Only the native Java backend falls back to other addresses, and only Java follows SRV
+        fallbacks: Vec::new(),
+        srv_target: None,
+        ..addr.clone()
+    };
+    let java = async { (Edition::Java, state.cached_status(addr).await) };
+    let bedrock = async {
+        let status = state.cached_status_as(bedrock_addr, Some(bedrock)).await;
+        (Edition::Bedrock, status)
+    };
+    tokio::pin!(java, bedrock);
+
+    let first: Outcome = tokio::select! {
+        outcome = &mut java => outcome,
+        outcome = &mut bedrock => outcome,
+    };
+    if answered(&first) {
+        return respond(first);
+    }
+    let second = match first.0 {
+        Edition::Java => bedrock.await,
+        Edition::Bedrock => java.await,
+    };
+    if answered(&second) {
+        return respond(second);
+    }
+
+    // Neither answered, the Java failure is reported as that is the more common edition
+    respond(if first.0 == Edition::Java {
+        first
+    } else {
+        second
+    })
+}
+
+fn answered((_, status): &Outcome) -> bool {
+    status.as_ref().is_ok_and(|s| s.output.is_some())
+}
+
+fn respond((edition, status): Outcome) -> Result<Json<EditionStatus>, (StatusCode, String)> {
+    Ok(Json(EditionStatus {
+        edition,
+        status: status?,
+    }))
+}
@@ -0,0 +1,43 @@
+//! `/static/*path`, the stylesheets, scripts and fonts of the HTML pages. They are embedded in the
+//! binary from the `static` directory, so it runs without any assets next to it.
+
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+use std::fmt::Write;
+
+use crate::not_modified;
+
+#[derive(RustEmbed)]
+#[folder = "static/"]
+struct Assets;
+
+pub async fn handler(
+    Path(path): Path<String>,
+    request_headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(asset) = Assets::get(&path) else {
+        return Err((StatusCode::NOT_FOUND, format!("No asset at /static/{path}")));
+    };
+
+    // A quarter of the hash is plenty to tell versions of a file apart
+    let etag = asset.metadata.sha256_hash()[..8]
+        .iter()
+        .fold("\"".to_owned(), |mut etag, byte| {
+            _ = write!(etag, "{byte:02x}");
+            etag
+        })
+        + "\"";
+    let headers = [
+        (header::CONTENT_TYPE, asset.metadata.mimetype().to_owned()),
+        (header::CACHE_CONTROL, "public, max-age=86400".to_owned()),
+        (header::ETAG, etag.clone()),
+    ];
+    if not_modified(&request_headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+    Ok((headers, asset.data).into_response())
+}
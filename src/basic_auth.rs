@@ -0,0 +1,115 @@
+//! HTTP Basic authentication for all routes, or only for those under the configured prefixes.
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::collections::HashMap;
+use tracing::debug;
+
+use crate::{env_vars::Loader, AppState};
+
+pub struct Credentials {
+    /// Passwords keyed by user name.
+    users: HashMap<String, String>,
+    /// Path prefixes that require authentication, every path does when empty.
+    paths: Vec<String>,
+}
+
+impl Credentials {
+    /// Reads `MCSTATUS_BASIC_AUTH_USERS` as a comma separated list of `user:password` pairs, and
+    /// `MCSTATUS_BASIC_AUTH_PATHS` as a comma separated list of path prefixes to protect.
+    /// Authentication is disabled when no users are configured.
+    pub fn from_env(vars: &mut Loader) -> Option<Self> {
+        const BASIC_AUTH_USERS: &str = "BASIC_AUTH_USERS";
+        const BASIC_AUTH_PATHS: &str = "BASIC_AUTH_PATHS";
+
+        let users = vars.secret(BASIC_AUTH_USERS).unwrap_or_default();
+        let users = users
+            .split(',')
+            .filter(|u| !u.is_empty())
+            .map(|u| {
+                let (user, password) = u.split_once(':')?;
+                Some((user.to_owned(), password.to_owned()))
+            })
+            .collect::<Option<HashMap<_, _>>>();
+        let Some(users) = users else {
+            // Not saying which one, as it would show the password
+            vars.invalid(BASIC_AUTH_USERS, "entries must be `user:password`");
+            return None;
+        };
+
+        let paths = vars.string(BASIC_AUTH_PATHS, "");
+        let paths = paths
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+
+        (!users.is_empty()).then_some(Self { users, paths })
+    }
+
+    /// `/healthz` is always open, so container healthchecks don't need credentials.
+    fn protects(&self, path: &str) -> bool {
+        path != "/healthz"
+            && (self.paths.is_empty() || self.paths.iter().any(|p| path.starts_with(p.as_str())))
+    }
+
+    fn authorized(&self, authorization: Option<&str>) -> bool {
+        let Some(encoded) = authorization.and_then(|a| a.strip_prefix("Basic ")) else {
+            return false;
+        };
+        let Some(decoded) = STANDARD
+            .decode(encoded.trim())
+            .ok()
+            .and_then(|d| String::from_utf8(d).ok())
+        else {
+            return false;
+        };
+        let Some((user, password)) = decoded.split_once(':') else {
+            return false;
+        };
+        self.users
+            .get(user)
+            .is_some_and(|expected| constant_time_eq(expected.as_bytes(), password.as_bytes()))
+    }
+}
+
+/// Compares without short-circuiting, so timing does not leak how much of a password matched.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+pub async fn require(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(auth) = &state.basic_auth else {
+        return next.run(request).await;
+    };
+    if !auth.protects(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    if auth.authorized(authorization) {
+        return next.run(request).await;
+    }
+
+    debug!("Rejected request without valid basic auth credentials");
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, r#"Basic realm="mcstatus-http""#)],
+        "Authentication required",
+    )
+        .into_response()
+}
@@ -0,0 +1,63 @@
+//! How the fields of served JSON are named, picked with `?case=` or the `case` of the config file,
+//! for frontends that expect camelCase. They are `snake_case` otherwise, as they are declared.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::config::Config;
+
+/// Fields whose contents aren't named by the service, like labels or the MOTD servers send.
+const KEPT: [&str; 2] = ["labels", "description_json"];
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Case {
+    #[default]
+    Snake,
+    Camel,
+}
+
+impl Case {
+    /// What the request asked for, or else what the config file says.
+    pub fn pick(requested: Option<Self>, config: &Config) -> Self {
+        requested.or(config.case).unwrap_or_default()
+    }
+
+    /// Renames the fields of `value` and of everything in it, but for those of [`KEPT`].
+    pub fn apply(self, value: &mut Value) {
+        if self == Self::Snake {
+            return;
+        }
+        match value {
+            Value::Object(fields) => {
+                *fields = std::mem::take(fields)
+                    .into_iter()
+                    .map(|(name, mut field)| {
+                        if !KEPT.contains(&name.as_str()) {
+                            self.apply(&mut field);
+                        }
+                        (camel_case(&name), field)
+                    })
+                    .collect();
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.apply(item)),
+            _ => {}
+        }
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let mut camel = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' && !camel.is_empty() {
+            upper = true;
+        } else if upper {
+            camel.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
+}
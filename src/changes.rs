@@ -0,0 +1,118 @@
+//! Detection of changes between consecutive polls of a server, like a new MOTD or an update to
+//! another version, or a server starting or stopping to flap, which are sent out as notifications.
+
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+use crate::{
+    notify::{EventKind, Notification, Notifier},
+    poller::PollResult,
+};
+
+/// What was last seen of a server while it was online.
+struct Snapshot {
+    motd: String,
+    version: String,
+    protocol: Option<i32>,
+}
+
+impl Snapshot {
+    /// The version along with its protocol number, when the server sent it.
+    fn version(&self) -> String {
+        self.protocol.map_or_else(
+            || self.version.clone(),
+            |protocol| format!("{} (protocol {protocol})", self.version),
+        )
+    }
+}
+
+pub async fn run(notifier: Notifier, mut poll_results: broadcast::Receiver<Arc<PollResult>>) {
+    let mut last_seen = HashMap::<String, Snapshot>::new();
+    let mut flapping = HashMap::<String, bool>::new();
+
+    loop {
+        let result = match poll_results.recv().await {
+            Ok(result) => result,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(
+                    skipped,
+                    "Change detection fell behind, skipped poll results"
+                );
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let was_flapping = flapping.insert(result.server.clone(), result.flapping);
+        if was_flapping.is_some_and(|was| was != result.flapping) {
+            let (event, title, message) = if result.flapping {
+                (
+                    EventKind::FlappingStarted,
+                    format!("{} is flapping", result.server),
+                    format!(
+                        "{} keeps going up and down, its alerts are held back until it settles",
+                        result.server
+                    ),
+                )
+            } else {
+                (
+                    EventKind::FlappingStopped,
+                    format!("{} stopped flapping", result.server),
+                    format!(
+                        "{} settled and is {}",
+                        result.server,
+                        if result.online { "online" } else { "offline" }
+                    ),
+                )
+            };
+            notifier.send(Notification::new(
+                event,
+                result.server.clone(),
+                title,
+                message,
+            ));
+        }
+
+        // Going offline is not a change of any of these, the values are compared with whatever the
+        // server reports once it is back
+        let Some(output) = result.status.as_ref().ok().and_then(|s| s.output.as_ref()) else {
+            continue;
+        };
+
+        let current = Snapshot {
+            motd: output.motd.clone(),
+            version: output.version.clone(),
+            protocol: output.protocol,
+        };
+        let version = current.version();
+        let Some(previous) = last_seen.insert(result.server.clone(), current) else {
+            continue;
+        };
+
+        if previous.motd != output.motd {
+            notifier.send(Notification::new(
+                EventKind::MotdChanged,
+                result.server.clone(),
+                format!("MOTD changed on {}", result.server),
+                format!(
+                    "MOTD of {} changed from {:?} to {:?}",
+                    result.server, previous.motd, output.motd
+                ),
+            ));
+        }
+        // The same name can be sent with another protocol, like by proxies that report their own
+        if previous.version != output.version || previous.protocol != output.protocol {
+            notifier.send(Notification::new(
+                EventKind::VersionChanged,
+                result.server.clone(),
+                format!("Version changed on {}", result.server),
+                format!(
+                    "{} now reports version {} instead of {}",
+                    result.server,
+                    version,
+                    previous.version()
+                ),
+            ));
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! A circuit breaker per Minecraft server. After enough fetches of a server fail in a row, it stops
+//! being contacted for a cooldown, and is answered for with how its last fetch went instead. Once
+//! the cooldown is over, a single fetch is let through to probe it, which closes the circuit if it
+//! succeeds and opens it for another cooldown if it doesn't.
+
+use axum::http::StatusCode;
+use mcstatus_core::ServerStatus;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info};
+
+use crate::env_vars::Loader;
+
+type Fetched = Result<ServerStatus, (StatusCode, String)>;
+
+pub struct Breaker {
+    /// Failures in a row that open a circuit, never opened when zero.
+    threshold: u32,
+    cooldown: Duration,
+    circuits: Mutex<HashMap<SocketAddr, Circuit>>,
+}
+
+/// A server whose last fetch failed.
+struct Circuit {
+    failures: u32,
+    last_failure: Instant,
+    last: Fetched,
+    opened_at: Option<Instant>,
+    /// Whether a fetch is being let through to see if the server is back.
+    probing: bool,
+}
+
+/// How a [`Circuit`] looks from outside.
+#[derive(Serialize)]
+pub struct Snapshot {
+    addr: SocketAddr,
+    failures: u32,
+    last_failure_secs_ago: f64,
+    last_error: Option<String>,
+    /// How long the circuit has been open, unset while it is closed.
+    open_secs: Option<f64>,
+    probing: bool,
+}
+
+pub enum Permit<'a> {
+    Fetch(Fetch<'a>),
+    /// The circuit is open, so this is how the last fetch went.
+    Open(Fetched),
+}
+
+/// A fetch the breaker let through, whose outcome is counted with [`Fetch::record`]. A probe that
+/// is dropped without one, like when the rate cap turns it away, lets the next fetch probe instead.
+pub struct Fetch<'a> {
+    breaker: &'a Breaker,
+    addr: SocketAddr,
+    probing: bool,
+}
+
+impl Fetch<'_> {
+    /// Counts how the fetch went, where both errors and statuses of servers that didn't answer are
+    /// failures.
+    pub fn record(mut self, fetched: &Fetched) {
+        self.probing = false;
+        self.breaker.record(self.addr, fetched);
+    }
+}
+
+impl Drop for Fetch<'_> {
+    fn drop(&mut self) {
+        if !self.probing {
+            return;
+        }
+        if let Some(circuit) = self.breaker.lock().get_mut(&self.addr) {
+            debug!(addr = %self.addr, "Probe was given up, letting the next fetch probe instead");
+            circuit.probing = false;
+        }
+    }
+}
+
+impl Breaker {
+    pub fn from_env(vars: &mut Loader) -> Self {
+        const CIRCUIT_BREAKER_THRESHOLD: &str = "CIRCUIT_BREAKER_THRESHOLD";
+        const CIRCUIT_BREAKER_COOLDOWN: &str = "CIRCUIT_BREAKER_COOLDOWN";
+
+        let threshold = vars.value(CIRCUIT_BREAKER_THRESHOLD, "5");
+        let cooldown = vars.duration(CIRCUIT_BREAKER_COOLDOWN, "1 minute");
+        Self::new(threshold, cooldown)
+    }
+
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            circuits: Mutex::default(),
+        }
+    }
+
+    /// Decides whether `addr` may be fetched now, which it may unless its circuit is open, or half
+    /// open with another fetch already probing it.
+    pub fn acquire(&self, addr: SocketAddr) -> Permit {
+        let acquired = if self.threshold == 0 {
+            Ok(false)
+        } else {
+            acquire(&mut self.lock(), self.cooldown, addr)
+        };
+        let probing = match acquired {
+            Ok(probing) => probing,
+            Err(last) => return Permit::Open(last),
+        };
+        Permit::Fetch(Fetch {
+            breaker: self,
+            addr,
+            probing,
+        })
+    }
+
+    fn record(&self, addr: SocketAddr, fetched: &Fetched) {
+        if self.threshold == 0 {
+            return;
+        }
+        if fetched.as_ref().is_ok_and(|s| s.output.is_some()) {
+            let closed = self.lock().remove(&addr);
+            if closed.is_some_and(|c| c.opened_at.is_some()) {
+                info!(%addr, "Server is back, closing its circuit");
+            }
+            return;
+        }
+        record_failure(
+            &mut self.lock(),
+            self.threshold,
+            self.cooldown,
+            addr,
+            fetched,
+        );
+    }
+
+    /// Every server that failed recently, for diagnostics.
+    pub fn snapshot(&self) -> Vec<Snapshot> {
+        self.lock()
+            .iter()
+            .map(|(addr, c)| Snapshot {
+                addr: *addr,
+                failures: c.failures,
+                last_failure_secs_ago: c.last_failure.elapsed().as_secs_f64(),
+                last_error: match &c.last {
+                    Ok(status) => status.error.clone(),
+                    Err((_, e)) => Some(e.clone()),
+                },
+                open_secs: c.opened_at.map(|at| at.elapsed().as_secs_f64()),
+                probing: c.probing,
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<SocketAddr, Circuit>> {
+        self.circuits
+            .lock()
+            .expect("Circuits lock should not be poisoned")
+    }
+}
+
+/// Whether `addr` may be fetched, and if so whether that probes its circuit, or how its last fetch
+/// went if not.
+fn acquire(
+    circuits: &mut HashMap<SocketAddr, Circuit>,
+    cooldown: Duration,
+    addr: SocketAddr,
+) -> Result<bool, Fetched> {
+    // Failures stop counting once two cooldowns have gone by without another
+    circuits.retain(|_, c| c.last_failure.elapsed() < cooldown * 2);
+
+    let Some(circuit) = circuits.get_mut(&addr) else {
+        return Ok(false);
+    };
+    let Some(opened_at) = circuit.opened_at else {
+        return Ok(false);
+    };
+    if opened_at.elapsed() >= cooldown && !circuit.probing {
+        debug!(%addr, "Circuit cooled down, probing whether the server is back");
+        circuit.probing = true;
+        return Ok(true);
+    }
+    debug!(%addr, "Circuit is open, not fetching");
+    Err(circuit.last.clone())
+}
+
+fn record_failure(
+    circuits: &mut HashMap<SocketAddr, Circuit>,
+    threshold: u32,
+    cooldown: Duration,
+    addr: SocketAddr,
+    fetched: &Fetched,
+) {
+    let circuit = circuits.entry(addr).or_insert_with(|| Circuit {
+        failures: 0,
+        last_failure: Instant::now(),
+        last: fetched.clone(),
+        opened_at: None,
+        probing: false,
+    });
+    circuit.failures = circuit.failures.saturating_add(1);
+    circuit.last_failure = Instant::now();
+    circuit.last = fetched.clone();
+    // A failed probe opens it again straight away
+    if circuit.probing || circuit.failures == threshold {
+        info!(%addr, failures = circuit.failures, ?cooldown, "Opening circuit");
+        circuit.opened_at = Some(Instant::now());
+        circuit.probing = false;
+    }
+}
@@ -0,0 +1,122 @@
+//! Working out the real client address when running behind reverse proxies.
+//!
+//! `X-Forwarded-For` and `Forwarded` are only believed when the hop that set them is one of the
+//! configured trusted proxies; the chain is walked from the closest hop outwards and the first
+//! untrusted address is taken to be the client.
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use crate::env_vars::Loader;
+
+/// The resolved client address, stored in the request extensions. This is `None` for requests
+/// over a Unix socket that did not carry any forwarding headers.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+pub fn trusted_proxies_from_env(vars: &mut Loader) -> Vec<IpNet> {
+    const TRUSTED_PROXIES: &str = "TRUSTED_PROXIES";
+
+    vars.parse(TRUSTED_PROXIES, "", parse_networks)
+}
+
+/// A comma separated list of CIDRs, where bare addresses are accepted as single host networks.
+pub fn parse_networks(networks: &str) -> Result<Vec<IpNet>, String> {
+    networks
+        .split(',')
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map(|n| {
+            n.parse::<IpNet>()
+                .or_else(|_| n.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| format!("{n} is not a CIDR"))
+        })
+        .collect()
+}
+
+pub async fn resolve(
+    State(trusted): State<Arc<[IpNet]>>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client = ClientIp(client_ip(peer, request.headers(), &trusted));
+    request.extensions_mut().insert(client);
+    next.run(request).await
+}
+
+fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &[IpNet]) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+
+    // Whatever connects over a Unix socket is local, and so is treated like a trusted proxy
+    if peer.as_ref().is_some_and(|peer| !is_trusted(peer)) {
+        return peer;
+    }
+
+    let hops = forwarded_hops(headers);
+    let mut client = peer;
+    for hop in hops.iter().rev() {
+        let Some(ip) = parse_hop(hop) else {
+            // An obfuscated or `unknown` hop ends the part of the chain that can be followed
+            break;
+        };
+        client = Some(ip);
+        if !is_trusted(&ip) {
+            break;
+        }
+    }
+    client
+}
+
+/// The `for` addresses from `Forwarded`, or the entries of `X-Forwarded-For` if it is absent,
+/// ordered from the original client to the closest proxy.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<&str> {
+    let forwarded = headers
+        .get_all("forwarded")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.eq_ignore_ascii_case("for").then_some(value)
+            })
+        })
+        .collect::<Vec<_>>();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .collect()
+}
+
+/// Parses a single hop, which may be quoted and carry a port, as in `"[2001:db8::1]:4711"`.
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim().trim_matches('"');
+    if let Some(rest) = hop.strip_prefix('[') {
+        let (ip, _port) = rest.split_once(']')?;
+        return ip.parse().ok();
+    }
+    hop.parse()
+        .ok()
+        .or_else(|| hop.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
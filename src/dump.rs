@@ -0,0 +1,125 @@
+//! Snapshots of what the cache, pollers and circuit breakers hold, taken on `SIGUSR1` so something
+//! that looks wrong can be looked into without restarting. They go to the log, or overwrite
+//! `MCSTATUS_DUMP_FILE` when it is set.
+
+use chrono::{DateTime, Utc};
+use mcstatus_core::ServerStatus;
+use serde::Serialize;
+use std::{collections::BTreeMap, net::SocketAddr, path::PathBuf, sync::Arc};
+use tracing::{info, warn};
+
+use crate::{circuit, env_vars::Loader, poller::PollResult, AppState};
+
+pub fn path_from_env(vars: &mut Loader) -> Option<PathBuf> {
+    const DUMP_FILE: &str = "DUMP_FILE";
+
+    vars.optional(DUMP_FILE)
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+}
+
+#[derive(Serialize)]
+struct Snapshot {
+    taken_at: String,
+    cache: Vec<CacheEntry>,
+    /// The latest poll of every server from the config file, since the snapshots started.
+    pollers: BTreeMap<String, Poll>,
+    /// Servers that failed recently.
+    circuits: Vec<circuit::Snapshot>,
+}
+
+#[derive(Serialize)]
+struct CacheEntry {
+    addr: SocketAddr,
+    status: ServerStatus,
+}
+
+#[derive(Serialize)]
+struct Poll {
+    polled_at: String,
+    latency_ms: f64,
+    online: bool,
+    flapping: bool,
+    error: Option<String>,
+}
+
+/// Dumps a snapshot every time the process gets `SIGUSR1`, which only exists on Unix.
+pub async fn run(state: AppState, path: Option<PathBuf>) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut polls = state.poll_results.subscribe();
+        let mut signals = match signal(SignalKind::user_defined1()) {
+            Ok(signals) => signals,
+            Err(e) => {
+                warn!(%e, "Failed installing SIGUSR1 handler, state can't be dumped");
+                return;
+            }
+        };
+        let mut latest = BTreeMap::new();
+        loop {
+            tokio::select! {
+                // Lagging behind only misses polls that later ones replace, and the sender is
+                // never dropped while there is state to dump
+                result = polls.recv() => if let Ok(result) = result {
+                    latest.insert(result.server.clone(), result);
+                },
+                Some(()) = signals.recv() => dump(&state, &latest, path.as_ref()).await,
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        _ = (state, path);
+    }
+}
+
+async fn dump(
+    state: &AppState,
+    latest: &BTreeMap<String, Arc<PollResult>>,
+    path: Option<&PathBuf>,
+) {
+    let snapshot = Snapshot {
+        taken_at: Utc::now().to_rfc3339(),
+        cache: state
+            .cache
+            .entries()
+            .await
+            .into_iter()
+            .map(|(addr, status)| CacheEntry { addr, status })
+            .collect(),
+        pollers: latest
+            .iter()
+            .map(|(server, result)| {
+                let poll = Poll {
+                    polled_at: DateTime::<Utc>::from(result.polled_at).to_rfc3339(),
+                    latency_ms: result.latency.as_secs_f64() * 1000.0,
+                    online: result.online,
+                    flapping: result.flapping,
+                    error: match &result.status {
+                        Ok(status) => status.error.clone(),
+                        Err(e) => Some(e.clone()),
+                    },
+                };
+                (server.clone(), poll)
+            })
+            .collect(),
+        circuits: state.circuit_breaker.snapshot(),
+    };
+    let json = match serde_json::to_string_pretty(&snapshot) {
+        Ok(json) => json,
+        Err(e) => {
+            warn!(%e, "Failed serializing state dump");
+            return;
+        }
+    };
+    let Some(path) = path else {
+        info!("Dumped internal state:\n{json}");
+        return;
+    };
+    match tokio::fs::write(path, json).await {
+        Ok(()) => info!(path = %path.display(), "Dumped internal state"),
+        Err(e) => warn!(%e, path = %path.display(), "Failed writing state dump"),
+    }
+}
@@ -0,0 +1,223 @@
+//! Reading the `MCSTATUS_` prefixed environment variables everything is configured with.
+//!
+//! Nothing panics on a bad value. Every problem is collected, and [`Loader::finish`] logs one
+//! report of every variable that was read with the problems next to them, so a broken deployment
+//! can be fixed in one go instead of one restart per typo.
+//!
+//! The binary also reads a `.env` file first, or the one in `MCSTATUS_ENV_FILE`. What is already
+//! in the environment wins over the file.
+
+use std::{env, fmt, io, str::FromStr, time::Duration};
+use tracing::{info, warn};
+
+/// What every variable name starts with.
+pub const PREFIX: &str = "MCSTATUS_";
+
+/// Collects the variables read by the `from_env` constructors, and whatever was wrong with them.
+#[derive(Default)]
+pub struct Loader {
+    read: Vec<Var>,
+}
+
+struct Var {
+    /// Without the prefix.
+    name: &'static str,
+    source: Source,
+    /// Mustn't be shown, like passwords.
+    secret: bool,
+    error: Option<String>,
+}
+
+enum Source {
+    Set(String),
+    /// Set under the name it had before the prefix.
+    Legacy(String),
+    Default(String),
+    Unset,
+}
+
+/// Every problem found by a [`Loader`].
+#[derive(Debug)]
+pub struct Errors(Vec<String>);
+
+impl fmt::Display for Errors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid environment variable(s)", self.0.len())?;
+        for error in &self.0 {
+            write!(f, "\n  {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Errors {}
+
+impl Loader {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Like [`Loader::new`], after loading the variables from the `.env` file in
+    /// `MCSTATUS_ENV_FILE`. Only a file that was asked for by name has to exist.
+    #[must_use]
+    pub fn with_env_file() -> Self {
+        const ENV_FILE: &str = "ENV_FILE";
+
+        let mut vars = Self::new();
+        let (path, required) = vars
+            .optional(ENV_FILE)
+            .map_or_else(|| (".env".to_owned(), false), |path| (path, true));
+        match dotenvy::from_path(&path) {
+            Ok(()) => {}
+            Err(dotenvy::Error::Io(e)) if !required && e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => vars.invalid(ENV_FILE, format!("failed loading {path}: {e}")),
+        }
+        vars
+    }
+
+    /// `PREFIX` + `name` as it is, or `None` when it is unset.
+    pub fn optional(&mut self, name: &'static str) -> Option<String> {
+        self.read(name, None, false)
+    }
+
+    /// Like [`Loader::optional`], but never shown in the report.
+    pub fn secret(&mut self, name: &'static str) -> Option<String> {
+        self.read(name, None, true)
+    }
+
+    /// `PREFIX` + `name` as it is, or `default` when it is unset.
+    pub fn string(&mut self, name: &'static str, default: &str) -> String {
+        self.read(name, Some(default), false)
+            .unwrap_or_else(|| default.to_owned())
+    }
+
+    /// `PREFIX` + `name` parsed with `parse`, or `default` parsed the same way when it is unset or
+    /// invalid.
+    ///
+    /// # Panics
+    ///
+    /// If `default` itself can't be parsed.
+    pub fn parse<T>(
+        &mut self,
+        name: &'static str,
+        default: &str,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> T {
+        if let Some(value) = self.read(name, Some(default), false) {
+            match parse(&value) {
+                Ok(value) => return value,
+                Err(e) => self.invalid(name, e),
+            }
+        }
+        parse(default).unwrap_or_else(|e| panic!("Default {default} for {name} is invalid: {e}"))
+    }
+
+    /// `PREFIX` + `name` parsed with `parse`, or `None` when it is unset or invalid.
+    pub fn parse_optional<T>(
+        &mut self,
+        name: &'static str,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> Option<T> {
+        let value = self.optional(name)?;
+        parse(&value).map_err(|e| self.invalid(name, e)).ok()
+    }
+
+    /// A number or `true`/`false`, parsed with [`FromStr`].
+    pub fn value<T: FromStr>(&mut self, name: &'static str, default: &str) -> T {
+        self.parse(name, default, |v| {
+            v.parse()
+                .map_err(|_| format!("expected {}", std::any::type_name::<T>()))
+        })
+    }
+
+    /// A duration like `10 seconds`.
+    pub fn duration(&mut self, name: &'static str, default: &str) -> Duration {
+        self.parse(name, default, duration)
+    }
+
+    /// Records a problem with `name` that only shows once it is looked at together with something
+    /// else, like a file it points to.
+    pub fn invalid(&mut self, name: &'static str, error: impl Into<String>) {
+        let error = error.into();
+        match self.read.iter_mut().rev().find(|v| v.name == name) {
+            Some(var) => var.error = Some(error),
+            None => self.read.push(Var {
+                name,
+                source: Source::Unset,
+                secret: false,
+                error: Some(error),
+            }),
+        }
+    }
+
+    /// Logs the report of every variable read, and fails if any of them were invalid.
+    ///
+    /// # Errors
+    ///
+    /// With every problem found, if there were any.
+    pub fn finish(self) -> Result<(), Errors> {
+        let mut report = String::from("Configuration:");
+        let mut errors = Vec::new();
+        for var in &self.read {
+            let shown = |value: &str| {
+                if var.secret {
+                    "<hidden>".to_owned()
+                } else {
+                    format!("{value:?}")
+                }
+            };
+            let line = match &var.source {
+                Source::Set(value) => format!("{PREFIX}{}={}", var.name, shown(value)),
+                Source::Legacy(value) => format!(
+                    "{PREFIX}{}={} (read from {}, which is deprecated)",
+                    var.name,
+                    shown(value),
+                    var.name
+                ),
+                Source::Default(value) => {
+                    format!("{PREFIX}{}={} (default)", var.name, shown(value))
+                }
+                Source::Unset => format!("{PREFIX}{} is unset", var.name),
+            };
+            report.push_str("\n  ");
+            report.push_str(&line);
+            if let Some(error) = &var.error {
+                report.push_str(&format!("\n    ^ invalid: {error}"));
+                errors.push(format!("{PREFIX}{}: {error}", var.name));
+            }
+        }
+
+        if errors.is_empty() {
+            info!("{report}");
+            Ok(())
+        } else {
+            warn!("{report}");
+            Err(Errors(errors))
+        }
+    }
+
+    fn read(&mut self, name: &'static str, default: Option<&str>, secret: bool) -> Option<String> {
+        let prefixed = env::var(format!("{PREFIX}{name}"));
+        let (source, value) = match (prefixed, env::var(name)) {
+            (Ok(value), _) => (Source::Set(value.clone()), Some(value)),
+            (Err(_), Ok(value)) => (Source::Legacy(value.clone()), Some(value)),
+            (Err(_), Err(_)) => (
+                default.map_or(Source::Unset, |d| Source::Default(d.to_owned())),
+                None,
+            ),
+        };
+        self.read.push(Var {
+            name,
+            source,
+            secret,
+            error: None,
+        });
+        value
+    }
+}
+
+/// Durations are written like `10 seconds` or `1h 30m`.
+pub(crate) fn duration(value: &str) -> Result<Duration, String> {
+    parse_duration::parse(value).map_err(|e| format!("expected a duration like `10 seconds`: {e}"))
+}
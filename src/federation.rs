@@ -0,0 +1,257 @@
+//! Federation of instances that poll from different regions. Agents report every poll result to an
+//! aggregator, which serves how each server fares from each region under `/federation`, telling a
+//! server that is down apart from one that some regions can't reach.
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::{
+    basic_auth::constant_time_eq, config::Config, history::unix_seconds, poller::PollResult,
+    AppState,
+};
+
+/// How long sending a single report may take, the aggregator just misses it otherwise.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One poll result, as an agent sends it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub region: String,
+    /// Address of the server, as written in the agent's config file.
+    pub server: String,
+    /// Whether the server answered.
+    pub reachable: bool,
+    pub latency_ms: f64,
+    pub error: Option<String>,
+    /// Unix timestamp of the poll.
+    pub polled_at: u64,
+}
+
+impl Report {
+    fn new(region: &str, result: &PollResult) -> Self {
+        let error = match &result.status {
+            Ok(status) => status.error.clone(),
+            Err(e) => Some(e.clone()),
+        };
+        Self {
+            region: region.to_owned(),
+            server: result.server.clone(),
+            reachable: result.status.as_ref().is_ok_and(|s| s.output.is_some()),
+            latency_ms: result.latency.as_secs_f64() * 1000.0,
+            error,
+            polled_at: unix_seconds(result.polled_at),
+        }
+    }
+}
+
+/// Sends every poll result to the aggregator in [`Config::agent`].
+pub async fn run_agent(
+    config: Arc<Config>,
+    mut poll_results: broadcast::Receiver<Arc<PollResult>>,
+) {
+    let Some(agent) = &config.agent else {
+        return;
+    };
+    let url = format!(
+        "{}/federation/report",
+        agent.aggregator_url.trim_end_matches('/')
+    );
+    info!(
+        url,
+        region = agent.region,
+        "Reporting poll results to the aggregator"
+    );
+    let client = reqwest::Client::new();
+
+    loop {
+        let result = match poll_results.recv().await {
+            Ok(result) => result,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(
+                    skipped,
+                    "Federation reports fell behind, skipped poll results"
+                );
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        // In the background, so a slow aggregator doesn't hold up the reports of other servers
+        let request = client
+            .post(&url)
+            .bearer_auth(&agent.token)
+            .json(&Report::new(&agent.region, &result))
+            .timeout(TIMEOUT)
+            .send();
+        let server = result.server.clone();
+        tokio::spawn(async move {
+            match request.await.and_then(reqwest::Response::error_for_status) {
+                Ok(_) => debug!(server, "Reported poll result"),
+                Err(e) => warn!(%e, server, "Failed reporting poll result"),
+            }
+        });
+    }
+}
+
+/// The latest report of every server from every region, kept by the aggregator.
+#[derive(Default)]
+pub struct Aggregator {
+    /// Keyed by the server, then by the region.
+    reports: Mutex<BTreeMap<String, BTreeMap<String, Report>>>,
+}
+
+impl Aggregator {
+    fn record(&self, report: Report) {
+        self.reports
+            .lock()
+            .expect("Reports lock should not be poisoned")
+            .entry(report.server.clone())
+            .or_default()
+            .insert(report.region.clone(), report);
+    }
+}
+
+/// Counts the aggregator's own poll results under the region in its config, if it has one.
+pub async fn run_local(
+    config: Arc<Config>,
+    aggregator: Arc<Aggregator>,
+    mut poll_results: broadcast::Receiver<Arc<PollResult>>,
+) {
+    let Some(region) = config.aggregator.as_ref().and_then(|a| a.region.as_deref()) else {
+        return;
+    };
+    loop {
+        match poll_results.recv().await {
+            Ok(result) => aggregator.record(Report::new(region, &result)),
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Federation fell behind, skipped poll results");
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// `POST /federation/report`, where agents send their poll results with their region's token.
+pub async fn report(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(report): Json<Report>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let (Some(config), Some(aggregator)) = (&state.config.aggregator, &state.federation) else {
+        return Err(not_enabled());
+    };
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|a| a.strip_prefix("Bearer "));
+    let authorized = config
+        .agents
+        .get(&report.region)
+        .zip(token)
+        .is_some_and(|(expected, token)| constant_time_eq(expected.as_bytes(), token.as_bytes()));
+    if !authorized {
+        debug!(
+            region = report.region,
+            "Rejected report without a valid token"
+        );
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            format!("A valid token for the region {} is required", report.region),
+        ));
+    }
+
+    aggregator.record(report);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// How a server fares across the regions that reported it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Verdict {
+    /// Every region reached it.
+    Up,
+    /// Some regions reached it, so it is up but the others can't get to it.
+    Partial,
+    /// No region reached it.
+    Down,
+}
+
+#[derive(Serialize)]
+pub struct ServerRegions {
+    server: String,
+    verdict: Verdict,
+    regions: BTreeMap<String, RegionStatus>,
+}
+
+#[derive(Serialize)]
+struct RegionStatus {
+    reachable: bool,
+    latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// How long ago the region polled the server.
+    age_seconds: u64,
+}
+
+/// `GET /federation`, the latest report from every region for every server.
+pub async fn handler(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ServerRegions>>, (StatusCode, String)> {
+    let Some(aggregator) = &state.federation else {
+        return Err(not_enabled());
+    };
+    let now = unix_seconds(SystemTime::now());
+    let reports = aggregator
+        .reports
+        .lock()
+        .expect("Reports lock should not be poisoned")
+        .clone();
+
+    let servers = reports
+        .into_iter()
+        .map(|(server, regions)| {
+            let reachable = regions.values().filter(|r| r.reachable).count();
+            let verdict = match reachable {
+                0 => Verdict::Down,
+                n if n == regions.len() => Verdict::Up,
+                _ => Verdict::Partial,
+            };
+            let regions = regions
+                .into_iter()
+                .map(|(region, report)| {
+                    let status = RegionStatus {
+                        reachable: report.reachable,
+                        latency_ms: report.latency_ms,
+                        error: report.error,
+                        age_seconds: now.saturating_sub(report.polled_at),
+                    };
+                    (region, status)
+                })
+                .collect();
+            ServerRegions {
+                server,
+                verdict,
+                regions,
+            }
+        })
+        .collect();
+    Ok(Json(servers))
+}
+
+fn not_enabled() -> (StatusCode, String) {
+    (
+        StatusCode::NOT_FOUND,
+        "This instance is not a federation aggregator".to_owned(),
+    )
+}
@@ -0,0 +1,102 @@
+//! Hysteresis for servers going up and down, so a lossy network path doesn't turn into a stream of
+//! alerts. A server only counts as having changed once `confirm_polls` polls in a row agree, and
+//! one that keeps changing anyway is marked as flapping, which holds back its alerts.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use crate::config::FlapDetection;
+
+pub struct Tracker {
+    settings: FlapDetection,
+    servers: Mutex<HashMap<String, ServerState>>,
+}
+
+#[derive(Default)]
+struct ServerState {
+    /// What the server counts as, unset until its first poll.
+    online: Option<bool>,
+    /// Polls in a row that disagreed with [`ServerState::online`].
+    disagreeing: u32,
+    /// What the latest polls found, oldest first.
+    recent: VecDeque<bool>,
+    flapping: bool,
+}
+
+impl ServerState {
+    fn observe(&mut self, settings: &FlapDetection, online: bool) -> Judgement {
+        match self.online {
+            Some(counted) if counted != online => {
+                self.disagreeing += 1;
+                if self.disagreeing >= settings.confirm_polls {
+                    self.online = Some(online);
+                    self.disagreeing = 0;
+                }
+            }
+            _ => {
+                self.online = Some(online);
+                self.disagreeing = 0;
+            }
+        }
+
+        self.recent.push_back(online);
+        while self.recent.len() > settings.window {
+            self.recent.pop_front();
+        }
+        if let Some(threshold) = settings.transitions {
+            let transitions = self
+                .recent
+                .iter()
+                .zip(self.recent.iter().skip(1))
+                .filter(|(a, b)| a != b)
+                .count();
+            // Stopping takes fewer changes than starting, so it doesn't flap itself
+            if transitions >= threshold {
+                self.flapping = true;
+            } else if transitions <= threshold / 2 {
+                self.flapping = false;
+            }
+        }
+
+        Judgement {
+            online: self.online.unwrap_or(online),
+            flapping: self.flapping,
+        }
+    }
+}
+
+/// What a server counts as after a poll.
+#[derive(Debug, Clone, Copy)]
+pub struct Judgement {
+    pub online: bool,
+    pub flapping: bool,
+}
+
+impl Tracker {
+    pub fn new(settings: FlapDetection) -> Self {
+        Self {
+            settings,
+            servers: Mutex::default(),
+        }
+    }
+
+    /// Adds a poll of `server` that found it `online` or not.
+    pub fn observe(&self, server: &str, online: bool) -> Judgement {
+        self.lock()
+            .entry(server.to_owned())
+            .or_default()
+            .observe(&self.settings, online)
+    }
+
+    pub fn is_flapping(&self, server: &str) -> bool {
+        self.lock().get(server).is_some_and(|state| state.flapping)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, ServerState>> {
+        self.servers
+            .lock()
+            .expect("Flapping state lock should not be poisoned")
+    }
+}
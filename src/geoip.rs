@@ -0,0 +1,77 @@
+//! Country and ASN of the servers' addresses, from `MaxMind` databases, for server lists that group
+//! servers by region. Either kind of database works on its own, `GeoLite2` Country or City for the
+//! country and `GeoLite2` ASN for the rest.
+
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use mcstatus_core::Geo;
+use std::{collections::BTreeMap, net::IpAddr, path::Path};
+
+use crate::env_vars::Loader;
+
+pub struct GeoIp {
+    country: Option<Reader<Vec<u8>>>,
+    asn: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    /// Opens the databases in `MCSTATUS_GEOIP_DATABASE` and `MCSTATUS_GEOIP_ASN_DATABASE`, `None`
+    /// when neither is set.
+    pub fn from_env(vars: &mut Loader) -> Option<Self> {
+        const GEOIP_DATABASE: &str = "GEOIP_DATABASE";
+        const GEOIP_ASN_DATABASE: &str = "GEOIP_ASN_DATABASE";
+
+        let mut open = |name| {
+            let path = vars.optional(name)?;
+            Reader::open_readfile(&path)
+                .map_err(|e| vars.invalid(name, format!("failed opening {path}: {e}")))
+                .ok()
+        };
+        let country = open(GEOIP_DATABASE);
+        let asn = open(GEOIP_ASN_DATABASE);
+        (country.is_some() || asn.is_some()).then_some(Self { country, asn })
+    }
+
+    pub fn open(country: Option<&Path>, asn: Option<&Path>) -> Result<Self, MaxMindDBError> {
+        Ok(Self {
+            country: country.map(Reader::open_readfile).transpose()?,
+            asn: asn.map(Reader::open_readfile).transpose()?,
+        })
+    }
+
+    /// What the databases know about `ip`, `None` when it is in neither, like private addresses.
+    pub fn lookup(&self, ip: IpAddr) -> Option<Geo> {
+        let country = self
+            .country
+            .as_ref()
+            .and_then(|db| db.lookup::<geoip2::Country>(ip).ok())
+            .and_then(|c| c.country?.iso_code)
+            .map(str::to_owned);
+        let asn = self
+            .asn
+            .as_ref()
+            .and_then(|db| db.lookup::<geoip2::Asn>(ip).ok());
+        let geo = Geo {
+            country,
+            asn: asn.as_ref().and_then(|a| a.autonomous_system_number),
+            as_organization: asn
+                .and_then(|a| a.autonomous_system_organization)
+                .map(str::to_owned),
+        };
+        (geo != Geo::default()).then_some(geo)
+    }
+}
+
+/// Adds the country and ASN to the labels metrics are tagged with. The AS organization is left
+/// out, as names with spaces and punctuation make poor tags. Labels from the config file win.
+pub fn add_labels(geo: &Geo, labels: &mut BTreeMap<String, String>) {
+    if let Some(country) = &geo.country {
+        labels
+            .entry("country".to_owned())
+            .or_insert_with(|| country.clone());
+    }
+    if let Some(asn) = geo.asn {
+        labels
+            .entry("asn".to_owned())
+            .or_insert_with(|| asn.to_string());
+    }
+}
@@ -0,0 +1,68 @@
+//! Detection of Java servers that Bedrock players can join through
+//! [Geyser](https://geysermc.org), either because they say so in their MOTD or version, or because
+//! something answers Bedrock pings on the Geyser port of the same host.
+
+use mcstatus_core::{bedrock, Error, Outbound, ServerStatus};
+use std::net::SocketAddr;
+use tracing::debug;
+
+use crate::env_vars::Loader;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Detection {
+    /// Port Geyser is expected to listen on, which is also what gets reported for servers that
+    /// only advertise it.
+    port: u16,
+    /// Whether to ping `port` alongside the Java status, which can take as long as the Bedrock
+    /// timeout for servers without it.
+    ping: bool,
+}
+
+impl Detection {
+    pub fn from_env(vars: &mut Loader) -> Self {
+        const GEYSER_PORT: &str = "GEYSER_PORT";
+        const GEYSER_PING: &str = "GEYSER_PING";
+
+        let port = vars.value(GEYSER_PORT, &bedrock::DEFAULT_PORT.to_string());
+        let ping = vars.value(GEYSER_PING, "false");
+        Self::new(port, ping)
+    }
+
+    pub const fn new(port: u16, ping: bool) -> Self {
+        Self { port, ping }
+    }
+
+    /// Pings the Geyser port next to `java` if enabled, and then marks `status` as crossplay if
+    /// either the ping was answered or the server advertises Geyser.
+    pub async fn detect(
+        self,
+        java: impl std::future::Future<Output = Result<ServerStatus, Error>> + Send,
+        addr: &SocketAddr,
+        outbound: &Outbound,
+    ) -> Result<ServerStatus, Error> {
+        let geyser_addr = SocketAddr::new(addr.ip(), self.port);
+        let ping = async {
+            if !self.ping {
+                return false;
+            }
+            bedrock::fetch_status(&geyser_addr, bedrock::DEFAULT_TIMEOUT, outbound)
+                .await
+                .is_ok_and(|status| status.output.is_some())
+        };
+        let (status, answered) = tokio::join!(java, ping);
+
+        let mut status = status?;
+        let advertised = status.output.as_ref().is_some_and(|output| {
+            [&output.motd, &output.version].into_iter().any(|text| {
+                let text = text.to_lowercase();
+                text.contains("geyser") || text.contains("floodgate")
+            })
+        });
+        if status.output.is_some() && (answered || advertised) {
+            debug!(answered, advertised, "Server supports Bedrock players");
+            status.crossplay = true;
+            status.bedrock_port = Some(self.port);
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,88 @@
+//! Sending poll results to Graphite over the plaintext protocol, as `<prefix>.<server>.<metric>`
+//! for `up`, `players_online`, `max_players`, `latency_ms`, `icmp_rtt_ms`, and `connect_ms` and
+//! `exchange_ms` from the native backend's timings. Server labels become Graphite tags.
+
+use std::{fmt::Write, sync::Arc};
+use tokio::{
+    io::AsyncWriteExt,
+    net::TcpStream,
+    sync::broadcast::{self, error::RecvError},
+};
+use tracing::{info, warn};
+
+use crate::{
+    config::Config,
+    history::unix_seconds,
+    metrics::{name_component, Cardinality},
+    poller::PollResult,
+};
+
+pub async fn run(config: Arc<Config>, mut poll_results: broadcast::Receiver<Arc<PollResult>>) {
+    let Some(graphite) = &config.graphite else {
+        return;
+    };
+    info!(address = graphite.address, "Sending metrics to Graphite");
+    let cardinality = Cardinality::new(&config);
+
+    // Connected lazily, and again after every failed write
+    let mut connection = None::<TcpStream>;
+
+    loop {
+        let result = match poll_results.recv().await {
+            Ok(result) => result,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Graphite export fell behind, skipped poll results");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let lines = lines(&graphite.prefix, &cardinality, &result);
+
+        let stream = match &mut connection {
+            Some(stream) => stream,
+            None => match TcpStream::connect(&graphite.address).await {
+                Ok(stream) => connection.insert(stream),
+                Err(e) => {
+                    warn!(%e, "Failed connecting to Graphite, dropping metrics");
+                    continue;
+                }
+            },
+        };
+        if let Err(e) = stream.write_all(lines.as_bytes()).await {
+            warn!(%e, "Failed sending metrics to Graphite, dropping them");
+            connection = None;
+        }
+    }
+}
+
+fn lines(prefix: &str, cardinality: &Cardinality, result: &PollResult) -> String {
+    let path = format!(
+        "{prefix}.{}",
+        name_component(&cardinality.server(&result.server))
+    );
+    let mut tags = String::new();
+    for (key, value) in &cardinality.labels(&result.labels) {
+        _ = write!(tags, ";{}={}", name_component(key), name_component(value));
+    }
+    let timestamp = unix_seconds(result.polled_at);
+    let output = result.status.as_ref().ok().and_then(|s| s.output.as_ref());
+
+    let mut lines = String::new();
+    let mut metric = |name: &str, value: f64| {
+        _ = writeln!(lines, "{path}.{name}{tags} {value} {timestamp}");
+    };
+    metric("up", if output.is_some() { 1.0 } else { 0.0 });
+    metric("latency_ms", result.latency.as_secs_f64() * 1000.0);
+    if let Some(rtt) = result.status.as_ref().ok().and_then(|s| s.icmp_rtt_ms) {
+        metric("icmp_rtt_ms", rtt);
+    }
+    if let Some(timings) = result.status.as_ref().ok().and_then(|s| s.timings.as_ref()) {
+        metric("connect_ms", timings.connect_ms);
+        metric("exchange_ms", timings.exchange_ms);
+    }
+    if let Some(output) = output {
+        metric("players_online", output.online_player_count.into());
+        metric("max_players", output.max_player_count.into());
+    }
+    lines
+}
@@ -0,0 +1,253 @@
+//! GraphQL API over the same data as the JSON routes, so a dashboard can ask for exactly the
+//! fields it shows, for any number of servers, in a single request. Statuses are only fetched for
+//! the servers whose status is actually selected.
+
+use async_graphql::{
+    http::GraphiQLSource, Context, EmptyMutation, EmptySubscription, Error, Object, SimpleObject,
+};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::{extract::State, response::Html};
+use std::time::SystemTime;
+
+use crate::{
+    history::{self, Availability, Incident},
+    AppState, MAX_BATCH_ADDRESSES,
+};
+
+pub type Schema = async_graphql::Schema<Query, EmptyMutation, EmptySubscription>;
+
+pub fn schema() -> Schema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(8)
+        .finish()
+}
+
+pub async fn handler(State(state): State<AppState>, request: GraphQLRequest) -> GraphQLResponse {
+    let request = request.into_inner().data(state.clone());
+    state.graphql.execute(request).await.into()
+}
+
+/// The `GraphiQL` explorer, for trying out queries from a browser.
+pub async fn explorer() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// A single server, by `host[:port]`.
+    async fn server(&self, address: String) -> Server {
+        Server { address }
+    }
+
+    /// The given servers, or every server in the config file when none are given.
+    #[allow(clippy::unused_async)] // Resolvers have to be async
+    async fn servers(
+        &self,
+        ctx: &Context<'_>,
+        addresses: Option<Vec<String>>,
+    ) -> async_graphql::Result<Vec<Server>> {
+        let addresses = match addresses {
+            Some(addresses) if addresses.len() > MAX_BATCH_ADDRESSES => {
+                return Err(Error::new(format!(
+                    "At most {MAX_BATCH_ADDRESSES} addresses can be queried at once"
+                )));
+            }
+            Some(addresses) => addresses,
+            None => state(ctx)
+                .config
+                .servers
+                .iter()
+                .map(|s| s.address.clone())
+                .collect(),
+        };
+        Ok(addresses
+            .into_iter()
+            .map(|address| Server { address })
+            .collect())
+    }
+
+    /// A group from the config file.
+    async fn group(&self, ctx: &Context<'_>, name: String) -> Option<Group> {
+        state(ctx)
+            .config
+            .groups
+            .contains_key(&name)
+            .then_some(Group { name })
+    }
+
+    /// Every group from the config file, sorted by name.
+    async fn groups(&self, ctx: &Context<'_>) -> Vec<Group> {
+        let mut names = state(ctx).config.groups.keys().cloned().collect::<Vec<_>>();
+        names.sort_unstable();
+        names.into_iter().map(|name| Group { name }).collect()
+    }
+}
+
+fn state<'a>(ctx: &Context<'a>) -> &'a AppState {
+    ctx.data_unchecked::<AppState>()
+}
+
+pub struct Group {
+    name: String,
+}
+
+#[Object]
+impl Group {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn servers(&self, ctx: &Context<'_>) -> Vec<Server> {
+        state(ctx).config.groups[&self.name]
+            .servers
+            .iter()
+            .map(|address| Server {
+                address: address.clone(),
+            })
+            .collect()
+    }
+}
+
+pub struct Server {
+    address: String,
+}
+
+#[Object]
+impl Server {
+    async fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// The current status, from the cache when it is fresh enough.
+    async fn status(&self, ctx: &Context<'_>) -> async_graphql::Result<Status> {
+        let state = state(ctx);
+        let addr = state
+            .resolve(&self.address)
+            .await
+            .map_err(|e| Error::new(e.to_string()))?;
+        let status = state
+            .cached_status(addr)
+            .await
+            .map_err(|(_, e)| Error::new(e))?;
+        let output = status.output.as_ref();
+
+        Ok(Status {
+            online: output.is_some(),
+            version: output.map(|o| o.version.clone()),
+            players_online: output.map(|o| o.online_player_count),
+            max_players: output.map(|o| o.max_player_count),
+            motd: output.map(|o| o.motd.clone()),
+            error: status.error,
+            cache_hit: status.cache.as_ref().map_or(false, |c| c.hit),
+            age_seconds: status.cache.as_ref().map_or(0.0, |c| c.age_seconds),
+            labels: status
+                .labels
+                .into_iter()
+                .map(|(key, value)| Label { key, value })
+                .collect(),
+        })
+    }
+
+    /// Times the server was offline, newest first. Only servers from the config file have any.
+    async fn incidents(&self, ctx: &Context<'_>) -> Vec<IncidentInfo> {
+        let now = history::unix_seconds(SystemTime::now());
+        state(ctx)
+            .history
+            .server(&self.address)
+            .map(|timeline| {
+                timeline
+                    .incidents
+                    .iter()
+                    .rev()
+                    .map(|incident| IncidentInfo::new(incident, now))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Uptime over `window`, 30 days by default. Unset for servers that aren't polled.
+    #[allow(clippy::unused_async)]
+    async fn uptime(
+        &self,
+        ctx: &Context<'_>,
+        window: Option<String>,
+    ) -> async_graphql::Result<Option<Uptime>> {
+        let window = match window {
+            Some(window) => parse_duration::parse(&window)
+                .map_err(|_| Error::new(format!("Expected window {window} to be a duration")))?,
+            None => history::DEFAULT_UPTIME_WINDOW,
+        };
+        let now = history::unix_seconds(SystemTime::now());
+        Ok(state(ctx)
+            .history
+            .server(&self.address)
+            .map(|timeline| timeline.availability(window, now).into()))
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Status {
+    online: bool,
+    version: Option<String>,
+    players_online: Option<u16>,
+    max_players: Option<u16>,
+    motd: Option<String>,
+    error: Option<String>,
+    cache_hit: bool,
+    age_seconds: f64,
+    /// From the config file, empty for servers that aren't in it.
+    labels: Vec<Label>,
+}
+
+#[derive(SimpleObject)]
+pub struct Label {
+    key: String,
+    value: String,
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Incident")]
+pub struct IncidentInfo {
+    /// Unix timestamp.
+    started_at: u64,
+    /// Unix timestamp, unset while the server is still offline.
+    ended_at: Option<u64>,
+    duration_seconds: u64,
+    last_error: Option<String>,
+}
+
+impl IncidentInfo {
+    fn new(incident: &Incident, now: u64) -> Self {
+        Self {
+            started_at: incident.started_at,
+            ended_at: incident.ended_at,
+            duration_seconds: incident.duration_seconds(now),
+            last_error: incident.last_error.clone(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Uptime {
+    window_seconds: u64,
+    monitored_seconds: u64,
+    downtime_seconds: u64,
+    percent: f64,
+    failures: usize,
+    mean_time_between_failures_seconds: Option<u64>,
+}
+
+impl From<Availability> for Uptime {
+    fn from(availability: Availability) -> Self {
+        Self {
+            window_seconds: availability.window_seconds,
+            monitored_seconds: availability.monitored_seconds,
+            downtime_seconds: availability.downtime_seconds,
+            percent: availability.uptime_percent,
+            failures: availability.failures,
+            mean_time_between_failures_seconds: availability.mean_time_between_failures_seconds,
+        }
+    }
+}
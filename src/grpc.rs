@@ -0,0 +1,126 @@
+//! gRPC service from `proto/mcstatus.proto`, served on its own address next to the HTTP server and
+//! sharing its cache.
+
+use std::{future::Future, net::SocketAddr};
+use tokio::task::JoinSet;
+use tonic::{Request, Response, Status};
+
+use crate::{env_vars::Loader, AppState, MAX_BATCH_ADDRESSES};
+
+pub mod proto {
+    #![allow(clippy::pedantic, clippy::nursery)]
+    tonic::include_proto!("mcstatus.v1");
+}
+
+use proto::{
+    status_service_server::{StatusService, StatusServiceServer},
+    BatchGetStatusRequest, BatchGetStatusResponse, GetStatusRequest,
+};
+
+/// Address to serve gRPC on, from `MCSTATUS_GRPC_LISTEN_ADDR`. gRPC is disabled when it is unset.
+pub fn listen_addr_from_env(vars: &mut Loader) -> Option<SocketAddr> {
+    const GRPC_LISTEN_ADDR: &str = "GRPC_LISTEN_ADDR";
+
+    vars.parse_optional(GRPC_LISTEN_ADDR, |addr| {
+        addr.parse().map_err(|_| "expected ip:port".to_owned())
+    })
+}
+
+/// Serves the status service on `addr` until `signal` completes.
+///
+/// # Errors
+///
+/// If `addr` can't be bound, or the server fails.
+pub async fn serve(
+    state: AppState,
+    addr: SocketAddr,
+    signal: impl Future<Output = ()> + Send,
+) -> color_eyre::Result<()> {
+    tonic::transport::Server::builder()
+        .add_service(StatusServiceServer::new(Service { state }))
+        .serve_with_shutdown(addr, signal)
+        .await?;
+    Ok(())
+}
+
+struct Service {
+    state: AppState,
+}
+
+impl Service {
+    async fn status(&self, address: String) -> Result<proto::ServerStatus, String> {
+        let addr = self
+            .state
+            .resolve(&address)
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = self.state.cached_status(addr).await.map_err(|(_, e)| e)?;
+        let output = status.output.as_ref();
+
+        Ok(proto::ServerStatus {
+            address,
+            online: output.is_some(),
+            version: output.map(|o| o.version.clone()),
+            players_online: output.map(|o| o.online_player_count.into()),
+            max_players: output.map(|o| o.max_player_count.into()),
+            motd: output.map(|o| o.motd.clone()),
+            error: status.error,
+            cache_hit: status.cache.as_ref().map_or(false, |c| c.hit),
+            age_seconds: status.cache.as_ref().map_or(0.0, |c| c.age_seconds),
+            labels: status.labels.into_iter().collect(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl StatusService for Service {
+    async fn get_status(
+        &self,
+        request: Request<GetStatusRequest>,
+    ) -> Result<Response<proto::ServerStatus>, Status> {
+        self.status(request.into_inner().address)
+            .await
+            .map(Response::new)
+            .map_err(Status::invalid_argument)
+    }
+
+    async fn batch_get_status(
+        &self,
+        request: Request<BatchGetStatusRequest>,
+    ) -> Result<Response<BatchGetStatusResponse>, Status> {
+        let addresses = request.into_inner().addresses;
+        if addresses.len() > MAX_BATCH_ADDRESSES {
+            return Err(Status::invalid_argument(format!(
+                "At most {MAX_BATCH_ADDRESSES} addresses can be queried at once"
+            )));
+        }
+
+        let mut fetches = JoinSet::new();
+        for (i, address) in addresses.into_iter().enumerate() {
+            let service = Self {
+                state: self.state.clone(),
+            };
+            fetches.spawn(async move {
+                let status = service
+                    .status(address.clone())
+                    .await
+                    .unwrap_or_else(|error| proto::ServerStatus {
+                        address,
+                        error: Some(error),
+                        ..Default::default()
+                    });
+                (i, status)
+            });
+        }
+
+        let mut statuses = Vec::with_capacity(fetches.len());
+        while let Some(result) = fetches.join_next().await {
+            statuses.push(result.map_err(|e| Status::internal(e.to_string()))?);
+        }
+        statuses.sort_unstable_by_key(|(i, _)| *i);
+
+        Ok(Response::new(BatchGetStatusResponse {
+            statuses: statuses.into_iter().map(|(_, s)| s).collect(),
+        }))
+    }
+}
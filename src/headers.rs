@@ -0,0 +1,71 @@
+//! The static `headers` and `route_headers` of the config file, set on responses for integrations
+//! that need them, like CORS or `X-Robots-Tag`, where there is no reverse proxy to add them.
+
+use axum::{
+    body::Body,
+    extract::{MatchedPath, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use crate::config::Config;
+
+pub struct Headers {
+    all: HeaderMap,
+    /// Keyed by route pattern, as in `/:url`.
+    routes: HashMap<String, HeaderMap>,
+}
+
+impl Headers {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            all: header_map(&config.headers),
+            routes: config
+                .route_headers
+                .iter()
+                .map(|(route, headers)| (route.clone(), header_map(headers)))
+                .collect(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.all.is_empty() && self.routes.is_empty()
+    }
+}
+
+/// Skips headers that don't parse, which config files are checked for when loading.
+fn header_map(headers: &BTreeMap<String, String>) -> HeaderMap {
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = HeaderName::from_bytes(name.as_bytes()).ok()?;
+            Some((name, HeaderValue::from_str(value).ok()?))
+        })
+        .collect()
+}
+
+/// Sets the configured headers on the response, those of its route last. Responses that matched no
+/// route, like 404s, only get the ones for all of them.
+pub async fn add(
+    State(headers): State<Arc<Headers>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    if headers.is_empty() {
+        return next.run(request).await;
+    }
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|path| headers.routes.get(path.as_str()));
+    let mut response = next.run(request).await;
+    for (name, value) in headers.all.iter().chain(route.into_iter().flatten()) {
+        response.headers_mut().insert(name, value.clone());
+    }
+    response
+}
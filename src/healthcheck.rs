@@ -0,0 +1,84 @@
+//! `mcstatus-http healthcheck [addr]`, for the Dockerfile's `HEALTHCHECK` in an image without curl.
+//!
+//! With an address this checks that the Minecraft server there is online, without one it checks
+//! that the HTTP server on the first TCP address in `MCSTATUS_LISTEN_ADDR` answers on `/healthz`.
+//! Failing checks exit with 1, through the error returned from `main`.
+
+use color_eyre::{
+    eyre::{bail, eyre},
+    Result,
+};
+use mcstatus_http::{
+    env_vars::{self, Loader},
+    AppState,
+};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use crate::listener::ListenAddr;
+
+/// How long a check may take, Docker's own timeout defaults to 30 seconds.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn run(server: Option<String>) -> Result<()> {
+    match server {
+        Some(server) => check_server(server).await,
+        None => check_local().await,
+    }
+}
+
+async fn check_server(server: String) -> Result<()> {
+    let mut vars = Loader::with_env_file();
+    let state = AppState::from_env(&mut vars);
+    vars.finish()?;
+    let status = tokio::time::timeout(TIMEOUT, state.fetch_uncached(server.clone()))
+        .await
+        .map_err(|_| eyre!("Checking {server} timed out"))?
+        .map_err(|e| eyre!("Checking {server} failed: {e}"))?;
+
+    if status.output.is_none() {
+        bail!(
+            "{server} is offline: {}",
+            status.error.as_deref().unwrap_or("no error given")
+        );
+    }
+    println!("{server} is online");
+    Ok(())
+}
+
+async fn check_local() -> Result<()> {
+    let mut vars = Loader::with_env_file();
+    let addrs = crate::listen_addrs_from_env(&mut vars);
+    vars.finish()?;
+    let Some(mut addr) = addrs.into_iter().find_map(|addr| match addr {
+        ListenAddr::Tcp(addr) => Some(addr),
+        ListenAddr::Unix(_) => None,
+    }) else {
+        bail!(
+            "The healthcheck needs a TCP address in {}{}",
+            env_vars::PREFIX,
+            crate::LISTEN_ADDR
+        );
+    };
+    // Listening on every interface includes loopback, which is the one that is always there
+    if addr.ip().is_unspecified() {
+        let ip = if addr.is_ipv4() {
+            Ipv4Addr::LOCALHOST.into()
+        } else {
+            Ipv6Addr::LOCALHOST.into()
+        };
+        addr = SocketAddr::new(ip, addr.port());
+    }
+
+    reqwest::Client::builder()
+        .timeout(TIMEOUT)
+        .build()?
+        .get(format!("http://{addr}/healthz"))
+        .send()
+        .await?
+        .error_for_status()?;
+    println!("http://{addr} is healthy");
+    Ok(())
+}
@@ -0,0 +1,49 @@
+//! Heartbeats for the servers in the config file with a `heartbeat_url`, pinged after every poll
+//! that found the server online. The check at the other end, like one on healthchecks.io, notices
+//! when the pings stop, whether the server went down or this service did.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+
+use crate::{config::Config, poller::PollResult};
+
+/// How long a single ping may take, a check that is unreachable for longer just misses one.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub async fn run(config: Arc<Config>, mut poll_results: broadcast::Receiver<Arc<PollResult>>) {
+    let urls = config
+        .servers
+        .iter()
+        .filter_map(|server| Some((server.address.clone(), server.heartbeat_url.clone()?)))
+        .collect::<HashMap<_, _>>();
+    if urls.is_empty() {
+        return;
+    }
+    let client = reqwest::Client::new();
+
+    loop {
+        let result = match poll_results.recv().await {
+            Ok(result) => result,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Heartbeats fell behind, skipped poll results");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let online = result.status.as_ref().is_ok_and(|s| s.output.is_some());
+        let Some(url) = urls.get(&result.server).filter(|_| online) else {
+            continue;
+        };
+
+        // In the background, so a slow check doesn't hold up the pings of the other servers
+        let request = client.get(url).timeout(TIMEOUT).send();
+        let server = result.server.clone();
+        tokio::spawn(async move {
+            match request.await.and_then(reqwest::Response::error_for_status) {
+                Ok(_) => debug!(server, "Sent heartbeat"),
+                Err(e) => warn!(%e, server, "Failed sending heartbeat"),
+            }
+        });
+    }
+}
@@ -0,0 +1,505 @@
+//! History of the polled servers, built from the poll results and kept across restarts in the
+//! `history_file` from the config file. Without one the history only lasts as long as the process.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
+
+use crate::{poller::PollResult, AppState};
+
+#[derive(Clone)]
+pub struct History {
+    path: Option<Arc<std::path::Path>>,
+    servers: Arc<Mutex<HashMap<String, Timeline>>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timeline {
+    /// When the server was first polled, as a unix timestamp.
+    pub tracked_since: u64,
+    /// Every time the server went offline, oldest first. Only the last one can be ongoing.
+    pub incidents: Vec<Incident>,
+    /// The poll before [`Timeline::latest`], which changes are reported against.
+    #[serde(default)]
+    pub previous: Option<Snapshot>,
+    #[serde(default)]
+    pub latest: Option<Snapshot>,
+}
+
+/// What a single poll found, just enough to tell what changed between two of them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub polled_at: u64,
+    /// Unset while the server was offline.
+    pub output: Option<SnapshotOutput>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotOutput {
+    pub players_online: u16,
+    pub max_players: u16,
+    pub motd: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Incident {
+    /// Unix timestamp of the first poll that found the server offline.
+    pub started_at: u64,
+    /// Unix timestamp of the first poll that found it back online, unset while it is still down.
+    pub ended_at: Option<u64>,
+    /// Error of the latest failed poll during the incident.
+    pub last_error: Option<String>,
+}
+
+impl Timeline {
+    /// Adds the outcome of a poll, returning whether anything worth saving changed.
+    fn record(&mut self, snapshot: Snapshot, error: Option<String>) -> bool {
+        let polled_at = snapshot.polled_at;
+        // Like the last error, only saved along with the next change
+        self.previous = self.latest.replace(snapshot);
+
+        let ongoing = self
+            .incidents
+            .last_mut()
+            .filter(|incident| incident.ended_at.is_none());
+
+        match (ongoing, error) {
+            (None, Some(error)) => {
+                self.incidents.push(Incident::start(polled_at, error));
+                true
+            }
+            (Some(incident), Some(error)) => {
+                // Not worth rewriting the file for on every poll, the error is saved along with
+                // the next change
+                incident.last_error = Some(error);
+                false
+            }
+            (Some(incident), None) => {
+                incident.ended_at = Some(polled_at);
+                true
+            }
+            (None, None) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Availability {
+    pub window_seconds: u64,
+    /// The part of the window the server was actually polled in, shorter than the window when it
+    /// was only added recently.
+    pub monitored_seconds: u64,
+    pub downtime_seconds: u64,
+    pub uptime_percent: f64,
+    /// Incidents that started inside the window.
+    pub failures: usize,
+    /// Time online divided by the number of failures, unset when there were none.
+    pub mean_time_between_failures_seconds: Option<u64>,
+}
+
+impl Timeline {
+    pub fn availability(&self, window: Duration, now: u64) -> Availability {
+        let window_seconds = window.as_secs();
+        let since = now.saturating_sub(window_seconds).max(self.tracked_since);
+        let monitored_seconds = now.saturating_sub(since);
+
+        let mut downtime_seconds = 0;
+        let mut failures = 0;
+        for incident in &self.incidents {
+            let end = incident.ended_at.unwrap_or(now);
+            if end <= since {
+                continue;
+            }
+            downtime_seconds += end - incident.started_at.max(since);
+            if incident.started_at >= since {
+                failures += 1;
+            }
+        }
+
+        // A server that was only just added has nothing to go by, and is not counted as down
+        #[allow(clippy::cast_precision_loss)]
+        let uptime_percent = if monitored_seconds == 0 {
+            100.0
+        } else {
+            (monitored_seconds - downtime_seconds) as f64 / monitored_seconds as f64 * 100.0
+        };
+        let mean_time_between_failures_seconds =
+            (failures > 0).then(|| (monitored_seconds - downtime_seconds) / failures as u64);
+
+        Availability {
+            window_seconds,
+            monitored_seconds,
+            downtime_seconds,
+            uptime_percent,
+            failures,
+            mean_time_between_failures_seconds,
+        }
+    }
+}
+
+impl Incident {
+    const fn start(started_at: u64, error: String) -> Self {
+        Self {
+            started_at,
+            ended_at: None,
+            last_error: Some(error),
+        }
+    }
+
+    /// How long the server was offline for, or has been so far if it still is.
+    pub fn duration_seconds(&self, now: u64) -> u64 {
+        self.ended_at.unwrap_or(now).saturating_sub(self.started_at)
+    }
+}
+
+impl History {
+    /// Loads the history stored at `path`, starting out empty if the file doesn't exist yet.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read, or doesn't hold a history.
+    pub fn load(path: Option<PathBuf>) -> Result<Self, String> {
+        let servers = match &path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(contents) => serde_json::from_str(&contents)
+                    .map_err(|e| format!("failed parsing history file {}: {e}", path.display()))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+                Err(e) => {
+                    return Err(format!(
+                        "failed reading history file {}: {e}",
+                        path.display()
+                    ))
+                }
+            },
+            None => HashMap::new(),
+        };
+        if let Some(path) = &path {
+            info!(path = %path.display(), servers = servers.len(), "Loaded history");
+        }
+
+        Ok(Self {
+            path: path.map(Into::into),
+            servers: Arc::new(Mutex::new(servers)),
+        })
+    }
+
+    /// A history that is only kept in memory.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            servers: Arc::default(),
+        }
+    }
+
+    pub fn server(&self, server: &str) -> Option<Timeline> {
+        self.lock().get(server).cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Timeline>> {
+        // The map is never left half updated, so a panic while holding the lock doesn't matter
+        self.servers
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Adds a poll result to the history, returning whether anything worth saving changed.
+    fn record(&self, result: &PollResult) -> bool {
+        let error = match &result.status {
+            Ok(status) if status.output.is_some() => None,
+            Ok(status) => Some(status.error.clone().unwrap_or_default()),
+            Err(e) => Some(e.clone()),
+        };
+        let output = result.status.as_ref().ok().and_then(|s| s.output.as_ref());
+        let snapshot = Snapshot {
+            polled_at: unix_seconds(result.polled_at),
+            output: output.map(|output| SnapshotOutput {
+                players_online: output.online_player_count,
+                max_players: output.max_player_count,
+                motd: output.motd.clone(),
+                version: output.version.clone(),
+            }),
+        };
+
+        record_into(&mut self.lock(), result.server.clone(), snapshot, error)
+    }
+
+    async fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let contents = match serde_json::to_vec(&*self.lock()) {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!(%e, "Failed serializing history");
+                return;
+            }
+        };
+
+        // Written next to the real file and then moved over it, so a crash halfway through never
+        // leaves a truncated history behind
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let result = async {
+            tokio::fs::write(&temporary, contents).await?;
+            tokio::fs::rename(&temporary, path).await
+        };
+        if let Err(e) = result.await {
+            warn!(%e, path = %path.display(), "Failed saving history");
+        }
+    }
+}
+
+pub async fn run(history: History, mut poll_results: broadcast::Receiver<Arc<PollResult>>) {
+    loop {
+        let result = match poll_results.recv().await {
+            Ok(result) => result,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "History fell behind, skipped poll results");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if history.record(&result) {
+            history.save().await;
+        }
+    }
+}
+
+fn record_into(
+    servers: &mut HashMap<String, Timeline>,
+    server: String,
+    snapshot: Snapshot,
+    error: Option<String>,
+) -> bool {
+    match servers.entry(server) {
+        Entry::Occupied(mut timeline) => timeline.get_mut().record(snapshot, error),
+        Entry::Vacant(entry) => {
+            let polled_at = snapshot.polled_at;
+            entry.insert(Timeline {
+                tracked_since: polled_at,
+                incidents: error
+                    .map(|e| Incident::start(polled_at, e))
+                    .into_iter()
+                    .collect(),
+                previous: None,
+                latest: Some(snapshot),
+            });
+            true
+        }
+    }
+}
+
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+#[derive(Serialize)]
+pub struct IncidentReport {
+    server: String,
+    tracked_since: u64,
+    incidents: Vec<IncidentSummary>,
+}
+
+#[derive(Serialize)]
+struct IncidentSummary {
+    started_at: u64,
+    ended_at: Option<u64>,
+    duration_seconds: u64,
+    ongoing: bool,
+    last_error: Option<String>,
+}
+
+/// The history of `server`, however its address is written, so `host` finds the one of a server
+/// configured as `host:25565`, and the other way around.
+fn timeline(state: &AppState, server: &str) -> Result<Timeline, (StatusCode, String)> {
+    let configured = state
+        .overrides
+        .configured(server)
+        .map_or(server, |configured| configured.address.as_str());
+    state
+        .history
+        .server(configured)
+        .ok_or_else(|| not_tracked(server))
+}
+
+fn not_tracked(server: &str) -> (StatusCode, String) {
+    (
+        StatusCode::NOT_FOUND,
+        format!("{server} is not polled, so it has no history"),
+    )
+}
+
+pub async fn incidents_handler(
+    State(state): State<AppState>,
+    Path(server): Path<String>,
+) -> Result<Json<IncidentReport>, (StatusCode, String)> {
+    let history = timeline(&state, &server)?;
+    let now = unix_seconds(SystemTime::now());
+
+    // Newest first, since that is usually what's being looked for
+    let incidents = history
+        .incidents
+        .iter()
+        .rev()
+        .map(|incident| IncidentSummary {
+            started_at: incident.started_at,
+            ended_at: incident.ended_at,
+            duration_seconds: incident.duration_seconds(now),
+            ongoing: incident.ended_at.is_none(),
+            last_error: incident.last_error.clone(),
+        })
+        .collect();
+
+    Ok(Json(IncidentReport {
+        server,
+        tracked_since: history.tracked_since,
+        incidents,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct UptimeQuery {
+    /// How far back to look, like `30d` or `12 hours`.
+    window: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct UptimeReport {
+    server: String,
+    #[serde(flatten)]
+    availability: Availability,
+}
+
+/// The window uptime is computed over when none is asked for.
+pub const DEFAULT_UPTIME_WINDOW: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+pub async fn uptime_handler(
+    State(state): State<AppState>,
+    Path(server): Path<String>,
+    Query(query): Query<UptimeQuery>,
+) -> Result<Json<UptimeReport>, (StatusCode, String)> {
+    let window = query
+        .window
+        .as_deref()
+        .map_or(Ok(DEFAULT_UPTIME_WINDOW), |window| {
+            parse_duration::parse(window).map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("Expected window {window} to be a duration"),
+                )
+            })
+        })?;
+    let history = timeline(&state, &server)?;
+
+    Ok(Json(UptimeReport {
+        server,
+        availability: history.availability(window, unix_seconds(SystemTime::now())),
+    }))
+}
+
+#[derive(Serialize)]
+pub struct DiffReport {
+    server: String,
+    previous_polled_at: u64,
+    latest_polled_at: u64,
+    changed: bool,
+    went: Option<Transition>,
+    /// Net change of the player count, as only the counts are known and not who joined or left.
+    players_joined: u16,
+    players_left: u16,
+    max_players: Option<Change<u16>>,
+    motd: Option<Change<String>>,
+    version: Option<Change<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Transition {
+    Up,
+    Down,
+}
+
+#[derive(Serialize)]
+struct Change<T> {
+    from: T,
+    to: T,
+}
+
+impl<T: PartialEq> Change<T> {
+    fn new(from: T, to: T) -> Option<Self> {
+        (from != to).then_some(Self { from, to })
+    }
+}
+
+impl DiffReport {
+    fn new(server: String, previous: Snapshot, latest: Snapshot) -> Self {
+        let went = match (&previous.output, &latest.output) {
+            (None, Some(_)) => Some(Transition::Up),
+            (Some(_), None) => Some(Transition::Down),
+            _ => None,
+        };
+        // Only known to have changed while the server was online for both polls, whatever it
+        // reports after coming back up is not a change
+        let both = previous.output.zip(latest.output);
+        let (players_joined, players_left) = both.as_ref().map_or((0, 0), |(from, to)| {
+            (
+                to.players_online.saturating_sub(from.players_online),
+                from.players_online.saturating_sub(to.players_online),
+            )
+        });
+        let (max_players, motd, version) = both.map_or((None, None, None), |(from, to)| {
+            (
+                Change::new(from.max_players, to.max_players),
+                Change::new(from.motd, to.motd),
+                Change::new(from.version, to.version),
+            )
+        });
+
+        Self {
+            server,
+            previous_polled_at: previous.polled_at,
+            latest_polled_at: latest.polled_at,
+            changed: went.is_some()
+                || players_joined > 0
+                || players_left > 0
+                || max_players.is_some()
+                || motd.is_some()
+                || version.is_some(),
+            went,
+            players_joined,
+            players_left,
+            max_players,
+            motd,
+            version,
+        }
+    }
+}
+
+/// What changed between the last two polls of a server from the config file.
+pub async fn diff_handler(
+    State(state): State<AppState>,
+    Path(server): Path<String>,
+) -> Result<Json<DiffReport>, (StatusCode, String)> {
+    let history = timeline(&state, &server)?;
+    let (Some(previous), Some(latest)) = (history.previous, history.latest) else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("{server} has only been polled once, there is nothing to compare yet"),
+        ));
+    };
+
+    Ok(Json(DiffReport::new(server, previous, latest)))
+}
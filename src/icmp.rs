@@ -0,0 +1,69 @@
+//! ICMP echoes to the servers' addresses, sent alongside every fetch when `MCSTATUS_ICMP_PING` is
+//! set, so the round trip of the network can be told apart from a server that is slow to answer
+//! because it is lagging. Opening ICMP sockets needs `CAP_NET_RAW`, or on Linux a group in the
+//! `net.ipv4.ping_group_range` sysctl.
+
+use std::{
+    io,
+    net::IpAddr,
+    sync::atomic::{AtomicU16, Ordering},
+    time::Duration,
+};
+use surge_ping::{Client, Config, PingIdentifier, PingSequence, ICMP};
+use tracing::debug;
+
+use crate::env_vars::Loader;
+
+pub struct Pinger {
+    v4: Client,
+    /// Missing on hosts without IPv6.
+    v6: Option<Client>,
+    /// Tells apart the replies of concurrent pings to the same address.
+    next_identifier: AtomicU16,
+}
+
+impl Pinger {
+    pub fn from_env(vars: &mut Loader) -> Option<Self> {
+        const ICMP_PING: &str = "ICMP_PING";
+
+        if !vars.value::<bool>(ICMP_PING, "false") {
+            return None;
+        }
+        Self::new()
+            .map_err(|e| {
+                vars.invalid(
+                    ICMP_PING,
+                    format!("failed opening an ICMP socket, which needs CAP_NET_RAW: {e}"),
+                );
+            })
+            .ok()
+    }
+
+    /// Has to be called from within a Tokio runtime.
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            v4: Client::new(&Config::default())?,
+            v6: Client::new(&Config::builder().kind(ICMP::V6).build()).ok(),
+            next_identifier: AtomicU16::new(0),
+        })
+    }
+
+    /// Round trip of an echo to `ip`, `None` without a reply within `timeout`, as plenty of hosts
+    /// drop pings.
+    pub async fn rtt(&self, ip: IpAddr, timeout: Duration) -> Option<Duration> {
+        let client = match ip {
+            IpAddr::V4(_) => &self.v4,
+            IpAddr::V6(_) => self.v6.as_ref()?,
+        };
+        let identifier = self.next_identifier.fetch_add(1, Ordering::Relaxed);
+        let mut pinger = client.pinger(ip, PingIdentifier(identifier)).await;
+        pinger.timeout(timeout);
+        match pinger.ping(PingSequence(0), &[0; 8]).await {
+            Ok((_, rtt)) => Some(rtt),
+            Err(e) => {
+                debug!(%ip, %e, "No ICMP echo reply");
+                None
+            }
+        }
+    }
+}
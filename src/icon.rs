@@ -0,0 +1,172 @@
+//! `/:url/icon`, the server's own 64×64 icon, as a PNG or scaled and converted with `?size=` and
+//! `?format=`. Icons rarely change, so they are kept in a cache of their own with a much longer TTL
+//! than statuses, and not decoded again with every fetch.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{imageops::FilterType, ImageFormat};
+use mcstatus_core::ServerAddr;
+use serde::Deserialize;
+use std::io::Cursor;
+use tracing::debug;
+
+use crate::{status_error, AppState};
+
+/// Largest `?size=` served, past this it's just upscaling with nothing gained.
+const MAX_SIZE: u32 = 512;
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct Transform {
+    /// Width and height to scale to, the icon is served as it is when unset.
+    size: Option<u32>,
+    #[serde(default)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    #[default]
+    Png,
+    Webp,
+    Jpeg,
+}
+
+impl Format {
+    const fn image_format(self) -> ImageFormat {
+        match self {
+            Self::Png => ImageFormat::Png,
+            Self::Webp => ImageFormat::WebP,
+            Self::Jpeg => ImageFormat::Jpeg,
+        }
+    }
+}
+
+pub async fn handler(
+    Path(addr): Path<String>,
+    Query(query): Query<Transform>,
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, String)> {
+    if query.size.is_some_and(|size| size == 0 || size > MAX_SIZE) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("The icon size must be between 1 and {MAX_SIZE}"),
+        ));
+    }
+    debug!(%addr, "Icon requested from api");
+
+    let addr = state.resolve(&addr).await.map_err(status_error)?;
+    let address = addr.address;
+    let icon = state
+        .icons
+        .try_get_with(address, fetch(&state, addr))
+        .await
+        .map_err(|e| (*e).clone())?;
+    let Some(icon) = icon else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("{address} doesn't have an icon"),
+        ));
+    };
+
+    let icon = if query.size.is_none() && query.format == Format::Png {
+        icon
+    } else {
+        tokio::task::spawn_blocking(move || convert(&icon, query))
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Failed to join conversion thread: {e}"),
+                )
+            })??
+    };
+
+    let max_age = format!("public, max-age={}", state.icon_cache_ttl.as_secs());
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                query.format.image_format().to_mime_type().to_owned(),
+            ),
+            (header::CACHE_CONTROL, max_age),
+        ],
+        icon,
+    )
+        .into_response())
+}
+
+/// Takes the icon out of the server's status. Servers without one are cached as such, servers that
+/// couldn't be asked aren't cached at all.
+async fn fetch(state: &AppState, addr: ServerAddr) -> Result<Option<Bytes>, (StatusCode, String)> {
+    let status = state.cached_status(addr).await?;
+    let Some(raw) = status.raw else {
+        let reason = status
+            .error
+            .unwrap_or_else(|| "only the native backend gets icons".to_owned());
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No icon for {}: {reason}", status.requested_url),
+        ));
+    };
+
+    let json = serde_json::from_str::<serde_json::Value>(&raw.json).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Raw status was not JSON: {e}"),
+        )
+    })?;
+    let Some(favicon) = json.get("favicon").and_then(|f| f.as_str()) else {
+        return Ok(None);
+    };
+    let data = favicon
+        .strip_prefix("data:image/png;base64,")
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_GATEWAY,
+                "Server sent an icon that is not a PNG data URL".to_owned(),
+            )
+        })?;
+    // Some servers wrap the base64 like in a MIME message
+    let data = data.replace(['\n', '\r'], "");
+    let icon = STANDARD.decode(data).map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("Server sent an icon that is not valid base64: {e}"),
+        )
+    })?;
+    Ok(Some(icon.into()))
+}
+
+/// Scales and re-encodes a PNG icon as asked for in `transform`.
+fn convert(icon: &[u8], transform: Transform) -> Result<Bytes, (StatusCode, String)> {
+    let mut image = image::load_from_memory_with_format(icon, ImageFormat::Png).map_err(|e| {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("Server sent an icon that is not a valid PNG: {e}"),
+        )
+    })?;
+    if let Some(size) = transform.size {
+        image = image.resize_exact(size, size, FilterType::Lanczos3);
+    }
+    // JPEG has no transparency, so it would turn transparent pixels into garbage
+    if transform.format == Format::Jpeg {
+        image = image.into_rgb8().into();
+    }
+
+    let mut converted = Cursor::new(Vec::new());
+    image
+        .write_to(&mut converted, transform.format.image_format())
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed encoding the icon: {e}"),
+            )
+        })?;
+    Ok(converted.into_inner().into())
+}
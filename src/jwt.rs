@@ -0,0 +1,323 @@
+//! JWT bearer authentication, for deployments that hand out tokens per tenant instead of sharing
+//! one set of credentials. Tokens are checked against `MCSTATUS_JWT_SECRET` (HMAC) or the keys
+//! published at `MCSTATUS_JWT_JWKS_URL`, and need the `status:read` scope to see statuses. A token
+//! with the `admin` scope also gets what the admin token does.
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use crate::{env_vars::Loader, AppState};
+
+pub const READ_SCOPE: &str = "status:read";
+pub const ADMIN_SCOPE: &str = "admin";
+
+/// How long published keys are used before being fetched again.
+const JWKS_TTL: Duration = Duration::from_secs(60 * 60);
+/// How soon published keys may be fetched again for a token signed by a key they didn't have, so
+/// tokens with made up key IDs can't hammer the issuer.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+const JWKS_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Validator {
+    keys: Keys,
+    validation: Validation,
+    /// Path prefixes that require a token, every path does when empty.
+    paths: Vec<String>,
+}
+
+enum Keys {
+    Secret(DecodingKey),
+    Jwks {
+        url: String,
+        client: reqwest::Client,
+        /// Held while fetching, so concurrent requests wait on one fetch.
+        set: Mutex<Option<(JwkSet, Instant)>>,
+    },
+}
+
+/// What a token grants, put in the request extensions of those that sent a valid one.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Claims {
+    pub sub: Option<String>,
+    #[serde(default)]
+    scope: Scope,
+}
+
+/// Either space separated like in OAuth, or a list.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(untagged)]
+enum Scope {
+    #[default]
+    None,
+    Joined(String),
+    List(Vec<String>),
+}
+
+impl Claims {
+    #[must_use]
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match &self.scope {
+            Scope::None => false,
+            Scope::Joined(scopes) => scopes.split_whitespace().any(|s| s == scope),
+            Scope::List(scopes) => scopes.iter().any(|s| s == scope),
+        }
+    }
+}
+
+impl Validator {
+    /// Reads the key from `MCSTATUS_JWT_SECRET` or `MCSTATUS_JWT_JWKS_URL`, the expected `iss` and
+    /// `aud` from `MCSTATUS_JWT_ISSUER` and `MCSTATUS_JWT_AUDIENCE`, and
+    /// `MCSTATUS_JWT_PATHS` as a comma separated list of path prefixes to protect. Tokens aren't
+    /// checked when there is no key.
+    pub fn from_env(vars: &mut Loader) -> Option<Self> {
+        const JWT_SECRET: &str = "JWT_SECRET";
+        const JWT_JWKS_URL: &str = "JWT_JWKS_URL";
+        const JWT_ISSUER: &str = "JWT_ISSUER";
+        const JWT_AUDIENCE: &str = "JWT_AUDIENCE";
+        const JWT_PATHS: &str = "JWT_PATHS";
+
+        let secret = vars.secret(JWT_SECRET).filter(|s| !s.is_empty());
+        let jwks_url = vars.optional(JWT_JWKS_URL).filter(|u| !u.is_empty());
+        let issuer = vars.optional(JWT_ISSUER);
+        let audience = vars.optional(JWT_AUDIENCE);
+        let paths = vars.string(JWT_PATHS, "");
+        let paths = paths
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+
+        let validator = match (secret, jwks_url) {
+            (None, None) => return None,
+            (Some(secret), None) => Self::secret(secret.as_bytes()),
+            (None, Some(url)) => Self::jwks(url),
+            (Some(_), Some(_)) => {
+                vars.invalid(
+                    JWT_JWKS_URL,
+                    format!("can't be used along with {JWT_SECRET}"),
+                );
+                return None;
+            }
+        };
+        Some(validator.issuer(issuer).audience(audience).paths(paths))
+    }
+
+    /// Checks tokens signed with `secret` using HMAC.
+    #[must_use]
+    pub fn secret(secret: &[u8]) -> Self {
+        Self::new(
+            Keys::Secret(DecodingKey::from_secret(secret)),
+            &[Algorithm::HS256, Algorithm::HS384, Algorithm::HS512],
+        )
+    }
+
+    /// Checks tokens signed with one of the keys in the JWK set at `url`.
+    #[must_use]
+    pub fn jwks(url: impl Into<String>) -> Self {
+        Self::new(
+            Keys::Jwks {
+                url: url.into(),
+                client: reqwest::Client::new(),
+                set: Mutex::new(None),
+            },
+            // Never HMAC, which would take the public key as the secret
+            &[
+                Algorithm::RS256,
+                Algorithm::RS384,
+                Algorithm::RS512,
+                Algorithm::PS256,
+                Algorithm::PS384,
+                Algorithm::PS512,
+                Algorithm::ES256,
+                Algorithm::ES384,
+                Algorithm::EdDSA,
+            ],
+        )
+    }
+
+    fn new(keys: Keys, algorithms: &[Algorithm]) -> Self {
+        let mut validation = Validation::new(algorithms[0]);
+        validation.algorithms = algorithms.to_vec();
+        validation.validate_aud = false;
+        Self {
+            keys,
+            validation,
+            paths: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn issuer(mut self, issuer: Option<String>) -> Self {
+        if let Some(issuer) = issuer {
+            self.validation.set_issuer(&[issuer]);
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn audience(mut self, audience: Option<String>) -> Self {
+        if let Some(audience) = audience {
+            self.validation.set_audience(&[audience]);
+            self.validation.validate_aud = true;
+            self.validation
+                .required_spec_claims
+                .insert("aud".to_owned());
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn paths(mut self, paths: Vec<String>) -> Self {
+        self.paths = paths;
+        self
+    }
+
+    /// `/healthz` is always open, agents have their own tokens for `/federation/report`, and the
+    /// routes under `/admin/` check for admin access themselves.
+    fn protects(&self, path: &str) -> bool {
+        path != "/healthz"
+            && path != "/federation/report"
+            && !path.starts_with("/admin/")
+            && (self.paths.is_empty() || self.paths.iter().any(|p| path.starts_with(p.as_str())))
+    }
+
+    /// The claims of `token`, if it is signed by one of the keys and valid.
+    pub(crate) async fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<T, String> {
+        let header = jsonwebtoken::decode_header(token).map_err(|e| e.to_string())?;
+        if !self.validation.algorithms.contains(&header.alg) {
+            return Err(format!(
+                "Tokens signed with {:?} aren't accepted",
+                header.alg
+            ));
+        }
+        let key = match &self.keys {
+            Keys::Secret(key) => key.clone(),
+            Keys::Jwks { url, client, set } => {
+                let kid = header.kid.as_deref().ok_or("Token has no key ID")?;
+                jwks_key(url, client, &mut *set.lock().await, kid).await?
+            }
+        };
+        // Every algorithm validated against has to suit the key, which only the token's does
+        let mut validation = self.validation.clone();
+        validation.algorithms = vec![header.alg];
+        jsonwebtoken::decode::<T>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// The key `kid` from the set at `url`, fetching the set again if it is old or doesn't have it.
+async fn jwks_key(
+    url: &str,
+    client: &reqwest::Client,
+    set: &mut Option<(JwkSet, Instant)>,
+    kid: &str,
+) -> Result<DecodingKey, String> {
+    let fetch = match set {
+        None => true,
+        Some((set, fetched_at)) => {
+            fetched_at.elapsed() >= JWKS_TTL
+                || (set.find(kid).is_none() && fetched_at.elapsed() >= JWKS_MIN_REFRESH)
+        }
+    };
+    if fetch {
+        match fetch_jwks(url, client).await {
+            Ok(fetched) => {
+                info!(url, keys = fetched.keys.len(), "Fetched JWT signing keys");
+                *set = Some((fetched, Instant::now()));
+            }
+            // Keeps using the old keys, or none, until the next try
+            Err(e) => {
+                warn!(%e, url, "Failed fetching JWT signing keys");
+                let old = set.take().map(|(set, _)| set);
+                *set = Some((old.unwrap_or(JwkSet { keys: Vec::new() }), Instant::now()));
+            }
+        }
+    }
+    let jwk = set
+        .as_ref()
+        .and_then(|(set, _)| set.find(kid))
+        .ok_or_else(|| format!("No signing key with the ID {kid}"))?;
+    DecodingKey::from_jwk(jwk).map_err(|e| format!("Signing key {kid} is unusable: {e}"))
+}
+
+async fn fetch_jwks(url: &str, client: &reqwest::Client) -> reqwest::Result<JwkSet> {
+    client
+        .get(url)
+        .timeout(JWKS_TIMEOUT)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+pub async fn require(
+    State(state): State<AppState>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(validator) = &state.jwt else {
+        return next.run(request).await;
+    };
+    let protected = validator.protects(request.uri().path());
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|a| a.strip_prefix("Bearer "))
+        .map(str::trim);
+    // Operators can keep using the admin token, or their SSO login
+    let admin = token.is_some_and(|t| state.admin_token.as_ref().is_some_and(|a| a.matches(t)))
+        || state
+            .oidc
+            .as_ref()
+            .is_some_and(|o| o.has_session(request.headers()));
+    if admin {
+        return next.run(request).await;
+    }
+
+    let claims = match token {
+        Some(token) => validator.decode::<Claims>(token).await,
+        None => Err("No bearer token".to_owned()),
+    };
+    let claims = match claims {
+        Ok(claims) => claims,
+        Err(_) if !protected => return next.run(request).await,
+        Err(e) => {
+            debug!(%e, "Rejected request without a valid JWT");
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, r#"Bearer realm="mcstatus-http""#)],
+                "A valid bearer token is required",
+            )
+                .into_response();
+        }
+    };
+    if protected && !claims.has_scope(READ_SCOPE) && !claims.has_scope(ADMIN_SCOPE) {
+        debug!(sub = claims.sub, "Rejected JWT without the read scope");
+        return (
+            StatusCode::FORBIDDEN,
+            [(
+                header::WWW_AUTHENTICATE,
+                format!(r#"Bearer error="insufficient_scope", scope="{READ_SCOPE}""#),
+            )],
+            format!("The token needs the {READ_SCOPE} scope"),
+        )
+            .into_response();
+    }
+
+    request.extensions_mut().insert(claims);
+    next.run(request).await
+}
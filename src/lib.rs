@@ -0,0 +1,2056 @@
+//! The status API as a library, for mounting it inside another axum application instead of running
+//! the `mcstatus-http` binary:
+//!
+//! ```no_run
+//! # async fn run() {
+//! let state = mcstatus_http::AppState::builder()
+//!     .cache_ttl(std::time::Duration::from_secs(30))
+//!     .build();
+//! mcstatus_http::spawn_background_tasks(&state);
+//! let app = axum::Router::new().nest("/mc", mcstatus_http::router(state));
+//! # let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
+//! # axum::serve(listener, app).await.unwrap();
+//! # }
+//! ```
+
+#![deny(
+    clippy::enum_glob_use,
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::unwrap_used
+)]
+
+mod admin;
+mod alerts;
+mod any;
+mod assets;
+mod basic_auth;
+mod case;
+mod changes;
+mod circuit;
+mod client_ip;
+pub mod config;
+pub mod dump;
+pub mod env_vars;
+mod federation;
+mod flapping;
+mod geoip;
+mod geyser;
+mod graphite;
+mod graphql;
+pub mod grpc;
+mod headers;
+mod heartbeat;
+mod history;
+mod icmp;
+mod icon;
+pub mod jwt;
+mod load_shed;
+mod maintenance;
+mod metrics;
+mod mqtt;
+mod notify;
+pub mod oidc;
+mod overrides;
+mod pages;
+mod plugins;
+mod poller;
+mod rate_cap;
+mod reachable;
+mod scripts;
+mod shared_cache;
+mod signing;
+mod stats;
+mod statsd;
+pub mod status_cache;
+mod summary;
+mod text;
+mod timeout;
+mod transform;
+mod wait;
+
+pub use notify::EventKind;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderName, Request, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use case::Case;
+use client_ip::ClientIp;
+use config::{Backend, Config};
+use env_vars::Loader;
+use geoip::GeoIp;
+use history::History;
+use ipnet::IpNet;
+use load_shed::LoadShed;
+use mcstatus_core::{
+    bedrock, slp, CacheInfo, Outbound, Resolver, ServerAddr, ServerStatus, Socks5Proxy,
+    TlsNameServer,
+};
+use moka::future::{Cache, CacheBuilder};
+use notify::Notifier;
+use overrides::{DefaultPorts, Overrides};
+use poller::PollResult;
+use rate_cap::RateCap;
+use serde::{Deserialize, Serialize};
+use status_cache::{MokaCache, StatusCache};
+use std::{
+    any::Any,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use timeout::RouteTimeouts;
+use tokio::sync::broadcast;
+use tower_http::{catch_panic::CatchPanicLayer, trace::TraceLayer};
+use tracing::{debug, debug_span, field, info, info_span, warn, Instrument, Span};
+
+/// Most servers whose status can be asked for in a single batch request, as each of them can mean
+/// a fetch.
+const MAX_BATCH_ADDRESSES: usize = 100;
+
+/// Everything the routes and background tasks share, created with [`AppState::from_env`] or
+/// [`AppState::builder`].
+#[derive(Clone)]
+pub struct AppState {
+    mc_monitor_executable: Arc<str>,
+    use_mc_monitor: Arc<bool>,
+    mc_monitor_timeout: Duration,
+    /// Keyed by the resolved address, so a server asked for by name and by IP, or with and
+    /// without its default port, is fetched once and shares one in-flight fetch.
+    cache: Arc<dyn StatusCache>,
+    /// For servers without one in the config file.
+    cache_ttl: Duration,
+    /// Decoded server icons, kept much longer than statuses.
+    icons: Cache<SocketAddr, Option<Bytes>>,
+    /// The last reachability probes, kept for as long as the rate cap keeps servers from being
+    /// fetched again.
+    reachability: Cache<SocketAddr, reachable::Reachability>,
+    icon_cache_ttl: Duration,
+    /// The last status fetched of each server, served when fetching a new one fails. Unset when
+    /// stale statuses are never served.
+    last_fetched: Option<Cache<SocketAddr, ServerStatus>>,
+    hot_refresh: Option<HotRefresh>,
+    trusted_proxies: Arc<[IpNet]>,
+    /// Where the admin routes and packet dumps may be reached from, anywhere when empty.
+    admin_allowed_ips: Arc<[IpNet]>,
+    basic_auth: Option<Arc<basic_auth::Credentials>>,
+    admin_token: Option<Arc<admin::Token>>,
+    signing_key: Option<Arc<signing::Key>>,
+    jwt: Option<Arc<jwt::Validator>>,
+    oidc: Option<Arc<oidc::Provider>>,
+    favicon: Favicon,
+    config: Arc<Config>,
+    overrides: Arc<Overrides>,
+    /// Whether statuses served by alias leave out the addresses the server resolved to.
+    hide_ips: bool,
+    poll_results: broadcast::Sender<Arc<PollResult>>,
+    history: History,
+    graphql: graphql::Schema,
+    statsd: Option<statsd::Client>,
+    counters: Arc<stats::Counters>,
+    timeouts: Arc<RouteTimeouts>,
+    load_shed: Arc<LoadShed>,
+    geyser: geyser::Detection,
+    outbound: Arc<Outbound>,
+    rate_cap: Arc<RateCap>,
+    /// Reports of the agents in other regions, when this instance is their aggregator.
+    federation: Option<Arc<federation::Aggregator>>,
+    circuit_breaker: Arc<circuit::Breaker>,
+    /// Statuses shared with the other replicas, when there is a Redis to share them through.
+    shared_cache: Option<Arc<shared_cache::SharedCache>>,
+    geoip: Option<Arc<GeoIp>>,
+    icmp: Option<Arc<icmp::Pinger>>,
+    flapping: Arc<flapping::Tracker>,
+    templates: Arc<pages::Templates>,
+    plugins: Arc<plugins::Plugins>,
+}
+
+#[derive(Clone)]
+struct Favicon {
+    content_type: &'static str,
+    data: Bytes,
+}
+
+impl Default for Favicon {
+    /// The icon embedded in the binary.
+    fn default() -> Self {
+        Self {
+            content_type: "image/png",
+            data: Bytes::from_static(include_bytes!("../assets/favicon.png")),
+        }
+    }
+}
+
+impl Favicon {
+    /// Loads the icon from `MCSTATUS_FAVICON_PATH`, falling back to the one embedded in the binary.
+    fn from_env(vars: &mut Loader) -> Self {
+        const FAVICON_PATH: &str = "FAVICON_PATH";
+
+        let Some(path) = vars.optional(FAVICON_PATH) else {
+            return Self::default();
+        };
+
+        let extension = std::path::Path::new(&path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+        let content_type = match extension.as_deref() {
+            Some("ico") => "image/x-icon",
+            Some("png") => "image/png",
+            Some("svg") => "image/svg+xml",
+            Some("gif") => "image/gif",
+            Some("jpg" | "jpeg") => "image/jpeg",
+            Some("webp") => "image/webp",
+            _ => {
+                vars.invalid(
+                    FAVICON_PATH,
+                    "unrecognized format, expected ico, png, svg, gif, jpeg or webp",
+                );
+                return Self::default();
+            }
+        };
+        match std::fs::read(&path) {
+            Ok(data) => Self {
+                content_type,
+                data: data.into(),
+            },
+            Err(e) => {
+                vars.invalid(FAVICON_PATH, format!("failed reading {path}: {e}"));
+                Self::default()
+            }
+        }
+    }
+}
+
+/// How the native backends reach servers, as set by `MCSTATUS_SOURCE_ADDRESS`,
+/// `MCSTATUS_SOURCE_INTERFACE` and `MCSTATUS_SOCKS5_PROXY`, and how long they wait on them, as set
+/// by `MCSTATUS_CONNECT_TIMEOUT` and `MCSTATUS_READ_TIMEOUT`.
+fn outbound_from_env(vars: &mut Loader) -> Outbound {
+    const SOURCE_ADDRESS: &str = "SOURCE_ADDRESS";
+    const SOURCE_INTERFACE: &str = "SOURCE_INTERFACE";
+    const SOCKS5_PROXY: &str = "SOCKS5_PROXY";
+    const CONNECT_TIMEOUT: &str = "CONNECT_TIMEOUT";
+    const READ_TIMEOUT: &str = "READ_TIMEOUT";
+
+    let source = vars.parse_optional(SOURCE_ADDRESS, |address| {
+        address
+            .parse()
+            .map_err(|_| format!("{address} is not an IP address"))
+    });
+    let interface = vars.optional(SOURCE_INTERFACE);
+    if interface.is_some() && cfg!(not(target_os = "linux")) {
+        vars.invalid(SOURCE_INTERFACE, "only supported on Linux");
+    }
+    // Credentials in the URL keep it out of the printed configuration
+    let socks5_proxy = vars.secret(SOCKS5_PROXY).and_then(|proxy| {
+        proxy
+            .parse()
+            .map_err(|e| vars.invalid(SOCKS5_PROXY, e))
+            .ok()
+    });
+    // Unset, only the backend's timeout applies to the whole ping
+    let connect_timeout = vars.parse_optional(CONNECT_TIMEOUT, env_vars::duration);
+    let read_timeout = vars.parse_optional(READ_TIMEOUT, env_vars::duration);
+    Outbound {
+        source,
+        interface,
+        socks5_proxy,
+        connect_timeout,
+        read_timeout,
+    }
+}
+
+/// Settings for proactively refreshing popular cache entries before they expire.
+#[derive(Clone)]
+struct HotRefresh {
+    /// Number of requests within a single cache lifetime for an entry to count as hot.
+    threshold: u32,
+    /// How long before expiry a hot entry gets refreshed.
+    lead: Duration,
+    /// Requests seen for each key since it was last (re)fetched.
+    request_counts: Arc<Mutex<HashMap<SocketAddr, HotCount>>>,
+}
+
+struct HotCount {
+    requests: u32,
+    /// How the key was last resolved, so refreshing connects the way requests do.
+    addr: ServerAddr,
+}
+
+impl HotRefresh {
+    fn from_env(vars: &mut Loader, cache_ttl: Duration) -> Option<Self> {
+        const HOT_REFRESH_THRESHOLD: &str = "HOT_REFRESH_THRESHOLD";
+        const HOT_REFRESH_LEAD: &str = "HOT_REFRESH_LEAD";
+
+        let threshold = vars.value::<u32>(HOT_REFRESH_THRESHOLD, "5");
+        let lead = vars.duration(HOT_REFRESH_LEAD, "2 seconds");
+        if lead.is_zero() {
+            vars.invalid(HOT_REFRESH_LEAD, "must be longer than zero");
+            return None;
+        }
+        if lead >= cache_ttl {
+            vars.invalid(HOT_REFRESH_LEAD, "must be shorter than the cache TTL");
+            return None;
+        }
+
+        // A threshold of 0 disables hot refreshing entirely
+        (threshold > 0).then(|| Self {
+            threshold,
+            lead,
+            request_counts: Arc::default(),
+        })
+    }
+
+    /// The keys of `expiring` that were requested often enough to be refreshed, as they were last
+    /// resolved. Every expiring key starts a new counting window, whether it gets refreshed or not.
+    fn take_hot(
+        &self,
+        cached: &HashSet<SocketAddr>,
+        expiring: impl Iterator<Item = SocketAddr>,
+    ) -> Vec<ServerAddr> {
+        let mut counts = self
+            .request_counts
+            .lock()
+            .expect("Request counts lock should not be poisoned");
+        // Failed fetches aren't cached, so without this every address anyone asked for once, like
+        // made up ones, would be counted forever
+        counts.retain(|addr, _| cached.contains(addr));
+        let hot = expiring
+            .filter_map(|addr| counts.remove(&addr))
+            .filter(|count| count.requests >= self.threshold)
+            .map(|count| count.addr)
+            .collect();
+        drop(counts);
+        hot
+    }
+}
+
+impl AppState {
+    /// Sets everything up from the environment and the config file in `MCSTATUS_CONFIG_FILE`, as
+    /// the `mcstatus-http` binary does. Anything invalid is recorded in `vars` and left at its
+    /// default, so [`Loader::finish`] must be checked before using the state.
+    pub fn from_env(vars: &mut Loader) -> Self {
+        const MC_MONITOR_EXECUTABLE: &str = "MC_MONITOR_EXECUTABLE";
+        const CACHE_TTL: &str = "CACHE_TTL";
+        const USE_MC_MONITOR: &str = "USE_MC_MONITOR";
+        const ICON_CACHE_TTL: &str = "ICON_CACHE_TTL";
+        const MC_MONITOR_TIMEOUT: &str = "MC_MONITOR_TIMEOUT";
+        const HIDE_IPS: &str = "HIDE_IPS";
+        const MAX_STALENESS: &str = "MAX_STALENESS";
+        const CONFIG_FILE: &str = "CONFIG_FILE";
+
+        let mc_monitor_executable = vars.string(MC_MONITOR_EXECUTABLE, "mc-monitor");
+        let cache_ttl = vars.duration(CACHE_TTL, "10 seconds");
+        let use_mc_monitor = vars.value(USE_MC_MONITOR, "true");
+        let icon_cache_ttl = vars.duration(ICON_CACHE_TTL, "1 hour");
+        let mc_monitor_timeout = vars.duration(MC_MONITOR_TIMEOUT, "10 seconds");
+        let hide_ips = vars.value(HIDE_IPS, "false");
+        let max_staleness = vars.duration(MAX_STALENESS, "5 minutes");
+
+        let mut problems = Vec::new();
+        let state = AppStateBuilder {
+            mc_monitor_executable,
+            use_mc_monitor,
+            mc_monitor_timeout,
+            cache_ttl,
+            icon_cache_ttl,
+            max_staleness,
+            hot_refresh: HotRefresh::from_env(vars, cache_ttl),
+            trusted_proxies: client_ip::trusted_proxies_from_env(vars),
+            admin_allowed_ips: admin::allowed_ips_from_env(vars),
+            basic_auth: basic_auth::Credentials::from_env(vars),
+            admin_token: admin::Token::from_env(vars),
+            signing_key: signing::Key::from_env(vars),
+            jwt: jwt::Validator::from_env(vars),
+            oidc: oidc::Provider::from_env(vars),
+            favicon: Favicon::from_env(vars),
+            config: Config::from_env(vars),
+            hide_ips,
+            timeouts: RouteTimeouts::from_env(vars),
+            load_shed: LoadShed::from_env(vars),
+            geyser: geyser::Detection::from_env(vars),
+            outbound: outbound_from_env(vars),
+            rate_cap: RateCap::from_env(vars),
+            circuit_breaker: circuit::Breaker::from_env(vars),
+            shared_cache: shared_cache::SharedCache::from_env(vars),
+            status_cache: None,
+            geoip: GeoIp::from_env(vars),
+            icmp: icmp::Pinger::from_env(vars),
+            templates: pages::Templates::from_env(vars),
+            plugins: plugins::Plugins::from_env(vars),
+            default_ports: DefaultPorts::from_env(vars),
+        }
+        .build_reporting(|e| problems.push(e));
+        if !problems.is_empty() {
+            vars.invalid(CONFIG_FILE, problems.join("; "));
+        }
+        state
+    }
+
+    /// Starts configuring a state in code, with the same defaults as when the environment is empty.
+    pub fn builder() -> AppStateBuilder {
+        AppStateBuilder {
+            mc_monitor_executable: "mc-monitor".to_owned(),
+            use_mc_monitor: true,
+            mc_monitor_timeout: Duration::from_secs(10),
+            cache_ttl: Duration::from_secs(10),
+            icon_cache_ttl: Duration::from_secs(60 * 60),
+            max_staleness: Duration::from_secs(5 * 60),
+            hot_refresh: None,
+            trusted_proxies: Vec::new(),
+            admin_allowed_ips: Vec::new(),
+            basic_auth: None,
+            admin_token: None,
+            signing_key: None,
+            jwt: None,
+            oidc: None,
+            favicon: Favicon::default(),
+            config: Config::default(),
+            hide_ips: false,
+            timeouts: RouteTimeouts::new(Duration::from_secs(15)),
+            load_shed: LoadShed::new(64, 256, Duration::from_secs(5)),
+            geyser: geyser::Detection::new(mcstatus_core::bedrock::DEFAULT_PORT, false),
+            outbound: Outbound::default(),
+            rate_cap: RateCap::new(Duration::from_secs(5)),
+            circuit_breaker: circuit::Breaker::new(5, Duration::from_secs(60)),
+            shared_cache: None,
+            status_cache: None,
+            geoip: None,
+            icmp: None,
+            templates: pages::Templates::default(),
+            plugins: plugins::Plugins::default(),
+            default_ports: DefaultPorts::default(),
+        }
+        .hot_refresh(5, Duration::from_secs(2))
+    }
+
+    /// Gets the status for `addr` from the cache, fetching it if it is not there yet.
+    async fn cached_status(&self, addr: ServerAddr) -> Result<ServerStatus, (StatusCode, String)> {
+        self.cached_status_as(addr, None).await
+    }
+
+    /// Like [`AppState::cached_status`], fetching with `backend` instead of the configured one when
+    /// set. Those aren't refreshed ahead of time, as the refresh wouldn't know the backend.
+    async fn cached_status_as(
+        &self,
+        addr: ServerAddr,
+        backend: Option<Backend>,
+    ) -> Result<ServerStatus, (StatusCode, String)> {
+        if let Some(hot_refresh) = self.hot_refresh.as_ref().filter(|_| backend.is_none()) {
+            let mut counts = hot_refresh
+                .request_counts
+                .lock()
+                .expect("Request counts lock should not be poisoned");
+            counts
+                .entry(addr.address)
+                .and_modify(|count| {
+                    count.requests += 1;
+                    count.addr = addr.clone();
+                })
+                .or_insert_with(|| HotCount {
+                    requests: 1,
+                    addr: addr.clone(),
+                });
+        }
+
+        let address = addr.address;
+        let domain_name = addr.domain_name.clone();
+        let resolution = addr.resolution();
+        let resolved_in = addr.resolved_in;
+        // This is spawned in a task so the fetch isn't killed if the request is stopped This makes
+        // it so repeated requests to the endpoint, while killing the previous request (like browser
+        // refreshes) don't hammer the mc server.
+        let state = self.clone();
+        let handle = tokio::spawn(async move {
+            state
+                .cache
+                .get_or_fetch(addr.address, Box::pin(state.fetch_as(&addr, backend)))
+                .await
+        });
+        let entry = handle.await.map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to join cache thread: {e}"),
+            )
+        })?;
+        let (mut status, hit) = match entry {
+            Ok((status, hit)) => {
+                self.counters.cache_lookup(hit);
+                if let Some(statsd) = &self.statsd {
+                    statsd.count(if hit { "cache.hit" } else { "cache.miss" }, None);
+                }
+                (status, hit)
+            }
+            // Dashboards would rather show an old status than none
+            Err((code, error)) => {
+                let last = match &self.last_fetched {
+                    Some(last_fetched) => last_fetched.get(&address).await,
+                    None => None,
+                };
+                let Some(mut status) = last else {
+                    return Err((code, error));
+                };
+                debug!(%address, %error, "Fetching failed, serving a stale status");
+                self.counters.stale_served();
+                if let Some(statsd) = &self.statsd {
+                    statsd.count("cache.stale", None);
+                }
+                status.stale = true;
+                status.refresh_error = Some(error);
+                (status, true)
+            }
+        };
+        let ttl = self
+            .overrides
+            .cache_ttl(status.requested_url, self.cache_ttl);
+        status.cache = Some(CacheInfo::new(status.fetched_at, ttl, hit));
+        status.domain_name = domain_name;
+        status.resolution = resolution;
+        if let Some(timings) = &mut status.timings {
+            timings.dns_ms = resolved_in.map(|d| d.as_secs_f64() * 1000.0);
+        }
+        if let Some(server) = self.overrides.get(status.requested_url) {
+            status.maintenance =
+                maintenance::active(&self.config, &server.address, chrono::Utc::now());
+            status.flapping = self.flapping.is_flapping(&server.address);
+        }
+        Ok(status)
+    }
+
+    /// Fetches the status of `address` with the configured backend, skipping the cache, for one-off
+    /// checks from outside the HTTP server.
+    ///
+    /// # Errors
+    ///
+    /// If the address doesn't resolve, or the backend fails.
+    pub async fn fetch_uncached(&self, address: String) -> Result<ServerStatus, String> {
+        let addr = self.resolve(&address).await.map_err(|e| e.to_string())?;
+        let mut status = self.fetch(&addr).await.map_err(|(_, e)| e)?;
+        status.resolution = addr.resolution();
+        status.domain_name = addr.domain_name;
+        status.cached_at = None;
+        Ok(status)
+    }
+
+    /// `status` as `/:url` serves it, after the plugins and the transform rules had their way, with
+    /// its fields named in `case`.
+    fn status_json(&self, status: ServerStatus, case: Case) -> Response {
+        let rules = self.transform_rules(status.requested_url);
+        let transformed = self.plugins.transform(&status);
+        if transformed.is_none() && rules.is_empty() && case == Case::Snake {
+            return Json(status).into_response();
+        }
+        let mut value =
+            transformed.unwrap_or_else(|| serde_json::to_value(status).unwrap_or_default());
+        for rules in rules {
+            transform::apply(rules, &mut value);
+        }
+        case.apply(&mut value);
+        Json(value).into_response()
+    }
+
+    /// The transform rules of the whole config file, then those of the server at `address`.
+    fn transform_rules(&self, address: SocketAddr) -> Vec<&config::Transform> {
+        let server = self
+            .overrides
+            .get(address)
+            .and_then(|s| s.transform.as_ref());
+        self.config.transform.iter().chain(server).collect()
+    }
+
+    /// Resolves `addr`, with the default port from the config file if it names a server there.
+    async fn resolve(&self, addr: &str) -> Result<ServerAddr, mcstatus_core::Error> {
+        self.overrides.resolve(addr).await
+    }
+
+    /// Where `ip` is, if `GeoIP` databases are configured.
+    fn geo(&self, ip: IpAddr) -> Option<mcstatus_core::Geo> {
+        self.geoip.as_ref()?.lookup(ip)
+    }
+
+    /// How `server` is fetched: with which backend, unless that is given, giving up after how long,
+    /// and from where.
+    fn fetch_settings(
+        &self,
+        server: Option<&config::Server>,
+        backend: Option<Backend>,
+    ) -> (Backend, Duration, Cow<Outbound>) {
+        let backend = backend
+            .or_else(|| server.and_then(|s| s.backend))
+            .unwrap_or(if *self.use_mc_monitor {
+                Backend::McMonitor
+            } else {
+                Backend::Native
+            });
+        let timeout = server.and_then(|s| s.timeout).unwrap_or(match backend {
+            Backend::McMonitor | Backend::McMonitorBedrock => self.mc_monitor_timeout,
+            Backend::Native => slp::DEFAULT_TIMEOUT,
+            Backend::Bedrock => bedrock::DEFAULT_TIMEOUT,
+        });
+
+        let outbound = match server {
+            Some(server)
+                if server.socks5_proxy.is_some()
+                    || server.connect_timeout.is_some()
+                    || server.read_timeout.is_some() =>
+            {
+                Cow::Owned(Outbound {
+                    socks5_proxy: server
+                        .socks5_proxy
+                        .clone()
+                        .or_else(|| self.outbound.socks5_proxy.clone()),
+                    connect_timeout: server.connect_timeout.or(self.outbound.connect_timeout),
+                    read_timeout: server.read_timeout.or(self.outbound.read_timeout),
+                    ..(*self.outbound).clone()
+                })
+            }
+            _ => Cow::Borrowed(&*self.outbound),
+        };
+        (backend, timeout, outbound)
+    }
+
+    /// The status another replica fetched of `address`, or `None` if this one should fetch it.
+    async fn fetch_shared(
+        &self,
+        shared: &shared_cache::SharedCache,
+        address: SocketAddr,
+        timeout: Duration,
+    ) -> Result<Option<ServerStatus>, (StatusCode, String)> {
+        if let Some(status) = shared.get(address).await {
+            debug!(%address, "Using the status another replica fetched");
+            return Ok(Some(status));
+        }
+        match shared
+            .claim(address, self.rate_cap.interval(), timeout)
+            .await
+        {
+            shared_cache::Claim::Fetch => Ok(None),
+            shared_cache::Claim::Fetched(status) => Ok(Some(*status)),
+            shared_cache::Claim::Missing => Err((
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("{address} is being fetched by another replica, try again shortly"),
+            )),
+        }
+    }
+
+    /// Fetches the status of `addr` to be cached, marking when it was.
+    async fn fetch(&self, addr: &ServerAddr) -> Result<ServerStatus, (StatusCode, String)> {
+        self.fetch_as(addr, None).await
+    }
+
+    /// Like [`AppState::fetch`], with `backend` instead of the configured one when set.
+    async fn fetch_as(
+        &self,
+        addr: &ServerAddr,
+        backend: Option<Backend>,
+    ) -> Result<ServerStatus, (StatusCode, String)> {
+        let mut status = self.fetch_status(addr, backend).await?;
+        status.cached_at = Some(chrono::Utc::now().to_rfc3339());
+        if let Some(last_fetched) = &self.last_fetched {
+            last_fetched.insert(addr.address, status.clone()).await;
+        }
+        Ok(status)
+    }
+
+    async fn fetch_status(
+        &self,
+        addr: &ServerAddr,
+        backend: Option<Backend>,
+    ) -> Result<ServerStatus, (StatusCode, String)> {
+        let server = self.overrides.get(addr.address);
+        let (backend, timeout, outbound) = self.fetch_settings(server, backend);
+        if let Some(shared) = &self.shared_cache {
+            if let Some(status) = self.fetch_shared(shared, addr.address, timeout).await? {
+                return Ok(status);
+            }
+        }
+
+        let circuit = match self.circuit_breaker.acquire(addr.address) {
+            circuit::Permit::Fetch(fetch) => fetch,
+            circuit::Permit::Open(last) => {
+                if let Some(statsd) = &self.statsd {
+                    statsd.count("fetch.circuit_open", None);
+                }
+                return last;
+            }
+        };
+        if let rate_cap::Permit::Reuse(status) = self.rate_cap.acquire(addr.address)? {
+            if let Some(statsd) = &self.statsd {
+                statsd.count("fetch.rate_capped", None);
+            }
+            return Ok(*status);
+        }
+
+        let _in_flight = self.counters.fetching();
+        let started = Instant::now();
+        let fetch = async {
+            match backend {
+                Backend::Bedrock | Backend::McMonitorBedrock => {
+                    mcstatus_core::fetch_bedrock_status(
+                        &addr.address,
+                        backend == Backend::McMonitorBedrock,
+                        &self.mc_monitor_executable,
+                        timeout,
+                        &outbound,
+                    )
+                    .await
+                }
+                Backend::McMonitor | Backend::Native => {
+                    let java = mcstatus_core::fetch_status(
+                        &addr.address,
+                        addr.domain_name.as_ref().map(|name| name.ascii.as_str()),
+                        &addr.fallbacks,
+                        backend == Backend::McMonitor,
+                        &self.mc_monitor_executable,
+                        timeout,
+                        &outbound,
+                    );
+                    self.geyser.detect(java, &addr.address, &outbound).await
+                }
+            }
+        };
+        // Pinged at the same time, so it doesn't add to how long fetching takes
+        let icmp = async {
+            match &self.icmp {
+                Some(pinger) => pinger.rtt(addr.address.ip(), timeout).await,
+                None => None,
+            }
+        };
+        let (status, icmp_rtt) = tokio::join!(fetch, icmp);
+        let status = status.map_err(status_error).map(|mut status| {
+            if let Some(server) = server {
+                status.labels = server.labels.clone();
+            }
+            status.geo = self.geo(addr.address.ip());
+            status.icmp_rtt_ms = icmp_rtt.map(|rtt| rtt.as_secs_f64() * 1000.0);
+            let checked_at = SystemTime::now() - status.fetched_at.elapsed();
+            status.checked_at =
+                Some(chrono::DateTime::<chrono::Utc>::from(checked_at).to_rfc3339());
+            self.plugins.parse(&mut status);
+            status
+        });
+        if let Ok(status) = &status {
+            self.rate_cap.record(addr.address, status);
+            if let Some(shared) = &self.shared_cache {
+                let ttl = self.overrides.cache_ttl(addr.address, self.cache_ttl);
+                shared.put(addr.address, status, ttl).await;
+            }
+        }
+        circuit.record(&status);
+
+        if let Some(statsd) = &self.statsd {
+            let outcome = match &status {
+                Ok(status) if status.output.is_some() => "fetch.online",
+                Ok(_) => "fetch.offline",
+                Err(_) => "fetch.error",
+            };
+            statsd.count(outcome, None);
+            statsd.timing(
+                "fetch.duration",
+                started.elapsed().as_secs_f64() * 1000.0,
+                None,
+            );
+        }
+        status
+    }
+}
+
+/// Configuration for an [`AppState`] made in code, for embedding the routes in another
+/// application instead of reading the environment.
+#[must_use]
+pub struct AppStateBuilder {
+    mc_monitor_executable: String,
+    use_mc_monitor: bool,
+    mc_monitor_timeout: Duration,
+    cache_ttl: Duration,
+    icon_cache_ttl: Duration,
+    max_staleness: Duration,
+    hot_refresh: Option<HotRefresh>,
+    trusted_proxies: Vec<IpNet>,
+    admin_allowed_ips: Vec<IpNet>,
+    basic_auth: Option<basic_auth::Credentials>,
+    admin_token: Option<admin::Token>,
+    signing_key: Option<signing::Key>,
+    jwt: Option<jwt::Validator>,
+    oidc: Option<oidc::Provider>,
+    favicon: Favicon,
+    config: Config,
+    hide_ips: bool,
+    timeouts: RouteTimeouts,
+    load_shed: LoadShed,
+    geyser: geyser::Detection,
+    outbound: Outbound,
+    rate_cap: RateCap,
+    circuit_breaker: circuit::Breaker,
+    shared_cache: Option<shared_cache::SharedCache>,
+    status_cache: Option<Arc<dyn StatusCache>>,
+    geoip: Option<GeoIp>,
+    icmp: Option<icmp::Pinger>,
+    templates: pages::Templates,
+    plugins: plugins::Plugins,
+    default_ports: DefaultPorts,
+}
+
+impl AppStateBuilder {
+    pub fn mc_monitor_executable(mut self, path: impl Into<String>) -> Self {
+        self.mc_monitor_executable = path.into();
+        self
+    }
+
+    pub const fn use_mc_monitor(mut self, use_mc_monitor: bool) -> Self {
+        self.use_mc_monitor = use_mc_monitor;
+        self
+    }
+
+    /// How long `mc-monitor` may run before it is killed.
+    pub const fn mc_monitor_timeout(mut self, timeout: Duration) -> Self {
+        self.mc_monitor_timeout = timeout;
+        self
+    }
+
+    pub const fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    /// How long server icons are kept, which is separate from statuses as they rarely change.
+    pub const fn icon_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.icon_cache_ttl = ttl;
+        self
+    }
+
+    /// How old a status may be to still be served, marked as stale, when fetching a new one
+    /// fails. Zero serves the error instead.
+    pub const fn max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = max_staleness;
+        self
+    }
+
+    /// Refreshes entries requested at least `threshold` times within one cache lifetime `lead`
+    /// before they expire. A threshold of 0 disables this, `lead` has to be longer than zero and
+    /// shorter than the cache TTL.
+    pub fn hot_refresh(mut self, threshold: u32, lead: Duration) -> Self {
+        self.hot_refresh = (threshold > 0).then(|| HotRefresh {
+            threshold,
+            lead,
+            request_counts: Arc::default(),
+        });
+        self
+    }
+
+    /// Proxies whose `Forwarded` and `X-Forwarded-For` headers are believed.
+    pub fn trusted_proxies(mut self, proxies: impl IntoIterator<Item = IpNet>) -> Self {
+        self.trusted_proxies = proxies.into_iter().collect();
+        self
+    }
+
+    /// Only serves the admin routes and packet dumps to clients in `networks`, whether they are
+    /// authenticated or not.
+    pub fn admin_allowed_ips(mut self, networks: impl IntoIterator<Item = IpNet>) -> Self {
+        self.admin_allowed_ips = networks.into_iter().collect();
+        self
+    }
+
+    /// Bearer token for what only operators should see, like `?debug=true`, which is disabled
+    /// without one.
+    pub fn admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(admin::Token::new(token));
+        self
+    }
+
+    /// Signs every response with an HMAC of its body under `key`, so whoever embeds them can tell
+    /// they weren't tampered with on the way.
+    pub fn signing_key(mut self, key: impl Into<Vec<u8>>) -> Self {
+        self.signing_key = Some(signing::Key::new(key));
+        self
+    }
+
+    /// Requires a JWT that `validator` accepts, with the `status:read` scope, on the routes it
+    /// protects. Tokens with the `admin` scope also get what the admin token does.
+    pub fn jwt(mut self, validator: jwt::Validator) -> Self {
+        self.jwt = Some(validator);
+        self
+    }
+
+    /// Lets operators log in with `provider` under `/admin/login` to get admin access.
+    pub fn oidc(mut self, provider: oidc::Provider) -> Self {
+        self.oidc = Some(provider);
+        self
+    }
+
+    /// What would otherwise be read from the config file.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Leaves the resolved addresses out of statuses served by alias, and out of their errors.
+    pub const fn hide_ips(mut self, hide_ips: bool) -> Self {
+        self.hide_ips = hide_ips;
+        self
+    }
+
+    /// How long any request may take.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts = RouteTimeouts::new(timeout);
+        self
+    }
+
+    /// How many status requests are worked on at once, and how many more may wait for a turn.
+    pub fn load_shed(
+        mut self,
+        max_concurrent: usize,
+        queue_length: usize,
+        retry_after: Duration,
+    ) -> Self {
+        self.load_shed = LoadShed::new(max_concurrent, queue_length, retry_after);
+        self
+    }
+
+    /// Port Geyser is looked for on, 19132 by default, and whether to ping it rather than only
+    /// looking for Geyser in the MOTD and version.
+    pub const fn geyser(mut self, port: u16, ping: bool) -> Self {
+        self.geyser = geyser::Detection::new(port, ping);
+        self
+    }
+
+    /// Local address the native backends send from, for multi-homed hosts.
+    pub const fn source_address(mut self, source: IpAddr) -> Self {
+        self.outbound.source = Some(source);
+        self
+    }
+
+    /// Network interface the native backends send through, only supported on Linux.
+    pub fn source_interface(mut self, interface: impl Into<String>) -> Self {
+        self.outbound.interface = Some(interface.into());
+        self
+    }
+
+    /// SOCKS5 proxy the native backend tunnels Java pings through, unless a configured server has
+    /// a proxy of its own.
+    pub fn socks5_proxy(mut self, proxy: Socks5Proxy) -> Self {
+        self.outbound.socks5_proxy = Some(proxy);
+        self
+    }
+
+    /// How long the native backend may take to connect to a Java server, unless a configured
+    /// server has a timeout of its own. Unset, only the backend's timeout bounds it.
+    pub const fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.outbound.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// How long a Java server may take to answer the native backend's status request once
+    /// connected, unless a configured server has a timeout of its own.
+    pub const fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.outbound.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Shortest time between two fetches of the same server, however many clients ask for it.
+    /// Zero removes the cap.
+    pub fn min_fetch_interval(mut self, interval: Duration) -> Self {
+        self.rate_cap = RateCap::new(interval);
+        self
+    }
+
+    /// Stops fetching a server for `cooldown` once `threshold` fetches of it failed in a row,
+    /// answering with how the last one went instead. A threshold of zero never stops fetching.
+    pub fn circuit_breaker(mut self, threshold: u32, cooldown: Duration) -> Self {
+        self.circuit_breaker = circuit::Breaker::new(threshold, cooldown);
+        self
+    }
+
+    /// Adds the country from a `GeoLite2` Country or City database, and the ASN from a `GeoLite2`
+    /// ASN database, to every status and to the labels of polled servers' metrics.
+    ///
+    /// # Panics
+    ///
+    /// If a database can't be opened.
+    pub fn geoip_databases(
+        mut self,
+        country: Option<&std::path::Path>,
+        asn: Option<&std::path::Path>,
+    ) -> Self {
+        self.geoip = Some(
+            GeoIp::open(country, asn)
+                .unwrap_or_else(|e| panic!("Failed opening the GeoIP databases: {e}")),
+        );
+        self
+    }
+
+    /// Port of servers asked for without one, 25565 by default.
+    pub const fn default_port(mut self, port: u16) -> Self {
+        self.default_ports.java = port;
+        self
+    }
+
+    /// Port of configured Bedrock servers asked for without one, and of the Bedrock ping of
+    /// `/any/:url`, 19132 by default.
+    pub const fn bedrock_default_port(mut self, port: u16) -> Self {
+        self.default_ports.bedrock = port;
+        self
+    }
+
+    /// Renders the HTML pages from the templates in `dir`, falling back to the embedded ones for
+    /// those it doesn't have.
+    ///
+    /// # Panics
+    ///
+    /// If `dir` is not a directory, or one of its templates doesn't parse.
+    pub fn template_dir(mut self, dir: &std::path::Path) -> Self {
+        self.templates = pages::Templates::load(Some(dir))
+            .unwrap_or_else(|e| panic!("Failed loading the templates: {e}"));
+        self
+    }
+
+    /// Loads the WASM plugins in `dir`, see the `plugins` feature.
+    ///
+    /// # Panics
+    ///
+    /// If `dir` can't be read, or one of its plugins doesn't compile.
+    pub fn plugin_dir(mut self, dir: &std::path::Path) -> Self {
+        self.plugins = plugins::Plugins::load(Some(dir))
+            .unwrap_or_else(|e| panic!("Failed loading the plugins: {e}"));
+        self
+    }
+
+    /// Shares statuses and fetch claims with other replicas through the Redis at `url`, with every
+    /// key starting with `prefix`.
+    ///
+    /// # Errors
+    ///
+    /// If `url` is not a valid Redis URL.
+    pub fn redis(mut self, url: &str, prefix: impl Into<String>) -> redis::RedisResult<Self> {
+        self.shared_cache = Some(shared_cache::SharedCache::new(url, prefix.into())?);
+        Ok(self)
+    }
+
+    /// Keeps fetched statuses in `cache` instead of in memory.
+    pub fn status_cache(mut self, cache: impl StatusCache + 'static) -> Self {
+        self.status_cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Sends an ICMP echo to the server along with every fetch, and adds its round trip to the
+    /// status. Has to be called from within a Tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// If no ICMP socket could be opened, which needs `CAP_NET_RAW`.
+    pub fn icmp_ping(mut self) -> std::io::Result<Self> {
+        self.icmp = Some(icmp::Pinger::new()?);
+        Ok(self)
+    }
+
+    /// # Panics
+    ///
+    /// If hot refreshing is enabled with a lead of zero or one that isn't shorter than the cache
+    /// TTL, or the history file or `StatsD` address from the config can't be used.
+    pub fn build(self) -> AppState {
+        self.build_reporting(|e| panic!("Invalid config: {e}"))
+    }
+
+    /// Builds the state, passing what from the config can't be used to `invalid` and going on
+    /// without it.
+    fn build_reporting(self, mut invalid: impl FnMut(String)) -> AppState {
+        if let Some(hot_refresh) = &self.hot_refresh {
+            assert!(
+                !hot_refresh.lead.is_zero(),
+                "The hot refresh lead must be longer than zero"
+            );
+            assert!(
+                hot_refresh.lead < self.cache_ttl,
+                "The hot refresh lead must be shorter than the cache TTL"
+            );
+        }
+        let config = Arc::new(self.config);
+        let overrides = Arc::new(Overrides::new(
+            config.clone(),
+            self.default_ports,
+            resolver(&config),
+        ));
+        let history = History::load(config.history_file.clone()).unwrap_or_else(|e| {
+            invalid(e);
+            History::in_memory()
+        });
+        let statsd = config
+            .statsd
+            .as_ref()
+            .and_then(|statsd| statsd::Client::new(statsd).map_err(&mut invalid).ok());
+        let flapping = flapping::Tracker::new(config.flap_detection.clone());
+        let federation = config.aggregator.as_ref().map(|_| Arc::default());
+
+        AppState {
+            mc_monitor_executable: self.mc_monitor_executable.into(),
+            cache: self.status_cache.unwrap_or_else(|| {
+                Arc::new(MokaCache::new(
+                    100,
+                    overrides::Expiry {
+                        default: self.cache_ttl,
+                        overrides: overrides.clone(),
+                    },
+                ))
+            }),
+            use_mc_monitor: Arc::new(self.use_mc_monitor),
+            mc_monitor_timeout: self.mc_monitor_timeout,
+            cache_ttl: self.cache_ttl,
+            icons: CacheBuilder::new(100)
+                .time_to_live(self.icon_cache_ttl)
+                .build(),
+            icon_cache_ttl: self.icon_cache_ttl,
+            reachability: CacheBuilder::new(100)
+                .time_to_live(self.rate_cap.interval())
+                .build(),
+            last_fetched: (!self.max_staleness.is_zero()).then(|| {
+                CacheBuilder::new(1000)
+                    .time_to_live(self.max_staleness)
+                    .build()
+            }),
+            hot_refresh: self.hot_refresh,
+            trusted_proxies: self.trusted_proxies.into(),
+            admin_allowed_ips: self.admin_allowed_ips.into(),
+            basic_auth: self.basic_auth.map(Arc::new),
+            admin_token: self.admin_token.map(Arc::new),
+            signing_key: self.signing_key.map(Arc::new),
+            jwt: self.jwt.map(Arc::new),
+            oidc: self.oidc.map(Arc::new),
+            favicon: self.favicon,
+            config,
+            overrides,
+            hide_ips: self.hide_ips,
+            poll_results: broadcast::channel(64).0,
+            history,
+            graphql: graphql::schema(),
+            statsd,
+            counters: Arc::default(),
+            timeouts: Arc::new(self.timeouts),
+            load_shed: Arc::new(self.load_shed),
+            geyser: self.geyser,
+            outbound: Arc::new(self.outbound),
+            rate_cap: Arc::new(self.rate_cap),
+            federation,
+            circuit_breaker: Arc::new(self.circuit_breaker),
+            shared_cache: self.shared_cache.map(Arc::new),
+            geoip: self.geoip.map(Arc::new),
+            icmp: self.icmp.map(Arc::new),
+            flapping: Arc::new(flapping),
+            templates: Arc::new(self.templates),
+            plugins: Arc::new(self.plugins),
+        }
+    }
+}
+
+/// The response for a failed lookup, blaming the client for addresses that don't work.
+fn status_error(error: mcstatus_core::Error) -> (StatusCode, String) {
+    match error {
+        mcstatus_core::Error::InvalidAddress(e) => (StatusCode::BAD_REQUEST, e),
+        mcstatus_core::Error::Backend(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
+        mcstatus_core::Error::Timeout(e) => (StatusCode::GATEWAY_TIMEOUT, e),
+    }
+}
+
+#[derive(Deserialize)]
+struct StatusQuery {
+    /// Serve the status JSON the server sent instead of the parsed status.
+    #[serde(default)]
+    raw: bool,
+    /// Serve the status as plain text made from this template, see [`text::format`].
+    format: Option<String>,
+    /// Also ping the server natively and include a dump of the packets exchanged, for admins.
+    #[serde(default)]
+    debug: bool,
+    /// How the fields of the status are named, see [`Case`].
+    case: Option<Case>,
+}
+
+/// What `?debug=true` serves, the status along with how a fresh ping of the server went.
+#[derive(Serialize)]
+struct DebugResponse {
+    #[serde(flatten)]
+    status: ServerStatus,
+    debug: mcstatus_core::capture::Capture,
+}
+
+/// What `?raw=true` serves, the server's own JSON left untouched.
+#[derive(Serialize)]
+struct RawResponse {
+    latency_ms: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icmp_rtt_ms: Option<f64>,
+    status: Box<serde_json::value::RawValue>,
+}
+
+async fn get_status_for_server(
+    Path(addr): Path<String>,
+    Query(query): Query<StatusQuery>,
+    State(state): State<AppState>,
+    claims: Option<Extension<jwt::Claims>>,
+    request_headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    debug!(%addr, "Requested from api");
+
+    let name = addr;
+    let addr = state.resolve(&name).await.map_err(status_error)?;
+    if query.debug {
+        admin::authorize(&state, claims.as_ref().map(|c| &c.0), &request_headers)?;
+        let server = state.overrides.get(addr.address);
+        let (backend, timeout, outbound) = state.fetch_settings(server, None);
+        if backend.is_bedrock() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Packet dumps are only supported for Java edition servers".to_owned(),
+            ));
+        }
+        let status = state.cached_status(addr.clone()).await?;
+        let host = addr.domain_name.as_ref().map(|name| name.ascii.as_str());
+        let capture = slp::capture(&addr.address, host, timeout, &outbound).await;
+        return Ok(Json(DebugResponse {
+            status,
+            debug: capture,
+        })
+        .into_response());
+    }
+    let status = state.cached_status(addr).await?;
+    if query.raw {
+        let name = status.requested_url.to_string();
+        return raw_status(status, &name);
+    }
+    if let Some(format) = &query.format {
+        return formatted_status(status, &request_headers, format, &name);
+    }
+    let case = Case::pick(query.case, &state.config);
+    Ok(status_response(status, &request_headers, |status| {
+        state.status_json(status, case)
+    }))
+}
+
+/// `HEAD /:url`, for uptime checkers that only look at the status code: 200 while the server is
+/// online and 503 while it is not.
+async fn head_status_for_server(
+    Path(addr): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    debug!(%addr, "Requested without a body from api");
+
+    let addr = state.resolve(&addr).await.map_err(status_error)?;
+    let status = state.cached_status(addr).await?;
+    Ok(if status.output.is_some() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    })
+}
+
+/// A status served by alias, with the addresses left out if they are hidden.
+#[derive(Serialize)]
+struct AliasedStatus {
+    alias: String,
+    #[serde(flatten)]
+    status: serde_json::Value,
+}
+
+async fn get_status_for_alias(
+    Path(alias): Path<String>,
+    Query(query): Query<StatusQuery>,
+    State(state): State<AppState>,
+    request_headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    debug!(%alias, "Requested by alias from api");
+
+    let Some(server) = state
+        .config
+        .servers
+        .iter()
+        .find(|s| s.alias.as_deref() == Some(alias.as_str()))
+    else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("No server is called {alias}"),
+        ));
+    };
+    let hide_ips = state.hide_ips;
+    // Errors mention the server by whatever it was resolved from or to
+    let hide = |error: String, hidden: &[String]| {
+        if !hide_ips {
+            return error;
+        }
+        hidden
+            .iter()
+            .fold(error, |error, hidden| error.replace(hidden, &alias))
+    };
+    let configured = [server.address.clone()];
+    let addr = state
+        .resolve(&server.address)
+        .await
+        .map_err(status_error)
+        .map_err(|(code, e)| (code, hide(e, &configured)))?;
+    let resolved = [
+        server.address.clone(),
+        addr.address.to_string(),
+        addr.address.ip().to_string(),
+    ];
+    let mut status = state
+        .cached_status(addr)
+        .await
+        .map_err(|(code, e)| (code, hide(e, &resolved)))?;
+    if server.hide_address {
+        status.domain_name = None;
+        status.resolution = None;
+    }
+    status.error = status.error.map(|e| hide(e, &resolved));
+    if query.raw {
+        return raw_status(status, &alias);
+    }
+    if let Some(format) = &query.format {
+        let name = if hide_ips { &alias } else { &server.address };
+        return formatted_status(status, &request_headers, format, name);
+    }
+
+    let case = Case::pick(query.case, &state.config);
+    Ok(status_response(status, &request_headers, |status| {
+        let rules = state.transform_rules(status.requested_url);
+        let mut status = state
+            .plugins
+            .transform(&status)
+            .unwrap_or_else(|| serde_json::to_value(status).unwrap_or_default());
+        // Plugins see the whole status, but what they make of it still has the addresses hidden
+        if let Some(fields) = status.as_object_mut().filter(|_| hide_ips) {
+            fields.remove("requested_url");
+            if let Some(resolution) = fields.get_mut("resolution").and_then(|r| r.as_object_mut()) {
+                resolution.remove("address");
+            }
+        }
+        for rules in rules {
+            transform::apply(rules, &mut status);
+        }
+        case.apply(&mut status);
+        Json(AliasedStatus { alias, status })
+    }))
+}
+
+/// Serves `status` as `body` makes it, with its cache headers, or only those if the client has
+/// it already.
+fn status_response<B: IntoResponse>(
+    status: ServerStatus,
+    request_headers: &HeaderMap,
+    body: impl FnOnce(ServerStatus) -> B,
+) -> Response {
+    let hit = status.cache.as_ref().is_some_and(|c| c.hit);
+    let etag = etag(&status);
+    let headers = [
+        (
+            header::AGE,
+            status.fetched_at.elapsed().as_secs().to_string(),
+        ),
+        (
+            HeaderName::from_static("x-cache"),
+            if hit { "HIT" } else { "MISS" }.to_owned(),
+        ),
+        (header::ETAG, etag.clone()),
+    ];
+
+    if not_modified(request_headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+    (headers, body(status)).into_response()
+}
+
+/// Whether the client has what `etag` stands for already, as told by `If-None-Match`.
+fn not_modified(request_headers: &HeaderMap, etag: &str) -> bool {
+    request_headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+/// Serves `status` as the text `format` makes of it, as `?format=` asks for.
+fn formatted_status(
+    status: ServerStatus,
+    request_headers: &HeaderMap,
+    format: &str,
+    name: &str,
+) -> Result<Response, (StatusCode, String)> {
+    let text = text::format(format, &status, name).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(status_response(status, request_headers, |_| text))
+}
+
+/// Serves what the server sent, `name` is what it is called in errors.
+fn raw_status(status: ServerStatus, name: &str) -> Result<Response, (StatusCode, String)> {
+    let Some(raw) = status.raw else {
+        let reason = status
+            .error
+            .unwrap_or_else(|| "only the native backend keeps it".to_owned());
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("{name} has no raw status: {reason}"),
+        ));
+    };
+    let json = serde_json::value::RawValue::from_string(raw.json.to_string()).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Raw status was not JSON: {e}"),
+        )
+    })?;
+
+    let age = status.fetched_at.elapsed().as_secs().to_string();
+    let response = RawResponse {
+        latency_ms: raw.latency.as_secs_f64() * 1000.0,
+        icmp_rtt_ms: status.icmp_rtt_ms,
+        status: json,
+    };
+    Ok(([(header::AGE, age)], Json(response)).into_response())
+}
+
+/// A strong `ETag` over everything in `status` except the cache info, which changes with every
+/// request. Fetches that found the server unchanged get the same tag.
+fn etag(status: &ServerStatus) -> String {
+    let mut hasher = DefaultHasher::new();
+    let status = ServerStatus {
+        cache: None,
+        ..status.clone()
+    };
+    // Serializing a status can't fail, there are no maps with non-string keys in it
+    serde_json::to_vec(&status)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Resolves with the DNS-over-TLS servers of `config`, or else like the system does.
+fn resolver(config: &Config) -> Resolver {
+    if config.dns_over_tls.is_empty() {
+        return Resolver::system();
+    }
+    let servers = config
+        .dns_over_tls
+        .iter()
+        .map(|server| TlsNameServer {
+            address: server.address,
+            tls_name: server.tls_name.clone(),
+        })
+        .collect::<Vec<_>>();
+    Resolver::dns_over_tls(&servers)
+}
+
+/// Every route, with the middleware they are served through. To serve them under a prefix of
+/// another application, nest it there, as in `app.nest("/mc", router(state))`.
+#[allow(clippy::too_many_lines)] // One route after another
+pub fn router(state: AppState) -> Router {
+    let timeouts = state.timeouts.clone();
+    let with_timeout =
+        |route: &str| middleware::from_fn_with_state(timeouts.for_route(route), timeout::enforce);
+
+    let load_shed = state.load_shed.clone();
+    let with_load_shed = || middleware::from_fn_with_state(load_shed.clone(), load_shed::limit);
+
+    Router::new()
+        .route("/", get(index).layer(with_timeout("/")))
+        .route("/healthz", get(healthz).layer(with_timeout("/healthz")))
+        .route("/version", get(version).layer(with_timeout("/version")))
+        .route(
+            "/static/*path",
+            get(assets::handler).layer(with_timeout("/static/*path")),
+        )
+        .route(
+            "/favicon.ico",
+            get(favicon).layer(with_timeout("/favicon.ico")),
+        )
+        .route(
+            "/summary",
+            get(summary::handler)
+                .layer(with_load_shed())
+                .layer(with_timeout("/summary")),
+        )
+        .route(
+            "/group/:name",
+            get(summary::group_handler)
+                .layer(with_load_shed())
+                .layer(with_timeout("/group/:name")),
+        )
+        .route(
+            "/federation",
+            get(federation::handler).layer(with_timeout("/federation")),
+        )
+        .route(
+            "/federation/report",
+            post(federation::report).layer(with_timeout("/federation/report")),
+        )
+        .route(
+            "/admin/stats",
+            get(stats::handler).layer(with_timeout("/admin/stats")),
+        )
+        .route(
+            "/admin/login",
+            get(oidc::login).layer(with_timeout("/admin/login")),
+        )
+        .route(
+            "/admin/callback",
+            get(oidc::callback).layer(with_timeout("/admin/callback")),
+        )
+        .route(
+            "/admin/logout",
+            get(oidc::logout).layer(with_timeout("/admin/logout")),
+        )
+        .route(
+            "/graphql",
+            get(graphql::explorer)
+                .post(graphql::handler)
+                .layer(with_load_shed())
+                .layer(with_timeout("/graphql")),
+        )
+        .route(
+            "/any/:url",
+            get(any::handler)
+                .layer(with_load_shed())
+                .layer(with_timeout("/any/:url")),
+        )
+        .route(
+            "/:url/icon",
+            get(icon::handler)
+                .layer(with_load_shed())
+                .layer(with_timeout("/:url/icon")),
+        )
+        .route(
+            "/:url/reachable",
+            get(reachable::handler)
+                .layer(with_load_shed())
+                .layer(with_timeout("/:url/reachable")),
+        )
+        .route(
+            "/:url/players",
+            get(text::players)
+                .layer(with_load_shed())
+                .layer(with_timeout("/:url/players")),
+        )
+        .route(
+            "/:url/version",
+            get(text::version)
+                .layer(with_load_shed())
+                .layer(with_timeout("/:url/version")),
+        )
+        .route(
+            "/:url/motd",
+            get(text::motd)
+                .layer(with_load_shed())
+                .layer(with_timeout("/:url/motd")),
+        )
+        .route(
+            "/:url/online",
+            get(text::online)
+                .layer(with_load_shed())
+                .layer(with_timeout("/:url/online")),
+        )
+        .route(
+            "/:url/incidents",
+            get(history::incidents_handler).layer(with_timeout("/:url/incidents")),
+        )
+        // Waiting is the point, so neither the request timeout nor the concurrency limit apply
+        .route("/:url/wait", get(wait::handler))
+        .route(
+            "/:url/diff",
+            get(history::diff_handler).layer(with_timeout("/:url/diff")),
+        )
+        .route(
+            "/:url/uptime",
+            get(history::uptime_handler).layer(with_timeout("/:url/uptime")),
+        )
+        .route(
+            "/server/:alias",
+            get(get_status_for_alias)
+                .layer(with_load_shed())
+                .layer(with_timeout("/server/:alias")),
+        )
+        .route(
+            "/:url",
+            get(get_status_for_server)
+                .head(head_status_for_server)
+                .layer(with_load_shed())
+                .layer(with_timeout("/:url")),
+        )
+        .layer(middleware::from_fn_with_state(state.clone(), jwt::require))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            basic_auth::require,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.admin_allowed_ips.clone(),
+            admin::restrict,
+        ))
+        .layer(CatchPanicLayer::custom(handle_panic))
+        // Outside of the auth layers, so their rejections get CORS headers too
+        .layer(middleware::from_fn_with_state(
+            Arc::new(headers::Headers::new(&state.config)),
+            headers::add,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.signing_key.clone(),
+            signing::sign,
+        ))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(make_access_span)
+                .on_request(())
+                .on_response(log_access)
+                .on_failure(()),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.trusted_proxies.clone(),
+            client_ip::resolve,
+        ))
+        .with_state(state)
+}
+
+/// Starts the hot refresher, and the poller along with everything consuming its results when there
+/// are servers configured. Has to be called from within a Tokio runtime.
+pub fn spawn_background_tasks(state: &AppState) {
+    if let Some(hot_refresh) = state.hot_refresh.clone() {
+        tokio::spawn(refresh_hot_entries(state.clone(), hot_refresh));
+    }
+
+    if state.config.servers.is_empty() {
+        return;
+    }
+    let scripts = scripts::Scripts::start(&state.config);
+    let notifier = Notifier::new(&state.config, state.plugins.clone(), scripts.clone());
+    if !state.config.alerts.is_empty() {
+        tokio::spawn(alerts::run(
+            state.config.clone(),
+            notifier.clone(),
+            state.poll_results.subscribe(),
+        ));
+    }
+    if !state.config.notifiers.is_empty() || !scripts.is_empty() {
+        tokio::spawn(changes::run(notifier, state.poll_results.subscribe()));
+    }
+    if !scripts.is_empty() {
+        tokio::spawn(scripts::run(scripts, state.poll_results.subscribe()));
+    }
+    if let Some(statsd) = state.statsd.clone() {
+        tokio::spawn(statsd::run(
+            statsd,
+            metrics::Cardinality::new(&state.config),
+            state.poll_results.subscribe(),
+        ));
+    }
+    if state.config.graphite.is_some() {
+        tokio::spawn(graphite::run(
+            state.config.clone(),
+            state.poll_results.subscribe(),
+        ));
+    }
+    if state
+        .config
+        .servers
+        .iter()
+        .any(|s| s.heartbeat_url.is_some())
+    {
+        tokio::spawn(heartbeat::run(
+            state.config.clone(),
+            state.poll_results.subscribe(),
+        ));
+    }
+    if state.config.mqtt.is_some() {
+        tokio::spawn(mqtt::run(
+            state.config.clone(),
+            state.poll_results.subscribe(),
+        ));
+    }
+    if state.config.agent.is_some() {
+        tokio::spawn(federation::run_agent(
+            state.config.clone(),
+            state.poll_results.subscribe(),
+        ));
+    }
+    if let Some(aggregator) = state.federation.clone() {
+        tokio::spawn(federation::run_local(
+            state.config.clone(),
+            aggregator,
+            state.poll_results.subscribe(),
+        ));
+    }
+    tokio::spawn(history::run(
+        state.history.clone(),
+        state.poll_results.subscribe(),
+    ));
+    tokio::spawn(poller::run(state.clone()));
+}
+
+#[derive(Serialize)]
+struct PanicError {
+    error: &'static str,
+    message: Option<String>,
+}
+
+fn handle_panic(payload: Box<dyn Any + Send + 'static>) -> Response {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => Some(*message),
+        Err(payload) => payload.downcast_ref::<&str>().map(|s| (*s).to_owned()),
+    };
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(PanicError {
+            error: "Internal server error",
+            message,
+        }),
+    )
+        .into_response()
+}
+
+/// Target for access logs, so they can be enabled independently of the rest of the logging, e.g.
+/// `RUST_LOG=mcstatus_http=warn,mcstatus_http::access=info`.
+const ACCESS_LOG_TARGET: &str = "mcstatus_http::access";
+
+fn make_access_span(request: &Request<Body>) -> Span {
+    let span = info_span!(
+        target: ACCESS_LOG_TARGET,
+        "request",
+        method = %request.method(),
+        path = request.uri().path(),
+        client_ip = field::Empty,
+    );
+    if let Some(ClientIp(Some(ip))) = request.extensions().get::<ClientIp>() {
+        span.record("client_ip", field::display(ip));
+    }
+    span
+}
+
+fn log_access(response: &Response, latency: Duration, _span: &Span) {
+    let cache = response
+        .headers()
+        .get("x-cache")
+        .and_then(|v| v.to_str().ok());
+    info!(
+        target: ACCESS_LOG_TARGET,
+        status = response.status().as_u16(),
+        ?latency,
+        cache,
+        "Served request"
+    );
+}
+
+/// Periodically re-fetches cache entries that are close to expiring and were requested at least
+/// `threshold` times during their lifetime, so popular servers never have to wait on a fetch.
+async fn refresh_hot_entries(state: AppState, hot_refresh: HotRefresh) {
+    let mut interval = tokio::time::interval(hot_refresh.lead / 2);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+
+        let entries = state.cache.entries().await;
+        let cached = entries
+            .iter()
+            .map(|(addr, _)| *addr)
+            .collect::<HashSet<_>>();
+        let expiring = entries
+            .into_iter()
+            .filter(|(addr, status)| {
+                let ttl = state.overrides.cache_ttl(*addr, state.cache_ttl);
+                status.fetched_at.elapsed() >= ttl.saturating_sub(hot_refresh.lead)
+            })
+            .map(|(addr, _)| addr);
+
+        for addr in hot_refresh.take_hot(&cached, expiring) {
+            let state = state.clone();
+            let address = addr.address;
+            let url = address.to_string();
+            tokio::spawn(
+                async move {
+                    match state.fetch(&addr).await {
+                        Ok(status) => {
+                            debug!("Refreshed hot entry");
+                            state.cache.insert(address, status).await;
+                        }
+                        Err((_, e)) => warn!(%e, "Failed refreshing hot entry"),
+                    }
+                }
+                .instrument(debug_span!("hot_refresh", url)),
+            );
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RouteInfo {
+    method: &'static str,
+    path: &'static str,
+    description: &'static str,
+    example: Option<&'static str>,
+}
+
+/// Everything served by the router, as documented by the index page.
+const ROUTES: &[RouteInfo] = &[
+    RouteInfo {
+        method: "GET",
+        path: "/",
+        description: "This page",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/healthz",
+        description: "Liveness check, answers as long as the service is up",
+        example: Some("/healthz"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/version",
+        description: "Version, git commit, build time and enabled features of this build",
+        example: Some("/version"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/favicon.ico",
+        description: "Icon for the service's pages",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/static/*path",
+        description: "Stylesheets and scripts of the service's pages, embedded in the binary",
+        example: Some("/static/style.css"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/summary",
+        description: "Combined status of every server in the config file",
+        example: Some("/summary"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/group/:name",
+        description: "Combined status of the servers in a group from the config file",
+        example: Some("/group/survival"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/federation",
+        description: "On an aggregator, how every server fares from every region that reports \
+            it, as up, partial when only some regions reach it, or down",
+        example: Some("/federation"),
+    },
+    RouteInfo {
+        method: "POST",
+        path: "/federation/report",
+        description: "Where agents report their poll results to an aggregator, with their \
+            region's token as a bearer token",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/admin/stats",
+        description: "For admins, uptime, memory, Tokio tasks, cache hits and misses, fetches in \
+            flight and mc-monitor processes of this instance",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/admin/login",
+        description: "Logs in with the configured OpenID Connect provider for admin access, \
+            going back to `return_to` afterwards",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/admin/callback",
+        description: "Where the OpenID Connect provider sends operators back to after logging in",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/admin/logout",
+        description: "Ends the admin session",
+        example: None,
+    },
+    RouteInfo {
+        method: "POST",
+        path: "/graphql",
+        description: "GraphQL API over statuses, history and groups, GET opens the GraphiQL \
+            explorer",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url",
+        description: "Status of the Minecraft server at a host name or IP address, with an \
+            optional port that defaults to 25565 or MCSTATUS_DEFAULT_PORT. With the native \
+            backend, ?raw=true serves the status JSON as the server sent it. \
+            ?format={online}/{max} on {version} serves it as text instead, also with {motd}, \
+            {status} and {address}. ?debug=true, with the admin token as a bearer token or \
+            logged in through /admin/login, adds a dump of the packets exchanged in a fresh ping \
+            of the server",
+        example: Some("/mc.example.com:25565"),
+    },
+    RouteInfo {
+        method: "HEAD",
+        path: "/:url",
+        description: "Answers 200 while the server is online and 503 while it is offline, without \
+            a body, for uptime checkers",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/server/:alias",
+        description: "Status of a server from the config file by its alias, taking the same \
+            ?raw=true and ?format=",
+        example: Some("/server/survival"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/any/:url",
+        description: "Status of a server that is either Java or Bedrock edition, tagged with the \
+            one that answered. Without a port, Bedrock is pinged on 19132 or \
+            MCSTATUS_BEDROCK_DEFAULT_PORT",
+        example: Some("/any/mc.example.com"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url/icon",
+        description: "The server's icon from the native backend, as a PNG or scaled with \
+            ?size= and converted with ?format= to webp or jpeg",
+        example: Some("/mc.example.com/icon?size=128&format=webp"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url/reachable",
+        description: "Whether the server's address accepts TCP connections and how long \
+            connecting took, without asking for its status",
+        example: Some("/mc.example.com/reachable"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url/players",
+        description: "Players online and the most the server takes as bare text, like 12/100",
+        example: Some("/mc.example.com/players"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url/version",
+        description: "Version the server reports as bare text",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url/motd",
+        description: "MOTD as bare text, without colors or formatting",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url/online",
+        description: "true or false as bare text",
+        example: None,
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url/incidents",
+        description: "Times a server from the config file was offline, newest first",
+        example: Some("/mc.example.com/incidents"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url/wait",
+        description: "Waits for the server's status to change, up to a ?timeout= that defaults \
+            to 30 seconds, and serves it with whether it changed",
+        example: Some("/mc.example.com/wait?timeout=60s"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url/diff",
+        description: "What changed between the last two polls of a server from the config file: \
+            going up or down, player counts, MOTD and version",
+        example: Some("/mc.example.com/diff"),
+    },
+    RouteInfo {
+        method: "GET",
+        path: "/:url/uptime",
+        description: "Uptime percentage and mean time between failures of a server from the \
+            config file, over a window that defaults to 30 days",
+        example: Some("/mc.example.com/uptime?window=7d"),
+    },
+];
+
+#[derive(Serialize)]
+struct ServiceInfo {
+    name: &'static str,
+    version: &'static str,
+    routes: &'static [RouteInfo],
+}
+
+const SERVICE_INFO: ServiceInfo = ServiceInfo {
+    name: env!("CARGO_PKG_NAME"),
+    version: env!("CARGO_PKG_VERSION"),
+    routes: ROUTES,
+};
+
+/// Doesn't touch any Minecraft server, so it only fails when the service itself is unresponsive.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Embedded by the build script.
+#[derive(Serialize)]
+struct BuildInfo {
+    version: &'static str,
+    commit: &'static str,
+    /// RFC 3339, unset if the timestamp didn't fit.
+    built_at: Option<String>,
+    features: Vec<&'static str>,
+}
+
+async fn version() -> Json<BuildInfo> {
+    let built_at = env!("MCSTATUS_BUILD_TIMESTAMP")
+        .parse()
+        .ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|time| time.to_rfc3339());
+    Json(BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("MCSTATUS_GIT_COMMIT"),
+        built_at,
+        features: env!("MCSTATUS_FEATURES")
+            .split(',')
+            .filter(|f| !f.is_empty())
+            .collect(),
+    })
+}
+
+/// Describes the service, as HTML for browsers and JSON for everything else.
+async fn index(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let wants_html = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if !wants_html {
+        return Json(SERVICE_INFO).into_response();
+    }
+
+    state
+        .templates
+        .render("index.html", &SERVICE_INFO)
+        .into_response()
+}
+
+async fn favicon(State(state): State<AppState>) -> impl IntoResponse {
+    let Favicon { content_type, data } = state.favicon;
+    (
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        data,
+    )
+}
@@ -0,0 +1,203 @@
+//! Address parsing and serving for the listeners the HTTP server can be bound to.
+//!
+//! TCP listeners go through `axum::serve`, but that only accepts a `TcpListener`, so Unix domain
+//! sockets get their own accept loop built on hyper directly.
+
+use axum::Router;
+use color_eyre::{eyre::eyre, Report, Result};
+use std::{fmt, future::Future, net::SocketAddr, path::PathBuf, str::FromStr};
+use tokio::net::TcpListener;
+use tracing::info;
+
+/// A bound listener, ready to serve the router.
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+impl Listener {
+    pub async fn bind(addr: &ListenAddr, unix_socket_mode: Option<u32>) -> Result<Self> {
+        match addr {
+            ListenAddr::Tcp(addr) => {
+                let listener = TcpListener::bind(addr).await?;
+                info!(%addr, "Listening on tcp");
+                Ok(Self::Tcp(listener))
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => Ok(Self::Unix(unix::bind(path, unix_socket_mode)?)),
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) => {
+                _ = unix_socket_mode;
+                Err(eyre!("Unix sockets are only supported on Unix"))
+            }
+        }
+    }
+
+    /// Serves `app` until `signal` resolves, then waits for open connections to finish.
+    pub async fn serve(
+        self,
+        app: Router,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<()> {
+        match self {
+            Self::Tcp(listener) => axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .with_graceful_shutdown(signal)
+            .await
+            .map_err(Into::into),
+            #[cfg(unix)]
+            Self::Unix(listener) => unix::serve(listener, app, signal).await,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    /// Written as `unix:/path/to/socket`.
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(eyre!("Unix socket address {s} did not contain a path"));
+            }
+            return Ok(Self::Unix(path.into()));
+        }
+        s.parse()
+            .map(Self::Tcp)
+            .map_err(|e| eyre!("Listen address {s} was neither `unix:<path>` nor ip:port: {e}"))
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub mod unix {
+    use axum::Router;
+    use color_eyre::{eyre::bail, Result};
+    use hyper::body::Incoming;
+    use hyper_util::{
+        rt::{TokioExecutor, TokioIo},
+        server::conn::auto::Builder,
+        service::TowerToHyperService,
+    };
+    use std::{
+        fs,
+        future::Future,
+        io::ErrorKind,
+        os::unix::fs::{FileTypeExt, PermissionsExt},
+        path::Path,
+        pin::pin,
+        sync::Arc,
+    };
+    use tokio::{net::UnixListener, sync::watch};
+    use tracing::{debug, info};
+
+    /// Binds a Unix socket at `path`, replacing a stale socket left behind by a previous run, and
+    /// sets its permissions to `mode` if given.
+    pub fn bind(path: &Path, mode: Option<u32>) -> Result<UnixListener> {
+        match fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_socket() => fs::remove_file(path)?,
+            Ok(_) => bail!(
+                "{} already exists and is not a socket, refusing to replace it",
+                path.display()
+            ),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let listener = UnixListener::bind(path)?;
+        if let Some(mode) = mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+        info!(
+            path = %path.display(),
+            mode = mode.map(|m| format!("{m:o}")),
+            "Listening on unix socket"
+        );
+        Ok(listener)
+    }
+
+    /// Mirrors what `axum::serve(..).with_graceful_shutdown(..)` does for TCP.
+    pub async fn serve(
+        listener: UnixListener,
+        app: Router,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<()> {
+        let (signal_tx, signal_rx) = watch::channel(());
+        let signal_tx = Arc::new(signal_tx);
+        tokio::spawn(async move {
+            signal.await;
+            drop(signal_rx);
+        });
+
+        let (close_tx, close_rx) = watch::channel(());
+
+        loop {
+            let stream = tokio::select! {
+                conn = listener.accept() => match conn {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        debug!(%e, "Failed accepting unix socket connection");
+                        continue;
+                    }
+                },
+                () = signal_tx.closed() => break,
+            };
+
+            let service = TowerToHyperService::new(app.clone().into_service::<Incoming>());
+            let signal_tx = Arc::clone(&signal_tx);
+            let close_rx = close_rx.clone();
+
+            tokio::spawn(async move {
+                let builder = Builder::new(TokioExecutor::new());
+                let mut conn =
+                    pin!(builder.serve_connection_with_upgrades(TokioIo::new(stream), service));
+                let mut signal_closed = pin!(signal_tx.closed());
+                let mut shutting_down = false;
+
+                loop {
+                    tokio::select! {
+                        result = conn.as_mut() => {
+                            if let Err(e) = result {
+                                debug!(%e, "Failed serving unix socket connection");
+                            }
+                            break;
+                        }
+                        () = &mut signal_closed, if !shutting_down => {
+                            shutting_down = true;
+                            conn.as_mut().graceful_shutdown();
+                        }
+                    }
+                }
+
+                drop(close_rx);
+            });
+        }
+
+        drop(close_rx);
+        let path = listener.local_addr()?.as_pathname().map(Path::to_owned);
+        drop(listener);
+        close_tx.closed().await;
+
+        if let Some(path) = path {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
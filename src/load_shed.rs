@@ -0,0 +1,87 @@
+//! Bounds how many status requests are worked on at once, with a limited queue behind that.
+//! Anything arriving while the queue is full is turned away with a 503 straight away, so a traffic
+//! spike can't pile up an unbounded number of fetches.
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+use crate::env_vars::Loader;
+
+pub struct LoadShed {
+    /// Permits for requests being worked on.
+    running: Arc<Semaphore>,
+    /// Permits for requests either being worked on or waiting in the queue.
+    admitted: Arc<Semaphore>,
+    retry_after: Duration,
+}
+
+impl LoadShed {
+    pub fn from_env(vars: &mut Loader) -> Self {
+        const MAX_CONCURRENT_REQUESTS: &str = "MAX_CONCURRENT_REQUESTS";
+        const REQUEST_QUEUE_LENGTH: &str = "REQUEST_QUEUE_LENGTH";
+        const OVERLOAD_RETRY_AFTER: &str = "OVERLOAD_RETRY_AFTER";
+
+        let max_concurrent = vars.parse(MAX_CONCURRENT_REQUESTS, "64", |v| match v.parse() {
+            Ok(0) | Err(_) => Err("expected a number of at least 1".to_owned()),
+            Ok(n) => Ok(n),
+        });
+        let queue_length = vars.value(REQUEST_QUEUE_LENGTH, "256");
+        let retry_after = vars.duration(OVERLOAD_RETRY_AFTER, "5 seconds");
+        Self::new(max_concurrent, queue_length, retry_after)
+    }
+
+    pub fn new(max_concurrent: usize, queue_length: usize, retry_after: Duration) -> Self {
+        Self {
+            running: Arc::new(Semaphore::new(max_concurrent)),
+            admitted: Arc::new(Semaphore::new(max_concurrent + queue_length)),
+            retry_after,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct OverloadedError {
+    error: &'static str,
+    retry_after_seconds: u64,
+}
+
+pub async fn limit(
+    State(load_shed): State<Arc<LoadShed>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Ok(_admitted) = load_shed.admitted.clone().try_acquire_owned() else {
+        let retry_after_seconds = load_shed.retry_after.as_secs().max(1);
+        warn!(
+            path = request.uri().path(),
+            "Shedding request, queue is full"
+        );
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, retry_after_seconds.to_string())],
+            Json(OverloadedError {
+                error: "Service is overloaded",
+                retry_after_seconds,
+            }),
+        )
+            .into_response();
+    };
+
+    let _running = load_shed
+        .running
+        .clone()
+        .acquire_owned()
+        .await
+        .expect("Semaphore should never be closed");
+    next.run(request).await
+}
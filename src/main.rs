@@ -5,366 +5,165 @@
     clippy::unwrap_used
 )]
 
-use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    routing::get,
-    Json, Router,
+mod healthcheck;
+mod listener;
+mod query;
+mod systemd;
+
+use color_eyre::{eyre::bail, Result};
+use listener::{ListenAddr, Listener};
+use mcstatus_http::{dump, env_vars::Loader, grpc, AppState};
+use std::env;
+use tokio::{sync::watch, task::JoinSet};
+use tracing::{error, warn};
+use tracing_subscriber::{
+    fmt::writer::BoxMakeWriter, layer::SubscriberExt, util::SubscriberInitExt, Layer,
 };
-use color_eyre::{
-    eyre::{bail, ensure},
-    Result,
-};
-use moka::future::{Cache, CacheBuilder};
-use serde::Serialize;
-use std::{
-    env,
-    net::{SocketAddr, ToSocketAddrs},
-    sync::Arc,
-};
-use tokio::{net::TcpStream, process::Command};
-use tracing::{debug, debug_span, info, warn, Instrument};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
-#[derive(Clone, PartialEq, Eq, Hash)]
-struct ServerAddr {
-    domain_name: Option<String>,
-    address: SocketAddr,
-}
-
-#[derive(Clone)]
-struct AppState {
-    mc_monitor_executable: Arc<str>,
-    use_mc_monitor: Arc<bool>,
-    cache: Cache<ServerAddr, ServerStatus>,
-}
-
-impl AppState {
-    fn new() -> Self {
-        const MC_MONITOR_EXECUTABLE: &str = "MC_MONITOR_EXECUTABLE";
-        const CACHE_TTL: &str = "CACHE_TTL";
-        const USE_MC_MONITOR: &str = "USE_MC_MONITOR";
-
-        let mc_monitor_executable = env::var(MC_MONITOR_EXECUTABLE)
-            .unwrap_or_else(|_| "mc-monitor".to_owned())
-            .into();
-
-        let cache_ttl = env::var(CACHE_TTL).unwrap_or_else(|_| "10 seconds".to_owned());
-        let cache_ttl = parse_duration::parse(&cache_ttl)
-            .unwrap_or_else(|_| panic!("Expected string {cache_ttl} to be a duration"));
 
-        let use_mc_monitor = env::var(USE_MC_MONITOR)
-            .unwrap_or_else(|_| "true".to_owned())
-            .parse::<bool>()
-            .unwrap_or_else(|_| panic!("Failed parsing variable {USE_MC_MONITOR} into bool"))
-            .into();
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let subcommand = args.next();
 
-        info!(%mc_monitor_executable);
-        info!(%use_mc_monitor);
-        info!(?cache_ttl);
+    // Subcommands print their results on stdout, so the logs get out of the way of scripts
+    let log_writer = if subcommand.is_some() {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let registry = tracing_subscriber::registry().with(
+        tracing_subscriber::fmt::layer()
+            .with_writer(log_writer)
+            .with_filter(
+                tracing_subscriber::EnvFilter::try_from_default_env()
+                    .unwrap_or_else(|_| "mcstatus_http=debug,mcstatus_core=debug".into()),
+            ),
+    );
+    // Next to the logs rather than under their filter, it needs the runtime's own task spans
+    #[cfg(feature = "console")]
+    let registry = registry.with(console_subscriber::spawn());
+    registry.init();
+
+    // Panics are reported through tracing, so they end up next to the request they happened in.
+    // Tracing escapes control characters, so reports can't be colored.
+    let (panic_hook, eyre_hook) = color_eyre::config::HookBuilder::default()
+        .theme(color_eyre::config::Theme::new())
+        .into_hooks();
+    eyre_hook.install()?;
+    std::panic::set_hook(Box::new(move |info| {
+        error!("{}", panic_hook.panic_report(info));
+    }));
+
+    match subcommand.as_deref() {
+        None => {}
+        Some("healthcheck") => return healthcheck::run(args.next()).await,
+        Some("query") => return query::run(args.next()).await,
+        Some(other) => bail!("Unknown subcommand {other}, expected healthcheck or query"),
+    }
 
-        Self {
-            mc_monitor_executable,
-            cache: CacheBuilder::new(100).time_to_live(cache_ttl).build(),
-            use_mc_monitor,
+    let mut vars = Loader::with_env_file();
+    let state = AppState::from_env(&mut vars);
+    let grpc_addr = grpc::listen_addr_from_env(&mut vars);
+    let dump_path = dump::path_from_env(&mut vars);
+    let unix_socket_mode = unix_socket_mode_from_env(&mut vars);
+    let listen_addrs = listen_addrs_from_env(&mut vars);
+    vars.finish()?;
+
+    mcstatus_http::spawn_background_tasks(&state);
+    tokio::spawn(dump::run(state.clone(), dump_path));
+    let app = mcstatus_http::router(state.clone());
+    let mut listeners = systemd::activated_listeners()?;
+    if listeners.is_empty() {
+        for addr in listen_addrs {
+            listeners.push(Listener::bind(&addr, unix_socket_mode).await?);
         }
     }
-}
-
-#[derive(Debug, Clone, Serialize)]
-struct MonitorOutput {
-    version: String,
-    online_player_count: u16,
-    max_player_count: u16,
-    motd: String,
-}
 
-impl MonitorOutput {
-    fn parse(output: &str) -> Result<Self> {
-        let Some((_host, rest)) = output.split_once(" : ") else {
-            bail!("Command output was not separated by `:`: {output}");
-        };
-
-        let Some((version, rest)) = rest.split_once(' ') else {
-            bail!("Command output finished unexpectedly, expected ` `, found nothing: {rest}");
-        };
-
-        let Some((version_str, version)) = version.split_once('=') else {
-            bail!("Version did not contain `=`. Found: {rest}");
-        };
-        ensure!(
-            version_str == "version",
-            "Version string was invalid: {version_str}"
-        );
-        let version = version.to_owned();
-
-        let Some((online, rest)) = rest.split_once(' ') else {
-            bail!("Command output finished unexpectedly, expected ` `, found nothing: {rest}");
-        };
-
-        let Some((online_str, online_player_count)) = online.split_once('=') else {
-            bail!("Online player count did not contain `=`. Found: {rest}");
-        };
-        ensure!(
-            online_str == "online",
-            "Online string was invalid: {online_str}"
-        );
-        let online_player_count = match online_player_count.parse() {
-            Ok(c) => c,
-            Err(e) => bail!("Failed parsing player count {online_player_count}: {e}"),
-        };
-
-        let Some((max, rest)) = rest.split_once(' ') else {
-            bail!("Command output finished unexpectedly, expected ` `, found nothing: {rest}");
-        };
-
-        let Some((max_str, max_player_count)) = max.split_once('=') else {
-            bail!("Max player count did not contain `=`. Found: {rest}");
-        };
-        ensure!(max_str == "max", "Max string was invalid: {max_str}");
-        let max_player_count = match max_player_count.parse() {
-            Ok(c) => c,
-            Err(e) => bail!("Failed parsing player count {max_player_count}: {e}"),
-        };
-
-        let Some((motd_str, motd)) = rest.split_once('=') else {
-            bail!("motd did not contain `=`. Found: {rest}");
-        };
-        ensure!(motd_str == "motd", "motd string was invalid: {motd_str}");
-        // motd='Minecraft server'
-        let l = motd.len();
-        let motd = motd[1..(l - 1)].to_owned();
+    // Every listener needs its own shutdown future, so the signal is fanned out through the
+    // sender being dropped
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        drop(shutdown_tx);
+    });
 
-        Ok(Self {
-            version,
-            online_player_count,
-            max_player_count,
-            motd,
-        })
+    let mut servers = JoinSet::new();
+    for listener in listeners {
+        let mut shutdown_rx = shutdown_rx.clone();
+        servers.spawn(listener.serve(app.clone(), async move {
+            _ = shutdown_rx.changed().await;
+        }));
     }
-}
-
-#[derive(Debug, Clone, Serialize)]
-struct ServerStatus {
-    requested_url: SocketAddr,
-    exit_code: u8,
-    output: Option<MonitorOutput>,
-    error: Option<String>,
-}
-
-async fn fetch_status_with_mc_monitor(
-    url: &SocketAddr,
-    mc_monitor_executable: &str,
-) -> Result<ServerStatus, (StatusCode, String)> {
-    let child = Command::new(mc_monitor_executable)
-        .arg("status")
-        .args([
-            "-host",
-            &url.ip().to_string(),
-            "-port",
-            &url.port().to_string(),
-        ])
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to spawn mc-monitor: {e}"),
-            )
-        })?;
-    info!("Spawned mc_monitor");
-    let output = child.wait_with_output().await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed running mc_monitor: {e}"),
-        )
-    })?;
-    info!("mc_monitor exited");
-
-    let stderr = output.stderr;
-    let stderr = String::from_utf8(stderr.clone()).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("mc-monitor outputted {stderr:?} on stderr, which was not utf-8: {e}"),
-        )
-    })?;
-    let stderr = if stderr.is_empty() {
-        None
-    } else {
-        Some(stderr)
-    };
-
-    if stderr.is_some() {
-        info!("mc_monitor returned an error");
-    } else {
-        info!("mc_monitor return successfully");
+    if let Some(addr) = grpc_addr {
+        let mut shutdown_rx = shutdown_rx.clone();
+        servers.spawn(grpc::serve(state, addr, async move {
+            _ = shutdown_rx.changed().await;
+        }));
     }
+    systemd::notify_ready();
+    systemd::spawn_watchdog();
 
-    let stdout = output.stdout;
-    let stdout = String::from_utf8(stdout.clone()).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("mc-monitor outputted {stdout:?} on stdin, which was not utf-8: {e}"),
-        )
-    })?;
-
-    let exit_code = output
-        .status
-        .code()
-        .expect("mc-monitor should terminate normally")
-        .try_into()
-        .expect("Exit codes should fit into u8s");
+    while let Some(result) = servers.join_next().await {
+        result??;
+    }
 
-    let output = if stderr.is_none() {
-        let output = MonitorOutput::parse(&stdout).map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed parsing mc_monitor output: {e}"),
-            )
-        })?;
-        Some(output)
-    } else {
-        None
-    };
+    Ok(())
+}
 
-    Ok(ServerStatus {
-        requested_url: url.to_owned(),
-        exit_code,
-        output,
-        error: stderr,
+const LISTEN_ADDR: &str = "LISTEN_ADDR";
+const UNIX_SOCKET_MODE: &str = "UNIX_SOCKET_MODE";
+
+/// Comma separated list of addresses to serve on, each either `ip:port` or `unix:<path>`.
+fn listen_addrs_from_env(vars: &mut Loader) -> Vec<ListenAddr> {
+    vars.parse(LISTEN_ADDR, "0.0.0.0:3789", |addrs| {
+        let addrs = addrs
+            .split(',')
+            .map(str::trim)
+            .filter(|addr| !addr.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<ListenAddr>>>()
+            .map_err(|e| e.to_string())?;
+        if addrs.is_empty() {
+            return Err("did not contain any addresses".to_owned());
+        }
+        Ok(addrs)
     })
 }
 
-async fn fetch_status_from_server(
-    url: &SocketAddr,
-    use_mc_monitor: bool,
-    mc_monitor_executable: &str,
-) -> Result<ServerStatus, (StatusCode, String)> {
-    // FIXME: Make sure this url is actually valid
-    let url_str = format!("{ip}:{port}", ip = url.ip(), port = url.port());
-    if use_mc_monitor {
-        let span = debug_span!("mc_monitor_fetch", url = url_str);
-        fetch_status_with_mc_monitor(url, mc_monitor_executable)
-            .instrument(span)
-            .await
-    } else {
-        todo!()
-    }
+/// Permissions for Unix sockets as an octal mode like `660`, left to the umask when unset.
+fn unix_socket_mode_from_env(vars: &mut Loader) -> Option<u32> {
+    vars.parse_optional(UNIX_SOCKET_MODE, |mode| {
+        u32::from_str_radix(mode, 8).map_err(|e| format!("expected an octal mode: {e}"))
+    })
 }
 
-async fn get_status_for_server(
-    Path(addr): Path<String>,
-    State(state): State<AppState>,
-) -> Result<Json<ServerStatus>, (StatusCode, String)> {
-    debug!(%addr, "Requested from api");
-
-    let cache = state.cache.clone();
-    let mc_monitor_executable = state.mc_monitor_executable;
-    let domain_name = if addr.contains(|c| char::is_ascii_alphabetic(&c)) {
-        let s = if addr.split(':').count() == 1 {
-            addr.clone()
-        } else {
-            addr.split(':')
-                .next()
-                .ok_or((StatusCode::BAD_REQUEST, "Input url was empty".to_owned()))?
-                .to_owned()
-        };
-        Some(s)
-    } else {
-        None
+/// Resolves once the process is asked to stop, which is Ctrl-C everywhere and additionally
+/// SIGTERM on Unix, as that is what Docker and Kubernetes send.
+// `tokio::select!` expands to a `pub(crate)` enum, which trips this lint
+#[allow(clippy::redundant_pub_crate)]
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        _ = tokio::signal::ctrl_c().await;
     };
 
-    let addr = {
-        let addr = match addr.split(':').count() {
-            0 => unreachable!(),
-            1 => format!("{addr}:25565"),
-            2 => addr,
-            _ => {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    format!("Invalid address {addr} for server, had too many `:`"),
-                ))
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sig) => _ = sig.recv().await,
+            Err(e) => {
+                warn!(%e, "Failed installing SIGTERM handler");
+                std::future::pending::<()>().await;
             }
-        };
-        addr.to_socket_addrs()
-            .map_err(|e| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    format!("addr {addr} was invalid: {e}"),
-                )
-            })?
-            .next()
-            .ok_or_else(|| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    format!("{addr} was addr, no addr was there"),
-                )
-            })?
-    };
-
-    let addr = ServerAddr {
-        domain_name,
-        address: addr,
-    };
-    // This is spawned in a task so the fetch isn't killed if the request is stopped This makes it
-    // so repeated requests to the endpoint, while killing the previous request (like browser
-    // refreshes) don't hammer the mc server.
-    let handle = tokio::spawn(async move {
-        cache
-            .try_get_with_by_ref(&addr, async {
-                fetch_status_from_server(
-                    &addr.address,
-                    *state.use_mc_monitor,
-                    &mc_monitor_executable,
-                )
-                .await
-            })
-            .await
-            .map_err(|e| (*e).clone())
-    });
-    let status = handle.await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to join cache thread: {e}"),
-        )
-    })?;
-    let status = status?;
-
-    Ok(Json::from(status))
-}
-
-#[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "mcstatus_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-    color_eyre::install()?;
-
-    let state = AppState::new();
-
-    let quit_sig = async {
-        _ = tokio::signal::ctrl_c().await;
-        warn!("Initiating graceful shutdown");
+        }
     };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    let app = Router::new()
-        .route("/favicon.ico", get(favicon))
-        .route("/:url", get(get_status_for_server))
-        .with_state(state);
-    let addr: SocketAddr = "0.0.0.0:3789".parse().expect("This is a valid address");
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(quit_sig)
-        .await?;
-
-    Ok(())
-}
-
-async fn favicon(State(_): State<AppState>) -> StatusCode {
-    StatusCode::NOT_FOUND
+    tokio::select! {
+        () = ctrl_c => {},
+        () = terminate => {},
+    }
+    warn!("Initiating graceful shutdown");
+    systemd::notify_stopping();
 }
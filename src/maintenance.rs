@@ -0,0 +1,44 @@
+//! Maintenance windows from the config file. Servers in one still have their polls recorded in
+//! the history, but alerts don't fire for them, and their statuses say they are in maintenance.
+
+use chrono::{DateTime, Utc};
+
+use crate::config::{Config, MaintenanceWindow};
+
+/// Whether `server`, as written in the config file, is in a maintenance window at `now`.
+pub fn active(config: &Config, server: &str, now: DateTime<Utc>) -> bool {
+    config
+        .maintenance
+        .iter()
+        .any(|window| applies_to(config, window, server) && covers(window, now))
+}
+
+fn applies_to(config: &Config, window: &MaintenanceWindow, server: &str) -> bool {
+    if window.servers.is_none() && window.groups.is_none() {
+        return true;
+    }
+    window.servers.iter().flatten().any(|s| s == server)
+        || window
+            .groups
+            .iter()
+            .flatten()
+            .filter_map(|name| config.groups.get(name))
+            .any(|group| group.servers.iter().any(|s| s == server))
+}
+
+fn covers(window: &MaintenanceWindow, now: DateTime<Utc>) -> bool {
+    match (&window.schedule, window.duration, window.start, window.end) {
+        (Some(schedule), Some(duration), _, _) => {
+            // The window is on if it started no longer than its duration ago
+            let Ok(duration) = chrono::Duration::from_std(duration) else {
+                return false;
+            };
+            schedule
+                .after(&(now - duration))
+                .next()
+                .is_some_and(|start| start <= now)
+        }
+        (_, _, Some(start), Some(end)) => start <= now && now < end,
+        _ => false,
+    }
+}
@@ -0,0 +1,90 @@
+//! What the `StatsD` and Graphite exports share: limits on which servers and labels per server
+//! metrics are named by, so public deployments polling many servers, or servers reported by many
+//! agents, don't make a new series for every one of them, see [`config::MetricLabels`], and how
+//! those make it into metric names.
+
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Mutex,
+};
+
+use crate::config::{self, Config};
+
+/// What servers that aren't exported by name are exported as, together.
+pub const OTHER: &str = "other";
+
+pub struct Cardinality {
+    servers: Option<HashSet<String>>,
+    max_servers: Option<usize>,
+    labels: Option<HashSet<String>>,
+    hash: bool,
+    /// Servers exported by name so far, for [`config::MetricLabels::max_servers`].
+    seen: Mutex<HashSet<String>>,
+}
+
+impl Cardinality {
+    pub fn new(config: &Config) -> Self {
+        let config::MetricLabels {
+            servers,
+            max_servers,
+            labels,
+            hash,
+        } = &config.metric_labels;
+        Self {
+            servers: servers.as_ref().map(|s| s.iter().cloned().collect()),
+            max_servers: *max_servers,
+            labels: labels.as_ref().map(|l| l.iter().cloned().collect()),
+            hash: *hash,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// What the metrics of `server` are named by.
+    pub fn server(&self, server: &str) -> String {
+        if self.servers.as_ref().is_some_and(|s| !s.contains(server)) {
+            return OTHER.to_owned();
+        }
+        if let Some(max) = self.max_servers {
+            let mut seen = self
+                .seen
+                .lock()
+                .expect("Seen servers lock should not be poisoned");
+            if !seen.contains(server) {
+                if seen.len() >= max {
+                    return OTHER.to_owned();
+                }
+                seen.insert(server.to_owned());
+            }
+        }
+        if self.hash {
+            // Short enough to read, long enough that servers don't collide
+            return hex::encode(&Sha256::digest(server.as_bytes())[..6]);
+        }
+        server.to_owned()
+    }
+
+    /// The labels of `labels` that metrics are tagged with.
+    pub fn labels(&self, labels: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        labels
+            .iter()
+            .filter(|(key, _)| self.labels.as_ref().map_or(true, |l| l.contains(*key)))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+}
+
+/// Metric names are made of `.` separated parts in both `StatsD` and Graphite, so those, and the
+/// characters either uses as separators, are replaced in addresses like `mc.example.com:25565`.
+pub fn name_component(address: &str) -> String {
+    address
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
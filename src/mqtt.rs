@@ -0,0 +1,182 @@
+//! Publishing of poll results to an MQTT broker, retained under `<topic_prefix>/<server>/state`,
+//! along with Home Assistant discovery configs so every polled server shows up as a device with
+//! sensors for being online, its player count and the latency of the poll.
+
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::{config::Config, poller::PollResult};
+
+/// How long to wait before reconnecting after the connection to the broker failed.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+pub async fn run(config: Arc<Config>, mut poll_results: broadcast::Receiver<Arc<PollResult>>) {
+    let Some(mqtt) = &config.mqtt else {
+        return;
+    };
+    let availability_topic = format!("{}/status", mqtt.topic_prefix);
+
+    let mut options = MqttOptions::new("mcstatus-http", &mqtt.host, mqtt.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    // Marks every sensor as unavailable in Home Assistant when the service goes away
+    options.set_last_will(LastWill::new(
+        &availability_topic,
+        "offline",
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let (Some(username), Some(password)) = (&mqtt.username, &mqtt.password) {
+        options.set_credentials(username, password);
+    }
+    info!(host = mqtt.host, port = mqtt.port, "Publishing to MQTT");
+
+    let (client, event_loop) = AsyncClient::new(options, 64);
+    tokio::spawn(drive(config.clone(), client.clone(), event_loop));
+
+    loop {
+        let result = match poll_results.recv().await {
+            Ok(result) => result,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "MQTT publishing fell behind, skipped poll results");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let status = result.status.as_ref().ok();
+        let output = status.and_then(|s| s.output.as_ref());
+        let timings = status.and_then(|s| s.timings.as_ref());
+        let state = json!({
+            "online": output.is_some(),
+            "players_online": output.map(|o| o.online_player_count),
+            "max_players": output.map(|o| o.max_player_count),
+            "version": output.map(|o| &o.version),
+            "motd": output.map(|o| &o.motd),
+            "latency_ms": result.latency.as_secs_f64() * 1000.0,
+            "icmp_rtt_ms": status.and_then(|s| s.icmp_rtt_ms),
+            "connect_ms": timings.map(|t| t.connect_ms),
+            "exchange_ms": timings.map(|t| t.exchange_ms),
+            "labels": result.labels,
+        });
+        let topic = format!("{}/{}/state", mqtt.topic_prefix, object_id(&result.server));
+        if let Err(e) = client
+            .publish(topic, QoS::AtLeastOnce, true, state.to_string())
+            .await
+        {
+            warn!(%e, "Failed queueing MQTT state");
+        }
+    }
+}
+
+/// Runs the connection to the broker, which also reconnects when it is lost. Discovery configs
+/// are (re)published on every connect, so a broker that lost its retained messages gets them back.
+async fn drive(config: Arc<Config>, client: AsyncClient, mut event_loop: EventLoop) {
+    loop {
+        match event_loop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                debug!("Connected to MQTT broker");
+                // Publishing waits for room in the queue, which is only emptied by polling the
+                // event loop here
+                tokio::spawn(announce(config.clone(), client.clone()));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!(%e, "MQTT connection failed, reconnecting");
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+async fn announce(config: Arc<Config>, client: AsyncClient) {
+    let Some(mqtt) = &config.mqtt else {
+        return;
+    };
+    let availability_topic = format!("{}/status", mqtt.topic_prefix);
+    let mut messages = vec![(availability_topic.clone(), "online".to_owned())];
+
+    if mqtt.home_assistant_discovery {
+        for server in &config.servers {
+            let id = object_id(&server.address);
+            let state_topic = format!("{}/{id}/state", mqtt.topic_prefix);
+            let device = json!({
+                "identifiers": [format!("mcstatus_{id}")],
+                "name": server.address,
+                "manufacturer": "mcstatus-http",
+                "model": "Minecraft server",
+            });
+            let sensors = [
+                (
+                    "binary_sensor",
+                    "online",
+                    json!({
+                        "name": "Online",
+                        "device_class": "connectivity",
+                        "value_template": "{{ 'ON' if value_json.online else 'OFF' }}",
+                    }),
+                ),
+                (
+                    "sensor",
+                    "players",
+                    json!({
+                        "name": "Players",
+                        "icon": "mdi:account-multiple",
+                        "unit_of_measurement": "players",
+                        "state_class": "measurement",
+                        "value_template": "{{ value_json.players_online }}",
+                    }),
+                ),
+                (
+                    "sensor",
+                    "latency",
+                    json!({
+                        "name": "Latency",
+                        "device_class": "duration",
+                        "unit_of_measurement": "ms",
+                        "state_class": "measurement",
+                        "value_template": "{{ value_json.latency_ms | round(1) }}",
+                    }),
+                ),
+            ];
+
+            for (component, sensor, mut payload) in sensors {
+                payload["unique_id"] = json!(format!("mcstatus_{id}_{sensor}"));
+                payload["object_id"] = json!(format!("mcstatus_{id}_{sensor}"));
+                payload["state_topic"] = json!(state_topic);
+                payload["availability_topic"] = json!(availability_topic);
+                payload["device"] = device.clone();
+                messages.push((
+                    format!(
+                        "{}/{component}/mcstatus_{id}/{sensor}/config",
+                        mqtt.discovery_prefix
+                    ),
+                    payload.to_string(),
+                ));
+            }
+        }
+    }
+
+    for (topic, payload) in messages {
+        if let Err(e) = client.publish(topic, QoS::AtLeastOnce, true, payload).await {
+            warn!(%e, "Failed queueing MQTT announcement");
+        }
+    }
+}
+
+/// Turns an address into something usable in topics and Home Assistant ids, which only allow
+/// letters, digits, `_` and `-`.
+fn object_id(address: &str) -> String {
+    address
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,284 @@
+//! Delivery of notifications about servers, like alerts, to the channels in the config file.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    config::{self, Config},
+    plugins::Plugins,
+    scripts::Scripts,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    AlertFired,
+    AlertResolved,
+    MotdChanged,
+    VersionChanged,
+    FlappingStarted,
+    FlappingStopped,
+}
+
+impl EventKind {
+    const fn description(self) -> &'static str {
+        match self {
+            Self::AlertFired => "Alert fired",
+            Self::AlertResolved => "Alert resolved",
+            Self::MotdChanged => "MOTD changed",
+            Self::VersionChanged => "Version changed",
+            Self::FlappingStarted => "Flapping started",
+            Self::FlappingStopped => "Flapping stopped",
+        }
+    }
+
+    /// Emoji shortcode ntfy shows next to the title.
+    const fn ntfy_tag(self) -> &'static str {
+        match self {
+            Self::AlertFired => "rotating_light",
+            Self::AlertResolved => "white_check_mark",
+            Self::MotdChanged | Self::VersionChanged => "pencil2",
+            Self::FlappingStarted => "warning",
+            Self::FlappingStopped => "ok",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Notification {
+    pub event: EventKind,
+    /// Address of the server, as written in the config file.
+    pub server: String,
+    pub title: String,
+    pub message: String,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+}
+
+impl Notification {
+    pub fn new(event: EventKind, server: String, title: String, message: String) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self {
+            event,
+            server,
+            title,
+            message,
+            timestamp,
+        }
+    }
+}
+
+struct Channel {
+    target: config::Channel,
+    events: Option<Vec<EventKind>>,
+    /// The servers from the notifier and the members of its groups, every server when unset.
+    servers: Option<HashSet<String>>,
+}
+
+impl Channel {
+    fn new(config: &config::Notifier, groups: &HashMap<String, config::Group>) -> Self {
+        let servers = (config.servers.is_some() || config.groups.is_some()).then(|| {
+            let mut servers = config
+                .servers
+                .iter()
+                .flatten()
+                .cloned()
+                .collect::<HashSet<_>>();
+            for name in config.groups.iter().flatten() {
+                if let Some(group) = groups.get(name) {
+                    servers.extend(group.servers.iter().cloned());
+                } else {
+                    warn!(group = name, "Notifier has a group that doesn't exist");
+                }
+            }
+            servers
+        });
+        Self {
+            target: config.channel.clone(),
+            events: config.events.clone(),
+            servers,
+        }
+    }
+
+    fn wants(&self, notification: &Notification) -> bool {
+        self.events
+            .as_ref()
+            .map_or(true, |events| events.contains(&notification.event))
+            && self
+                .servers
+                .as_ref()
+                .map_or(true, |servers| servers.contains(&notification.server))
+    }
+}
+
+#[derive(Clone)]
+pub struct Notifier {
+    client: reqwest::Client,
+    channels: Arc<[Channel]>,
+    plugins: Arc<Plugins>,
+    scripts: Scripts,
+}
+
+impl Notifier {
+    pub fn new(config: &Config, plugins: Arc<Plugins>, scripts: Scripts) -> Self {
+        let channels = config
+            .notifiers
+            .iter()
+            .map(|notifier| Channel::new(notifier, &config.groups))
+            .collect::<Arc<[_]>>();
+        for channel in channels.iter() {
+            if let config::Channel::Plugin { plugin } = &channel.target {
+                if !plugins.has(plugin) {
+                    warn!(plugin, "Notifier has a plugin that isn't loaded");
+                }
+            }
+        }
+        Self {
+            client: reqwest::Client::new(),
+            channels,
+            plugins,
+            scripts,
+        }
+    }
+
+    /// Delivers `notification` to every channel in the background.
+    pub fn send(&self, notification: Notification) {
+        info!(
+            event = ?notification.event,
+            server = notification.server,
+            message = notification.message,
+            "Sending notification"
+        );
+        let notification = Arc::new(notification);
+        self.scripts.notify(&notification);
+        for i in 0..self.channels.len() {
+            if !self.channels[i].wants(&notification) {
+                continue;
+            }
+            let notifier = self.clone();
+            let notification = notification.clone();
+            tokio::spawn(async move {
+                if let Err(e) = notifier.deliver(&notifier.channels[i], &notification).await {
+                    warn!(%e, "Failed delivering notification");
+                }
+            });
+        }
+    }
+
+    async fn deliver(
+        &self,
+        channel: &Channel,
+        notification: &Notification,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match &channel.target {
+            config::Channel::Webhook { url } => {
+                self.client
+                    .post(url)
+                    .json(notification)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                debug!(url, "Delivered webhook notification");
+            }
+            config::Channel::Ntfy {
+                url,
+                priority,
+                token,
+            } => {
+                let mut request = self
+                    .client
+                    .post(url)
+                    .header("Title", &notification.title)
+                    .header("Priority", priority.as_str())
+                    .header("Tags", notification.event.ntfy_tag())
+                    .body(notification.message.clone());
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                request.send().await?.error_for_status()?;
+                debug!(url, "Delivered ntfy notification");
+            }
+            config::Channel::Slack { url, icon_base_url } => {
+                self.client
+                    .post(url)
+                    .json(&slack_message(notification, icon_base_url.as_deref()))
+                    .send()
+                    .await?
+                    .error_for_status()?;
+                debug!("Delivered Slack notification");
+            }
+            config::Channel::Plugin { plugin } => {
+                self.plugins.notify(plugin, notification)?;
+                debug!(plugin, "Delivered plugin notification");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A Block Kit message, with the plain title as the fallback for notifications that can't show
+/// blocks.
+fn slack_message(notification: &Notification, icon_base_url: Option<&str>) -> serde_json::Value {
+    let mut section = json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": escape(&notification.message) },
+    });
+    if let Some(base) = icon_base_url {
+        section["accessory"] = json!({
+            "type": "image",
+            "image_url": format!("{}/{}/icon", base.trim_end_matches('/'), notification.server),
+            "alt_text": format!("Icon of {}", notification.server),
+        });
+    }
+
+    json!({
+        "text": notification.title,
+        "blocks": [
+            {
+                "type": "header",
+                "text": { "type": "plain_text", "text": notification.title },
+            },
+            section,
+            {
+                "type": "section",
+                "fields": [
+                    {
+                        "type": "mrkdwn",
+                        "text": format!("*Server*\n{}", escape(&notification.server)),
+                    },
+                    {
+                        "type": "mrkdwn",
+                        "text": format!("*Event*\n{}", notification.event.description()),
+                    },
+                ],
+            },
+            {
+                "type": "context",
+                "elements": [{
+                    "type": "mrkdwn",
+                    // Shown in the reader's time zone
+                    "text": format!(
+                        "<!date^{0}^{{date_short_pretty}} at {{time_secs}}|{0}>",
+                        notification.timestamp
+                    ),
+                }],
+            },
+        ],
+    })
+}
+
+/// Slack reads `<...>` in mrkdwn as links and mentions, which errors in messages shouldn't become.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
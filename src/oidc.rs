@@ -0,0 +1,408 @@
+//! Admin access through an OIDC login, so operators sign in with their SSO instead of sharing the
+//! admin token. `/admin/login` sends them to the provider at `MCSTATUS_OIDC_ISSUER`, which sends
+//! them back to `/admin/callback`, where they get a session cookie that
+//! [`admin::authorize`](crate::admin::authorize) lets through like the admin token.
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+use tokio::sync::OnceCell;
+use tracing::{debug, info};
+
+use crate::{env_vars::Loader, jwt, AppState};
+
+const COOKIE: &str = "mcstatus_admin";
+/// How long the provider may take to send the operator back.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Provider {
+    issuer: String,
+    client_id: String,
+    client_secret: String,
+    /// Where the provider sends operators back to, the public URL of `/admin/callback`.
+    redirect_url: String,
+    /// Subjects or emails that may log in, anyone the provider lets through may when empty.
+    admins: Vec<String>,
+    session_ttl: Duration,
+    client: reqwest::Client,
+    discovery: OnceCell<Discovery>,
+    /// Logins that were sent to the provider, keyed by their `state`.
+    pending: Mutex<HashMap<String, Pending>>,
+    /// Keyed by the ID in the cookie.
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+/// What the provider publishes under `/.well-known/openid-configuration`.
+#[derive(Deserialize)]
+struct Metadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct Discovery {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    id_tokens: jwt::Validator,
+}
+
+struct Pending {
+    nonce: String,
+    return_to: String,
+    started_at: Instant,
+}
+
+struct Session {
+    subject: String,
+    expires_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct IdClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+}
+
+impl Provider {
+    /// Reads the provider from `MCSTATUS_OIDC_ISSUER`, `MCSTATUS_OIDC_CLIENT_ID`,
+    /// `MCSTATUS_OIDC_CLIENT_SECRET` and `MCSTATUS_OIDC_REDIRECT_URL`, who may log in from
+    /// `MCSTATUS_OIDC_ADMINS` as a comma separated list of subjects or emails, and how long they
+    /// stay logged in from `MCSTATUS_OIDC_SESSION_TTL`. Disabled without an issuer.
+    pub fn from_env(vars: &mut Loader) -> Option<Self> {
+        const OIDC_ISSUER: &str = "OIDC_ISSUER";
+        const OIDC_CLIENT_ID: &str = "OIDC_CLIENT_ID";
+        const OIDC_CLIENT_SECRET: &str = "OIDC_CLIENT_SECRET";
+        const OIDC_REDIRECT_URL: &str = "OIDC_REDIRECT_URL";
+        const OIDC_ADMINS: &str = "OIDC_ADMINS";
+        const OIDC_SESSION_TTL: &str = "OIDC_SESSION_TTL";
+
+        let issuer = vars.optional(OIDC_ISSUER).filter(|i| !i.is_empty());
+        let client_id = vars.optional(OIDC_CLIENT_ID);
+        let client_secret = vars.secret(OIDC_CLIENT_SECRET);
+        let redirect_url = vars.optional(OIDC_REDIRECT_URL);
+        let admins = vars.string(OIDC_ADMINS, "");
+        let admins = admins
+            .split(',')
+            .map(str::trim)
+            .filter(|a| !a.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+        let session_ttl = vars.duration(OIDC_SESSION_TTL, "12 hours");
+
+        let issuer = issuer?;
+        let (Some(client_id), Some(client_secret), Some(redirect_url)) =
+            (client_id, client_secret, redirect_url)
+        else {
+            vars.invalid(
+                OIDC_ISSUER,
+                format!("needs {OIDC_CLIENT_ID}, {OIDC_CLIENT_SECRET} and {OIDC_REDIRECT_URL}"),
+            );
+            return None;
+        };
+        Some(
+            Self::new(issuer, client_id, client_secret, redirect_url)
+                .admins(admins)
+                .session_ttl(session_ttl),
+        )
+    }
+
+    /// Nothing is fetched from the provider until the first login.
+    pub fn new(
+        issuer: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        redirect_url: impl Into<String>,
+    ) -> Self {
+        Self {
+            issuer: issuer.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            redirect_url: redirect_url.into(),
+            admins: Vec::new(),
+            session_ttl: Duration::from_secs(12 * 60 * 60),
+            client: reqwest::Client::new(),
+            discovery: OnceCell::new(),
+            pending: Mutex::default(),
+            sessions: Mutex::default(),
+        }
+    }
+
+    #[must_use]
+    pub fn admins(mut self, admins: Vec<String>) -> Self {
+        self.admins = admins;
+        self
+    }
+
+    #[must_use]
+    pub const fn session_ttl(mut self, ttl: Duration) -> Self {
+        self.session_ttl = ttl;
+        self
+    }
+
+    /// Whether the request comes with the cookie of a session that is still valid.
+    pub fn has_session(&self, headers: &HeaderMap) -> bool {
+        let Some(id) = session_cookie(headers) else {
+            return false;
+        };
+        let mut sessions = self.sessions();
+        sessions.retain(|_, s| s.expires_at > Instant::now());
+        sessions.contains_key(id)
+    }
+
+    fn is_admin(&self, claims: &IdClaims) -> bool {
+        self.admins.is_empty()
+            || self
+                .admins
+                .iter()
+                .any(|a| *a == claims.sub || claims.email.as_ref() == Some(a))
+    }
+
+    async fn discovery(&self) -> Result<&Discovery, (StatusCode, String)> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.issuer.trim_end_matches('/')
+                );
+                let metadata: Metadata = self
+                    .client
+                    .get(&url)
+                    .timeout(REQUEST_TIMEOUT)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(|e| bad_gateway("discovering the OpenID provider", &e))?
+                    .json()
+                    .await
+                    .map_err(|e| bad_gateway("discovering the OpenID provider", &e))?;
+                info!(issuer = metadata.issuer, "Discovered OpenID provider");
+                Ok(Discovery {
+                    authorization_endpoint: metadata.authorization_endpoint,
+                    token_endpoint: metadata.token_endpoint,
+                    id_tokens: jwt::Validator::jwks(metadata.jwks_uri)
+                        .issuer(Some(metadata.issuer))
+                        .audience(Some(self.client_id.clone())),
+                })
+            })
+            .await
+    }
+
+    fn pending(&self) -> MutexGuard<'_, HashMap<String, Pending>> {
+        self.pending
+            .lock()
+            .expect("Pending logins lock should not be poisoned")
+    }
+
+    fn sessions(&self) -> MutexGuard<'_, HashMap<String, Session>> {
+        self.sessions
+            .lock()
+            .expect("Sessions lock should not be poisoned")
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct LoginQuery {
+    /// Path to go back to once logged in.
+    return_to: Option<String>,
+}
+
+/// `GET /admin/login`, which sends the operator to log in with the provider.
+pub(crate) async fn login(
+    State(state): State<AppState>,
+    Query(query): Query<LoginQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let provider = enabled(&state)?;
+    let discovery = provider.discovery().await?;
+    // Only paths, so the login can't be used to send someone elsewhere
+    let return_to = query
+        .return_to
+        .filter(|r| r.starts_with('/') && !r.starts_with("//"))
+        .unwrap_or_else(|| "/".to_owned());
+
+    let login_state = random_id();
+    let nonce = random_id();
+    let url = reqwest::Url::parse_with_params(
+        &discovery.authorization_endpoint,
+        [
+            ("response_type", "code"),
+            ("client_id", &provider.client_id),
+            ("redirect_uri", &provider.redirect_url),
+            ("scope", "openid email"),
+            ("state", &login_state),
+            ("nonce", &nonce),
+        ],
+    )
+    .map_err(|e| bad_gateway("building the login URL", &e))?;
+
+    let mut pending = provider.pending();
+    pending.retain(|_, p| p.started_at.elapsed() < LOGIN_TIMEOUT);
+    pending.insert(
+        login_state,
+        Pending {
+            nonce,
+            return_to,
+            started_at: Instant::now(),
+        },
+    );
+    drop(pending);
+    Ok(Redirect::to(url.as_str()).into_response())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// `GET /admin/callback`, where the provider sends the operator back to with a code for their ID
+/// token.
+pub(crate) async fn callback(
+    State(state): State<AppState>,
+    Query(query): Query<CallbackQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let provider = enabled(&state)?;
+    if let Some(error) = query.error {
+        let reason = query.error_description.unwrap_or(error);
+        return Err((StatusCode::UNAUTHORIZED, format!("Login failed: {reason}")));
+    }
+    let pending = query
+        .state
+        .and_then(|s| provider.pending().remove(&s))
+        .filter(|p| p.started_at.elapsed() < LOGIN_TIMEOUT)
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                "Unknown or expired login, start over from /admin/login".to_owned(),
+            )
+        })?;
+    let code = query.code.ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            "The provider sent no authorization code".to_owned(),
+        )
+    })?;
+
+    let discovery = provider.discovery().await?;
+    let tokens: TokenResponse = provider
+        .client
+        .post(&discovery.token_endpoint)
+        .basic_auth(&provider.client_id, Some(&provider.client_secret))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("redirect_uri", &provider.redirect_url),
+        ])
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| bad_gateway("exchanging the authorization code", &e))?
+        .json()
+        .await
+        .map_err(|e| bad_gateway("exchanging the authorization code", &e))?;
+    let claims: IdClaims = discovery
+        .id_tokens
+        .decode(&tokens.id_token)
+        .await
+        .map_err(|e| (StatusCode::UNAUTHORIZED, format!("Invalid ID token: {e}")))?;
+    if claims.nonce.as_deref() != Some(pending.nonce.as_str()) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "The ID token was not issued for this login".to_owned(),
+        ));
+    }
+    if !provider.is_admin(&claims) {
+        debug!(sub = claims.sub, email = claims.email, "Rejected login");
+        let who = claims.email.unwrap_or(claims.sub);
+        return Err((StatusCode::FORBIDDEN, format!("{who} is not an admin")));
+    }
+
+    info!(sub = claims.sub, email = claims.email, "Admin logged in");
+    let id = random_id();
+    provider.sessions().insert(
+        id.clone(),
+        Session {
+            subject: claims.sub,
+            expires_at: Instant::now() + provider.session_ttl,
+        },
+    );
+    let secure = if provider.redirect_url.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+    let cookie = format!(
+        "{COOKIE}={id}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax{secure}",
+        provider.session_ttl.as_secs()
+    );
+    Ok((
+        [(header::SET_COOKIE, cookie)],
+        Redirect::to(&pending.return_to),
+    )
+        .into_response())
+}
+
+/// `GET /admin/logout`, which ends the session of the operator.
+pub(crate) async fn logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let provider = enabled(&state)?;
+    if let Some(id) = session_cookie(&headers) {
+        let session = provider.sessions().remove(id);
+        if let Some(session) = session {
+            info!(sub = session.subject, "Admin logged out");
+        }
+    }
+    let cookie = format!("{COOKIE}=; Path=/; Max-Age=0; HttpOnly; SameSite=Lax");
+    Ok(([(header::SET_COOKIE, cookie)], "Logged out").into_response())
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .find_map(|c| c.trim().strip_prefix(COOKIE)?.strip_prefix('='))
+}
+
+fn random_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+fn enabled(state: &AppState) -> Result<&Provider, (StatusCode, String)> {
+    state.oidc.as_deref().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            "OpenID Connect login is not configured".to_owned(),
+        )
+    })
+}
+
+fn bad_gateway(doing: &str, e: &impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::BAD_GATEWAY, format!("Failed {doing}: {e}"))
+}
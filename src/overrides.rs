@@ -0,0 +1,163 @@
+//! Settings of the servers in the config file that take precedence over the global ones, for the
+//! few servers that need different treatment, like a Bedrock server next to Java ones.
+//!
+//! Configured servers are matched by the host and port they were asked for by, and from then on
+//! by the address they resolved to, so fetches that only know the address, like hot refreshes,
+//! get the same settings.
+
+use mcstatus_core::{bedrock, Error, Resolver, ServerAddr, ServerStatus};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{Backend, Config, Server},
+    env_vars::Loader,
+};
+
+/// Ports of servers asked for without one, unless they are configured with a port of their own.
+#[derive(Debug, Clone, Copy)]
+pub struct DefaultPorts {
+    pub java: u16,
+    /// For configured servers with the Bedrock backend.
+    pub bedrock: u16,
+}
+
+impl Default for DefaultPorts {
+    fn default() -> Self {
+        Self {
+            java: 25565,
+            bedrock: bedrock::DEFAULT_PORT,
+        }
+    }
+}
+
+impl DefaultPorts {
+    pub fn from_env(vars: &mut Loader) -> Self {
+        const DEFAULT_PORT: &str = "DEFAULT_PORT";
+        const BEDROCK_DEFAULT_PORT: &str = "BEDROCK_DEFAULT_PORT";
+
+        let defaults = Self::default();
+        Self {
+            java: vars.value(DEFAULT_PORT, &defaults.java.to_string()),
+            bedrock: vars.value(BEDROCK_DEFAULT_PORT, &defaults.bedrock.to_string()),
+        }
+    }
+}
+
+pub struct Overrides {
+    config: Arc<Config>,
+    ports: DefaultPorts,
+    resolver: Resolver,
+    /// Index into [`Config::servers`] by every address a configured server resolved to.
+    resolved: Mutex<HashMap<SocketAddr, usize>>,
+}
+
+impl Overrides {
+    pub fn new(config: Arc<Config>, ports: DefaultPorts, resolver: Resolver) -> Self {
+        Self {
+            config,
+            ports,
+            resolver,
+            resolved: Mutex::default(),
+        }
+    }
+
+    /// Resolves `addr` with the default port of the configured server it names, or the global one.
+    /// SRV records are followed for all but Bedrock servers, which have none.
+    pub async fn resolve(&self, addr: &str) -> Result<ServerAddr, Error> {
+        let index = self.find(addr);
+        let server = index.map(|i| &self.config.servers[i]);
+        let default_port = server.map_or(self.ports.java, |s| self.default_port(s));
+        let srv = !server.is_some_and(|s| s.backend.is_some_and(Backend::is_bedrock));
+        let resolved = self.resolver.resolve(addr, default_port, srv).await?;
+        if let Some(index) = index {
+            self.lock().insert(resolved.address, index);
+        }
+        Ok(resolved)
+    }
+
+    /// The configured server that resolved to `address`.
+    pub fn get(&self, address: SocketAddr) -> Option<&Server> {
+        let index = *self.lock().get(&address)?;
+        self.config.servers.get(index)
+    }
+
+    /// The configured server `addr` names, with or without its default port.
+    pub fn configured(&self, addr: &str) -> Option<&Server> {
+        self.find(addr).map(|i| &self.config.servers[i])
+    }
+
+    pub const fn ports(&self) -> DefaultPorts {
+        self.ports
+    }
+
+    /// The cache TTL of the server at `address`, `default` unless it has one of its own.
+    pub fn cache_ttl(&self, address: SocketAddr, default: Duration) -> Duration {
+        self.get(address)
+            .and_then(|server| server.cache_ttl)
+            .unwrap_or(default)
+    }
+
+    fn find(&self, addr: &str) -> Option<usize> {
+        let (host, port) = split(addr);
+        self.config.servers.iter().position(|server| {
+            let (server_host, server_port) = split(&server.address);
+            let default = self.default_port(server);
+            server_host.eq_ignore_ascii_case(host)
+                && port.unwrap_or(default) == server_port.unwrap_or(default)
+        })
+    }
+
+    fn default_port(&self, server: &Server) -> u16 {
+        server
+            .default_port
+            .unwrap_or(if server.backend.is_some_and(Backend::is_bedrock) {
+                self.ports.bedrock
+            } else {
+                self.ports.java
+            })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<SocketAddr, usize>> {
+        self.resolved
+            .lock()
+            .expect("Resolved servers lock should not be poisoned")
+    }
+}
+
+fn split(addr: &str) -> (&str, Option<u16>) {
+    addr.split_once(':')
+        .map_or((addr, None), |(host, port)| (host, port.parse().ok()))
+}
+
+/// Expires statuses after the cache TTL of their server, counting from every insert.
+pub struct Expiry {
+    pub default: Duration,
+    pub overrides: Arc<Overrides>,
+}
+
+impl moka::Expiry<SocketAddr, ServerStatus> for Expiry {
+    fn expire_after_create(
+        &self,
+        key: &SocketAddr,
+        _value: &ServerStatus,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        Some(self.overrides.cache_ttl(*key, self.default))
+    }
+
+    // Refreshed entries start over, instead of keeping the expiry of what they replaced
+    fn expire_after_update(
+        &self,
+        key: &SocketAddr,
+        _value: &ServerStatus,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(self.overrides.cache_ttl(*key, self.default))
+    }
+}
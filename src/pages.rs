@@ -0,0 +1,90 @@
+//! The HTML pages, rendered with Tera from the templates embedded in the binary, or from the
+//! directory in `MCSTATUS_TEMPLATE_DIR` for operators that want to brand them. Templates missing
+//! from the directory fall back to the embedded ones, so it only needs those that are changed.
+
+use axum::{http::StatusCode, response::Html};
+use serde::Serialize;
+use std::path::Path;
+use tera::{Context, Tera};
+use tracing::warn;
+
+use crate::env_vars::Loader;
+
+/// Every template the service renders, by name.
+const EMBEDDED: &[(&str, &str)] = &[("index.html", include_str!("../templates/index.html"))];
+
+pub struct Templates {
+    tera: Tera,
+}
+
+impl Default for Templates {
+    /// Only the templates embedded in the binary.
+    fn default() -> Self {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(EMBEDDED.iter().copied())
+            .expect("Embedded templates should parse");
+        Self { tera }
+    }
+}
+
+impl Templates {
+    pub fn from_env(vars: &mut Loader) -> Self {
+        const TEMPLATE_DIR: &str = "TEMPLATE_DIR";
+
+        let dir = vars.optional(TEMPLATE_DIR);
+        Self::load(dir.as_deref().map(Path::new)).unwrap_or_else(|e| {
+            vars.invalid(
+                TEMPLATE_DIR,
+                format!("failed loading templates: {}", describe(&e)),
+            );
+            Self::default()
+        })
+    }
+
+    /// Loads the templates in `dir` over the embedded ones.
+    ///
+    /// # Errors
+    ///
+    /// If `dir` is not a directory, or one of its templates doesn't parse.
+    pub fn load(dir: Option<&Path>) -> tera::Result<Self> {
+        let Some(dir) = dir else {
+            return Ok(Self::default());
+        };
+        if !dir.is_dir() {
+            return Err(tera::Error::msg(format!(
+                "{} is not a directory",
+                dir.display()
+            )));
+        }
+        let mut tera = Tera::new(&format!("{}/**/*", dir.display()))?;
+        tera.extend(&Self::default().tera)?;
+        Ok(Self { tera })
+    }
+
+    /// Renders the template `name` with the fields of `context`.
+    pub fn render(
+        &self,
+        name: &str,
+        context: &impl Serialize,
+    ) -> Result<Html<String>, (StatusCode, String)> {
+        let render = || self.tera.render(name, &Context::from_serialize(context)?);
+        render().map(Html).map_err(|e| {
+            warn!(name, e = describe(&e), "Failed rendering template");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed rendering {name}"),
+            )
+        })
+    }
+}
+
+/// Tera keeps the details of what went wrong, like the line, in the sources of its errors.
+fn describe(error: &tera::Error) -> String {
+    let mut description = error.to_string();
+    let mut source = std::error::Error::source(error);
+    while let Some(e) = source {
+        description.push_str(&format!(": {e}"));
+        source = e.source();
+    }
+    description
+}
@@ -0,0 +1,280 @@
+//! WASM plugins, for extending the service without forking it. Every `.wasm` or `.wat` module in
+//! `MCSTATUS_PLUGIN_DIR` is loaded at startup and named after its file, which needs mcstatus-http
+//! to be built with the `plugins` feature.
+//!
+//! Modules export their `memory`, and an `alloc(len: i32) -> i32` that reserves `len` bytes of it
+//! for JSON to be passed in, along with whichever of these hooks they implement:
+//!
+//! - `notify(ptr: i32, len: i32)` is given the notifications of notifiers with `kind = "plugin"`
+//!   and `plugin = "<name>"`, for channels the service doesn't have.
+//! - `parse(ptr: i32, len: i32) -> i64` is given every status as it is fetched, and returns an
+//!   object of labels to add to it, like ones made out of the MOTD.
+//! - `transform(ptr: i32, len: i32) -> i64` is given every status about to be served as JSON by
+//!   `/:url`, and returns what to serve instead.
+//!
+//! What hooks return is JSON at `ptr << 32 | len` of their memory, with 0 changing nothing. Modules
+//! may import `mcstatus.log(ptr: i32, len: i32)` to log a message. Every call gets a fresh instance
+//! with limited fuel and memory, so plugins can't hang the service or keep state between calls, and
+//! a plugin that fails is logged and skipped.
+
+use mcstatus_core::ServerStatus;
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+use tracing::{info, warn};
+
+use crate::{env_vars::Loader, notify::Notification};
+
+const NOTIFY: &str = "notify";
+const PARSE: &str = "parse";
+const TRANSFORM: &str = "transform";
+
+#[derive(Default)]
+pub struct Plugins {
+    /// Sorted by name, which is the order their hooks run in.
+    plugins: Vec<Plugin>,
+}
+
+struct Plugin {
+    name: String,
+    #[cfg(feature = "plugins")]
+    module: wasmtime::Module,
+}
+
+impl Plugins {
+    pub fn from_env(vars: &mut Loader) -> Self {
+        const PLUGIN_DIR: &str = "PLUGIN_DIR";
+
+        let dir = vars.optional(PLUGIN_DIR);
+        Self::load(dir.as_deref().map(Path::new)).unwrap_or_else(|e| {
+            vars.invalid(PLUGIN_DIR, format!("failed loading plugins: {e}"));
+            Self::default()
+        })
+    }
+
+    /// Loads every module in `dir`.
+    ///
+    /// # Errors
+    ///
+    /// If `dir` can't be read, one of its modules doesn't compile, or the `plugins` feature is off.
+    pub fn load(dir: Option<&Path>) -> Result<Self, String> {
+        let Some(dir) = dir else {
+            return Ok(Self::default());
+        };
+        let mut plugins = load(dir)?;
+        plugins.sort_by(|a, b| a.name.cmp(&b.name));
+        for plugin in &plugins {
+            info!(name = plugin.name, "Loaded plugin");
+        }
+        Ok(Self { plugins })
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.plugins.iter().any(|p| p.name == name)
+    }
+
+    /// Hands `notification` to the plugin `name`.
+    pub fn notify(&self, name: &str, notification: &Notification) -> Result<(), String> {
+        let plugin = self
+            .plugins
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| format!("No plugin is called {name}"))?;
+        if !plugin.exports(NOTIFY) {
+            return Err(format!("Plugin {name} has no {NOTIFY} hook"));
+        }
+        plugin.call(NOTIFY, &serialize(notification)?).map(drop)
+    }
+
+    /// Adds the labels plugins parse out of `status` to it.
+    pub fn parse(&self, status: &mut ServerStatus) {
+        let mut plugins = self.plugins.iter().filter(|p| p.exports(PARSE)).peekable();
+        if plugins.peek().is_none() {
+            return;
+        }
+        let input = match serialize(&*status) {
+            Ok(input) => input,
+            Err(e) => return warn!(%e, "Failed passing status to plugins"),
+        };
+        for plugin in plugins {
+            let labels = plugin.call(PARSE, &input).and_then(|output| {
+                output
+                    .map(|output| serde_json::from_slice::<BTreeMap<String, String>>(&output))
+                    .transpose()
+                    .map_err(|e| format!("Returned labels that aren't a string map: {e}"))
+            });
+            match labels {
+                Ok(labels) => status.labels.extend(labels.unwrap_or_default()),
+                Err(e) => warn!(plugin = plugin.name, %e, "Plugin failed parsing status"),
+            }
+        }
+    }
+
+    /// What plugins make of `status`, or `None` when none of them transform statuses.
+    pub fn transform(&self, status: &ServerStatus) -> Option<serde_json::Value> {
+        let mut plugins = self
+            .plugins
+            .iter()
+            .filter(|p| p.exports(TRANSFORM))
+            .peekable();
+        plugins.peek()?;
+        let mut value = match serde_json::to_value(status) {
+            Ok(value) => value,
+            Err(e) => {
+                warn!(%e, "Failed passing status to plugins");
+                return None;
+            }
+        };
+        // Each one transforms what the one before it made
+        for plugin in plugins {
+            let transformed = serialize(&value)
+                .and_then(|input| plugin.call(TRANSFORM, &input))
+                .and_then(|output| {
+                    output
+                        .map(|output| serde_json::from_slice(&output))
+                        .transpose()
+                        .map_err(|e| format!("Returned invalid JSON: {e}"))
+                });
+            match transformed {
+                Ok(Some(transformed)) => value = transformed,
+                Ok(None) => {}
+                Err(e) => warn!(plugin = plugin.name, %e, "Plugin failed transforming status"),
+            }
+        }
+        Some(value)
+    }
+}
+
+fn serialize(value: &impl Serialize) -> Result<Vec<u8>, String> {
+    serde_json::to_vec(value).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "plugins"))]
+fn load(_dir: &Path) -> Result<Vec<Plugin>, String> {
+    Err("mcstatus-http was built without the plugins feature".to_owned())
+}
+
+#[cfg(not(feature = "plugins"))]
+impl Plugin {
+    #[allow(clippy::unused_self)]
+    const fn exports(&self, _hook: &str) -> bool {
+        false
+    }
+
+    #[allow(clippy::unused_self)]
+    fn call(&self, _hook: &str, _input: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        Err("mcstatus-http was built without the plugins feature".to_owned())
+    }
+}
+
+#[cfg(feature = "plugins")]
+use wasm::load;
+
+#[cfg(feature = "plugins")]
+mod wasm {
+    use std::{fs, path::Path};
+    use tracing::info;
+    use wasmtime::{
+        Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits,
+        StoreLimitsBuilder,
+    };
+
+    use super::Plugin;
+
+    /// Roughly how many instructions a call may run.
+    const FUEL: u64 = 100_000_000;
+    const MAX_MEMORY: usize = 64 * 1024 * 1024;
+
+    pub fn load(dir: &Path) -> Result<Vec<Plugin>, String> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(|e| e.to_string())?;
+
+        let entries = fs::read_dir(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        let mut plugins = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            let is_module = path
+                .extension()
+                .is_some_and(|ext| ext == "wasm" || ext == "wat");
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if !is_module {
+                continue;
+            }
+            let module = Module::from_file(&engine, &path)
+                .map_err(|e| format!("{}: {e:#}", path.display()))?;
+            plugins.push(Plugin {
+                name: name.to_owned(),
+                module,
+            });
+        }
+        Ok(plugins)
+    }
+
+    impl Plugin {
+        pub(super) fn exports(&self, hook: &str) -> bool {
+            self.module.get_export(hook).is_some()
+        }
+
+        /// Calls `hook` with `input` in a fresh instance, returning its output if it has any.
+        pub(super) fn call(&self, hook: &str, input: &[u8]) -> Result<Option<Vec<u8>>, String> {
+            self.try_call(hook, input).map_err(|e| format!("{e:#}"))
+        }
+
+        fn try_call(&self, hook: &str, input: &[u8]) -> wasmtime::Result<Option<Vec<u8>>> {
+            let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+            let mut store = Store::new(self.module.engine(), limits);
+            store.limiter(|limits: &mut StoreLimits| limits);
+            store.set_fuel(FUEL)?;
+
+            let mut linker = Linker::new(self.module.engine());
+            let name = self.name.clone();
+            linker.func_wrap(
+                "mcstatus",
+                "log",
+                move |mut caller: Caller<'_, StoreLimits>, ptr: i32, len: i32| {
+                    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+                        return;
+                    };
+                    if let Ok(message) = read(&memory, &caller, ptr, len) {
+                        info!(plugin = name, "{}", String::from_utf8_lossy(&message));
+                    }
+                },
+            )?;
+            let instance = linker.instantiate(&mut store, &self.module)?;
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .ok_or_else(|| wasmtime::Error::msg("Module doesn't export its memory"))?;
+            let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+
+            let len = i32::try_from(input.len())?;
+            let ptr = alloc.call(&mut store, len)?;
+            memory.write(&mut store, usize::try_from(ptr)?, input)?;
+            let Ok(hook_with_output) = instance.get_typed_func::<(i32, i32), i64>(&mut store, hook)
+            else {
+                let hook = instance.get_typed_func::<(i32, i32), ()>(&mut store, hook)?;
+                hook.call(&mut store, (ptr, len))?;
+                return Ok(None);
+            };
+            let output = hook_with_output.call(&mut store, (ptr, len))?;
+            if output == 0 {
+                return Ok(None);
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            let (ptr, len) = ((output >> 32) as i32, output as i32);
+            read(&memory, &store, ptr, len).map(Some)
+        }
+    }
+
+    fn read(
+        memory: &Memory,
+        store: impl wasmtime::AsContext,
+        ptr: i32,
+        len: i32,
+    ) -> wasmtime::Result<Vec<u8>> {
+        let mut buffer = vec![0; usize::try_from(len)?];
+        memory.read(store, usize::try_from(ptr)?, &mut buffer)?;
+        Ok(buffer)
+    }
+}
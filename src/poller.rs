@@ -0,0 +1,144 @@
+//! Background polling of every server in the config file. Results go into the status cache, so
+//! requests for polled servers are served without waiting, and are broadcast to anything that
+//! wants to react to them, like alerting.
+
+use chrono::Utc;
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::task::JoinSet;
+use tracing::{debug, debug_span, Instrument};
+
+use crate::{geoip, AppState, ServerStatus};
+
+#[derive(Debug)]
+pub struct PollResult {
+    /// Address of the server, as written in the config file.
+    pub server: String,
+    /// The server's labels from the config file, and its country and ASN when `GeoIP` is set up,
+    /// for exporters to tag metrics with.
+    pub labels: BTreeMap<String, String>,
+    pub status: Result<ServerStatus, String>,
+    /// Whether the server counts as online, which lags behind the status while polls have yet to
+    /// confirm a change.
+    pub online: bool,
+    /// Whether the server keeps going up and down.
+    pub flapping: bool,
+    pub polled_at: SystemTime,
+    /// How long resolving and fetching the status took.
+    pub latency: Duration,
+}
+
+pub async fn run(state: AppState) {
+    // Every server is polled on its own interval or schedule
+    let mut pollers = JoinSet::new();
+    for server in &state.config.servers {
+        let state = state.clone();
+        let every = server
+            .poll_interval
+            .unwrap_or_else(|| state.config.poll_interval());
+        let schedule = server.schedule.clone();
+        let labels = server.labels.clone();
+        let server = server.address.clone();
+        let span = debug_span!("poll", server);
+        pollers.spawn(
+            async move {
+                match schedule {
+                    Some(schedule) => poll_on(&state, server, labels, &schedule).await,
+                    None => poll_every(&state, server, labels, every).await,
+                }
+            }
+            .instrument(span),
+        );
+    }
+    while pollers.join_next().await.is_some() {}
+}
+
+/// Polls once per `every`, counting from the start of one poll to the next. With
+/// [`Config::max_offline_backoff`](crate::config::Config::max_offline_backoff) set, the interval
+/// doubles with every poll that finds the server offline, up to that cap, and is back to `every`
+/// as soon as it answers again.
+async fn poll_every(
+    state: &AppState,
+    server: String,
+    labels: BTreeMap<String, String>,
+    every: Duration,
+) {
+    let max_backoff = state.config.max_offline_backoff;
+    let mut delay = every;
+
+    loop {
+        let started = Instant::now();
+        // Each poll finishes before the next one starts, so a slow server is never polled twice at
+        // the same time
+        let online = poll(state, server.clone(), labels.clone()).await;
+        delay = match max_backoff {
+            Some(max) if !online => delay.saturating_mul(2).clamp(every, max.max(every)),
+            _ => every,
+        };
+        if delay > every {
+            debug!(?delay, "Server is offline, polling it less often");
+        }
+        tokio::time::sleep_until((started + delay).into()).await;
+    }
+}
+
+/// Polls at every time `schedule` matches. Times that pass while a slow poll is still running are
+/// skipped rather than caught up on.
+async fn poll_on(
+    state: &AppState,
+    server: String,
+    labels: BTreeMap<String, String>,
+    schedule: &cron::Schedule,
+) {
+    let mut after = Utc::now();
+    loop {
+        let Some(next) = schedule.after(&after).next() else {
+            debug!("Schedule has no times left, no longer polling");
+            return;
+        };
+        if let Ok(wait) = (next - Utc::now()).to_std() {
+            tokio::time::sleep(wait).await;
+        }
+        poll(state, server.clone(), labels.clone()).await;
+        after = next.max(Utc::now());
+    }
+}
+
+/// Returns whether the server was online.
+async fn poll(state: &AppState, server: String, mut labels: BTreeMap<String, String>) -> bool {
+    let started = Instant::now();
+    let status = match state.resolve(&server).await {
+        Ok(addr) => {
+            // Known from the address alone, so failed polls are tagged the same
+            if let Some(geo) = state.geo(addr.address.ip()) {
+                geoip::add_labels(&geo, &mut labels);
+            }
+            match state.fetch(&addr).await {
+                Ok(status) => {
+                    state.cache.insert(addr.address, status.clone()).await;
+                    Ok(status)
+                }
+                Err((_, e)) => Err(e),
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    };
+    debug!(ok = status.is_ok(), "Polled server");
+    let online = status.as_ref().is_ok_and(|s| s.output.is_some());
+    let judgement = state.flapping.observe(&server, online);
+
+    // There being no subscribers is fine
+    _ = state.poll_results.send(Arc::new(PollResult {
+        server,
+        labels,
+        status,
+        online: judgement.online,
+        flapping: judgement.flapping,
+        polled_at: SystemTime::now(),
+        latency: started.elapsed(),
+    }));
+    online
+}
@@ -0,0 +1,18 @@
+//! `mcstatus-http query <addr>`, which fetches one status with the configured backend and prints it
+//! as the same JSON the status route serves, for scripts and cron jobs.
+
+use color_eyre::{eyre::eyre, Result};
+use mcstatus_http::{env_vars::Loader, AppState};
+
+pub async fn run(server: Option<String>) -> Result<()> {
+    let server = server.ok_or_else(|| eyre!("Usage: mcstatus-http query <addr>"))?;
+    let mut vars = Loader::with_env_file();
+    let state = AppState::from_env(&mut vars);
+    vars.finish()?;
+    let status = state
+        .fetch_uncached(server.clone())
+        .await
+        .map_err(|e| eyre!("Querying {server} failed: {e}"))?;
+    println!("{}", serde_json::to_string_pretty(&status)?);
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Caps how often any single Minecraft server is fetched, no matter how many clients or cache keys
+//! ask for it, so the service can't be used to flood a server with pings. Asking again within the
+//! interval gets the status of the last fetch instead.
+
+use axum::http::StatusCode;
+use mcstatus_core::ServerStatus;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+use crate::env_vars::Loader;
+
+pub struct RateCap {
+    /// Shortest time between two fetches of the same server, no cap when zero.
+    interval: Duration,
+    last_fetches: Mutex<HashMap<SocketAddr, LastFetch>>,
+}
+
+struct LastFetch {
+    started_at: Instant,
+    /// Missing while the fetch is still running, or if it failed.
+    status: Option<ServerStatus>,
+}
+
+pub enum Permit {
+    Fetch,
+    Reuse(Box<ServerStatus>),
+}
+
+impl RateCap {
+    pub fn from_env(vars: &mut Loader) -> Self {
+        const MIN_FETCH_INTERVAL: &str = "MIN_FETCH_INTERVAL";
+
+        Self::new(vars.duration(MIN_FETCH_INTERVAL, "5 seconds"))
+    }
+
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_fetches: Mutex::default(),
+        }
+    }
+
+    pub const fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Decides whether `addr` may be fetched now, and if so records that it is. Fails with a 429
+    /// when `addr` was fetched within the interval, but that fetch has no status to reuse.
+    pub fn acquire(&self, addr: SocketAddr) -> Result<Permit, (StatusCode, String)> {
+        if self.interval.is_zero() {
+            return Ok(Permit::Fetch);
+        }
+        acquire(&mut self.lock(), self.interval, addr)
+    }
+
+    /// Keeps `status` around for reuse until the interval since its fetch started is over.
+    pub fn record(&self, addr: SocketAddr, status: &ServerStatus) {
+        if self.interval.is_zero() {
+            return;
+        }
+        if let Some(last) = self.lock().get_mut(&addr) {
+            last.status = Some(status.clone());
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<SocketAddr, LastFetch>> {
+        self.last_fetches
+            .lock()
+            .expect("Last fetches lock should not be poisoned")
+    }
+}
+
+fn acquire(
+    last_fetches: &mut HashMap<SocketAddr, LastFetch>,
+    interval: Duration,
+    addr: SocketAddr,
+) -> Result<Permit, (StatusCode, String)> {
+    // Nothing older than the interval has any effect anymore
+    last_fetches.retain(|_, last| last.started_at.elapsed() < interval);
+
+    let Some(last) = last_fetches.get(&addr) else {
+        last_fetches.insert(
+            addr,
+            LastFetch {
+                started_at: Instant::now(),
+                status: None,
+            },
+        );
+        return Ok(Permit::Fetch);
+    };
+
+    debug!(%addr, "Fetched too recently, not fetching again");
+    last.status
+        .clone()
+        .map(|s| Permit::Reuse(Box::new(s)))
+        .ok_or_else(|| {
+            let retry_in = interval.saturating_sub(last.started_at.elapsed());
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                format!(
+                    "{addr} was fetched less than {interval:?} ago, try again in {:.1} seconds",
+                    retry_in.as_secs_f64()
+                ),
+            )
+        })
+}
@@ -0,0 +1,62 @@
+//! `/:url/reachable`, whether anything accepts TCP connections at the server's address, for
+//! monitoring that doesn't need the whole status. Nothing is sent over the connection, so it says
+//! nothing about whether the server behind it works. Servers are probed at most once per
+//! `MCSTATUS_MIN_FETCH_INTERVAL`, like they are fetched, with the last probe served in between.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use mcstatus_core::slp;
+use serde::Serialize;
+use std::{net::SocketAddr, time::Instant};
+use tracing::debug;
+
+use crate::{status_error, AppState};
+
+#[derive(Clone, Serialize)]
+pub struct Reachability {
+    address: SocketAddr,
+    reachable: bool,
+    /// Time until the connection was accepted, or refused.
+    connect_ms: f64,
+    error: Option<String>,
+}
+
+pub async fn handler(
+    Path(addr): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Reachability>, (StatusCode, String)> {
+    debug!(%addr, "Reachability requested from api");
+
+    let address = state.resolve(&addr).await.map_err(status_error)?.address;
+    let reachability = state
+        .reachability
+        .get_with(address, probe(&state, address))
+        .await;
+    Ok(Json(reachability))
+}
+
+async fn probe(state: &AppState, address: SocketAddr) -> Reachability {
+    let server = state.overrides.get(address);
+    let (_, _, outbound) = state.fetch_settings(server, None);
+    let timeout = outbound
+        .connect_timeout
+        .or_else(|| server.and_then(|server| server.timeout))
+        .unwrap_or(slp::DEFAULT_TIMEOUT);
+
+    let started = Instant::now();
+    // Connected like pings are, from the same source address and through the same proxy
+    let error = match tokio::time::timeout(timeout, outbound.connect_tcp(&address)).await {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(format!("Failed connecting to {address}: {e}")),
+        Err(_) => Some(format!("No connection to {address} within {timeout:?}")),
+    };
+    Reachability {
+        address,
+        reachable: error.is_none(),
+        connect_ms: started.elapsed().as_secs_f64() * 1000.0,
+        error,
+    }
+}
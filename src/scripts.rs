@@ -0,0 +1,335 @@
+//! Lua scripts of the servers in the config file with a `script`, for quick custom automation
+//! without a webhook receiver to run it on. Needs mcstatus-http to be built with the `lua`
+//! feature.
+//!
+//! A script is run once at startup, and can then define any of these functions, which are called
+//! with a table:
+//!
+//! - `on_poll(poll)` after every poll of its server, with `server`, `online`, `flapping`,
+//!   `polled_at` in seconds since the unix epoch, `latency_ms`, and either the `status` as served
+//!   by `/:url` or an `error`.
+//! - `on_event(event)` for every notification about its server, like an alert firing or
+//!   resolving, or its MOTD changing, with the fields webhooks get.
+//!
+//! Besides the standard library, scripts have `log(message)`, `json.encode(value)`,
+//! `json.decode(text)`, and `http.get(url, headers)` and `http.post(url, body, headers)`, which
+//! return the status code and the body of the response. Globals are kept between calls, so
+//! scripts can remember what they have seen. Each script runs on its own, one call at a time, and
+//! a call that takes longer than [`CALL_TIMEOUT`] is stopped.
+//!
+//! ```lua
+//! function on_poll(poll)
+//!   local players = poll.status and poll.status.output and poll.status.output.online_player_count
+//!   if players and players >= 20 and not crowded then
+//!     http.post("https://example.com/hooks/crowded", json.encode({ server = poll.server }))
+//!   end
+//!   crowded = players and players >= 20
+//! end
+//! ```
+
+// Events are only taken by scripts, which can't be configured without the feature
+#![cfg_attr(not(feature = "lua"), allow(dead_code))]
+
+use mcstatus_core::ServerStatus;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use tracing::warn;
+
+use crate::{config::Config, notify::Notification, poller::PollResult};
+
+/// How long a single call of a script, including its HTTP requests, may take.
+pub const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+enum Event {
+    Poll(Arc<PollResult>),
+    Notification(Arc<Notification>),
+}
+
+/// What scripts get for [`Event::Poll`].
+#[derive(Serialize)]
+struct Poll<'a> {
+    server: &'a str,
+    online: bool,
+    flapping: bool,
+    polled_at: f64,
+    latency_ms: f64,
+    status: Option<&'a ServerStatus>,
+    error: Option<&'a str>,
+}
+
+impl<'a> From<&'a PollResult> for Poll<'a> {
+    fn from(result: &'a PollResult) -> Self {
+        Self {
+            server: &result.server,
+            online: result.online,
+            flapping: result.flapping,
+            polled_at: result
+                .polled_at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |d| d.as_secs_f64()),
+            latency_ms: result.latency.as_secs_f64() * 1000.0,
+            status: result.status.as_ref().ok(),
+            error: result.status.as_ref().err().map(String::as_str),
+        }
+    }
+}
+
+/// Where the events of every server with a script go.
+#[derive(Clone, Default)]
+pub struct Scripts {
+    servers: Arc<HashMap<String, mpsc::UnboundedSender<Event>>>,
+}
+
+impl Scripts {
+    /// Starts the script of every server in `config` that has one, each in a task of its own.
+    pub fn start(config: &Config) -> Self {
+        let servers = config
+            .servers
+            .iter()
+            .filter_map(|server| {
+                let path = server.script.clone()?;
+                let (events_tx, events_rx) = mpsc::unbounded_channel();
+                lua::spawn(server.address.clone(), path, events_rx);
+                Some((server.address.clone(), events_tx))
+            })
+            .collect::<HashMap<_, _>>();
+        Self {
+            servers: Arc::new(servers),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.servers.is_empty()
+    }
+
+    /// Hands `notification` to the script of its server, if it has one.
+    pub fn notify(&self, notification: &Arc<Notification>) {
+        if let Some(events) = self.servers.get(&notification.server) {
+            // The script failing to load stops it from taking any
+            _ = events.send(Event::Notification(notification.clone()));
+        }
+    }
+}
+
+/// Hands every poll result to the script of its server.
+pub async fn run(scripts: Scripts, mut poll_results: broadcast::Receiver<Arc<PollResult>>) {
+    loop {
+        let result = match poll_results.recv().await {
+            Ok(result) => result,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "Scripts fell behind, skipped poll results");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if let Some(events) = scripts.servers.get(&result.server) {
+            _ = events.send(Event::Poll(result));
+        }
+    }
+}
+
+#[cfg(not(feature = "lua"))]
+mod lua {
+    use std::path::PathBuf;
+    use tokio::sync::mpsc;
+    use tracing::warn;
+
+    use super::Event;
+
+    /// Only reached by configs made in code, as files with scripts don't load without the feature.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn spawn(server: String, _: PathBuf, _: mpsc::UnboundedReceiver<Event>) {
+        warn!(
+            server,
+            "Ignoring the script, mcstatus-http was built without the lua feature"
+        );
+    }
+}
+
+#[cfg(feature = "lua")]
+// Scripts are run on threads of their own, see `spawn`
+#[allow(clippy::future_not_send)]
+mod lua {
+    use mlua::{
+        Function, HookTriggers, IntoLuaMulti, Lua, LuaSerdeExt, SerializeOptions, Table, Value,
+        Variadic,
+    };
+    use std::{collections::HashMap, path::PathBuf, thread, time::Instant};
+    use tokio::{runtime::Builder, sync::mpsc};
+    use tracing::{info, warn};
+
+    use super::{Event, Poll, CALL_TIMEOUT};
+
+    /// Runs the script on a thread of its own, as Lua can't be shared between threads, which also
+    /// keeps a slow script from holding up the rest of the service.
+    pub fn spawn(server: String, path: PathBuf, events: mpsc::UnboundedReceiver<Event>) {
+        let runtime = match Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(e) => return warn!(server, %e, "Failed starting runtime for script"),
+        };
+        let thread = thread::Builder::new()
+            .name(format!("script {server}"))
+            .spawn(move || runtime.block_on(run(server, path, events)));
+        if let Err(e) = thread {
+            warn!(%e, "Failed starting thread for script");
+        }
+    }
+
+    async fn run(server: String, path: PathBuf, mut events: mpsc::UnboundedReceiver<Event>) {
+        let lua = match load(&server, &path).await {
+            Ok(lua) => lua,
+            Err(e) => {
+                warn!(server, path = %path.display(), %e, "Failed loading script");
+                return;
+            }
+        };
+        info!(server, path = %path.display(), "Loaded script");
+
+        while let Some(event) = events.recv().await {
+            if let Err(e) = call(&lua, &event).await {
+                warn!(server, %e, "Script failed");
+            }
+        }
+    }
+
+    async fn load(server: &str, path: &PathBuf) -> mlua::Result<Lua> {
+        let source = tokio::fs::read_to_string(path).await?;
+        let lua = Lua::new();
+        add_globals(&lua, server)?;
+        let chunk = lua
+            .load(source)
+            .set_name(path.display().to_string())
+            .into_function()?;
+        call_guarded(&lua, chunk, ()).await?;
+        Ok(lua)
+    }
+
+    async fn call(lua: &Lua, event: &Event) -> mlua::Result<()> {
+        let options = SerializeOptions::new()
+            .serialize_none_to_null(false)
+            .serialize_unit_to_null(false);
+        let (hook, argument) = match event {
+            Event::Poll(result) => (
+                "on_poll",
+                lua.to_value_with(&Poll::from(&**result), options)?,
+            ),
+            Event::Notification(notification) => {
+                ("on_event", lua.to_value_with(&**notification, options)?)
+            }
+        };
+        let Some(function) = lua.globals().get::<_, Option<Function>>(hook)? else {
+            return Ok(());
+        };
+        call_guarded(lua, function, argument).await
+    }
+
+    /// Calls `function` with `argument`, stopping it once it has taken [`CALL_TIMEOUT`].
+    async fn call_guarded<'lua>(
+        lua: &'lua Lua,
+        function: Function<'lua>,
+        argument: impl IntoLuaMulti<'lua>,
+    ) -> mlua::Result<()> {
+        let deadline = Instant::now() + CALL_TIMEOUT;
+        let thread = lua.create_thread(function)?;
+        // A script stuck in a loop never gets back to the timeout below, so it is stopped from
+        // within
+        thread.set_hook(
+            HookTriggers::new().every_nth_instruction(10_000),
+            move |_, _| {
+                if Instant::now() >= deadline {
+                    return Err(mlua::Error::runtime("Script took too long and was stopped"));
+                }
+                Ok(())
+            },
+        );
+        tokio::time::timeout(CALL_TIMEOUT, thread.into_async::<_, ()>(argument))
+            .await
+            .map_err(|_| mlua::Error::runtime("Script took too long and was stopped"))?
+    }
+
+    fn add_globals(lua: &Lua, server: &str) -> mlua::Result<()> {
+        let globals = lua.globals();
+
+        let server = server.to_owned();
+        globals.set(
+            "log",
+            lua.create_function(move |_, message: Variadic<String>| {
+                info!(server, "{}", message.join(" "));
+                Ok(())
+            })?,
+        )?;
+
+        let json = lua.create_table()?;
+        json.set(
+            "encode",
+            lua.create_function(|lua, value: Value| {
+                let value = lua.from_value::<serde_json::Value>(value)?;
+                serde_json::to_string(&value).map_err(mlua::Error::external)
+            })?,
+        )?;
+        json.set(
+            "decode",
+            lua.create_function(|lua, text: String| {
+                let value = serde_json::from_str::<serde_json::Value>(&text)
+                    .map_err(mlua::Error::external)?;
+                lua.to_value(&value)
+            })?,
+        )?;
+        globals.set("json", json)?;
+
+        let client = reqwest::Client::new();
+        let http = lua.create_table()?;
+        let get_client = client.clone();
+        http.set(
+            "get",
+            lua.create_async_function(move |_, (url, headers): (String, Option<Table>)| {
+                let request = with_headers(get_client.get(url), headers);
+                async move { send(request?).await }
+            })?,
+        )?;
+        http.set(
+            "post",
+            lua.create_async_function(
+                move |_, (url, body, headers): (String, Option<String>, Option<Table>)| {
+                    let request =
+                        with_headers(client.post(url).body(body.unwrap_or_default()), headers);
+                    async move { send(request?).await }
+                },
+            )?,
+        )?;
+        globals.set("http", http)?;
+        Ok(())
+    }
+
+    fn with_headers(
+        mut request: reqwest::RequestBuilder,
+        headers: Option<Table>,
+    ) -> mlua::Result<reqwest::RequestBuilder> {
+        let headers = headers
+            .map(|h| {
+                h.pairs::<String, String>()
+                    .collect::<mlua::Result<HashMap<_, _>>>()
+            })
+            .transpose()?;
+        for (name, value) in headers.into_iter().flatten() {
+            request = request.header(name, value);
+        }
+        Ok(request)
+    }
+
+    /// Sends `request`, returning the status code and the body of the response.
+    async fn send(request: reqwest::RequestBuilder) -> mlua::Result<(u16, String)> {
+        let response = request.send().await.map_err(mlua::Error::external)?;
+        let status = response.status().as_u16();
+        let body = response.text().await.map_err(mlua::Error::external)?;
+        Ok((status, body))
+    }
+}
@@ -0,0 +1,196 @@
+//! Statuses shared through Redis by replicas behind the same load balancer, from the URL in
+//! `MCSTATUS_REDIS_URL`. Whichever replica fetches a server first stores its status for the
+//! others, and fetches are claimed in Redis so the minimum fetch interval holds across all of
+//! them rather than per replica. Redis being unreachable only costs the sharing, every replica
+//! goes on fetching by itself.
+
+use mcstatus_core::ServerStatus;
+use redis::{aio::ConnectionManager, Client, RedisResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::SocketAddr,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::OnceCell;
+use tracing::{debug, warn};
+
+use crate::env_vars::Loader;
+
+/// How often a replica that lost the claim on a fetch looks for the status of whoever won it.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long Redis may take to answer, a slow Redis is treated like one that is down.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long Redis is left alone after failing, so requests don't each wait on it while it's down.
+const RETRY_AFTER: Duration = Duration::from_secs(5);
+
+pub struct SharedCache {
+    client: Client,
+    /// Connected on first use, and reconnected by the manager whenever the connection drops.
+    connection: OnceCell<ConnectionManager>,
+    /// Prepended to every key, so several deployments can share one Redis.
+    prefix: String,
+    /// When Redis last failed a command.
+    failed_at: Mutex<Option<Instant>>,
+}
+
+/// A status as it is stored, with when it was fetched, as instants mean nothing to other replicas.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    status: ServerStatus,
+    /// Unix timestamp in milliseconds.
+    fetched_at_ms: u64,
+}
+
+pub enum Claim {
+    /// This replica fetches the server.
+    Fetch,
+    /// Another one already is, or did within the interval, and this is its status.
+    Fetched(Box<ServerStatus>),
+    /// Another one did, but its status didn't show up in time.
+    Missing,
+}
+
+impl SharedCache {
+    pub fn from_env(vars: &mut Loader) -> Option<Self> {
+        const REDIS_URL: &str = "REDIS_URL";
+        const REDIS_KEY_PREFIX: &str = "REDIS_KEY_PREFIX";
+
+        let url = vars.secret(REDIS_URL)?;
+        let prefix = vars.string(REDIS_KEY_PREFIX, "mcstatus:");
+        Self::new(&url, prefix)
+            .map_err(|e| vars.invalid(REDIS_URL, format!("not a Redis URL: {e}")))
+            .ok()
+    }
+
+    /// # Errors
+    ///
+    /// If `url` is not a valid Redis URL, nothing is connected to until the cache is first used.
+    pub fn new(url: &str, prefix: String) -> RedisResult<Self> {
+        Ok(Self {
+            client: Client::open(url)?,
+            connection: OnceCell::new(),
+            prefix,
+            failed_at: Mutex::new(None),
+        })
+    }
+
+    /// The status another replica stored for `addr`, if it is still fresh.
+    pub async fn get(&self, addr: SocketAddr) -> Option<ServerStatus> {
+        let entry = self
+            .run::<Option<String>>(redis::cmd("GET").arg(self.key("status", addr)))
+            .await
+            .flatten()?;
+        serde_json::from_str::<Entry>(&entry)
+            .map_err(|e| warn!(%e, %addr, "Shared cache has an invalid status"))
+            .ok()
+            .map(Entry::into_status)
+    }
+
+    /// Stores `status` for the other replicas for `ttl`.
+    pub async fn put(&self, addr: SocketAddr, status: &ServerStatus, ttl: Duration) {
+        let entry = Entry {
+            status: ServerStatus {
+                cache: None,
+                ..status.clone()
+            },
+            fetched_at_ms: unix_millis(SystemTime::now() - status.fetched_at.elapsed()),
+        };
+        let Ok(entry) = serde_json::to_string(&entry) else {
+            return;
+        };
+        self.run::<()>(
+            redis::cmd("SET")
+                .arg(self.key("status", addr))
+                .arg(entry)
+                .arg("PX")
+                .arg(millis(ttl)),
+        )
+        .await;
+    }
+
+    /// Claims fetching `addr` for `interval`, or waits up to `timeout` for the status of the
+    /// replica that already has. Claims are always granted while Redis is unreachable.
+    pub async fn claim(&self, addr: SocketAddr, interval: Duration, timeout: Duration) -> Claim {
+        if interval.is_zero() {
+            return Claim::Fetch;
+        }
+        let claimed = self
+            .run::<Option<String>>(
+                redis::cmd("SET")
+                    .arg(self.key("claim", addr))
+                    .arg(1)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(millis(interval)),
+            )
+            .await;
+        if !matches!(claimed, Some(None)) {
+            return Claim::Fetch;
+        }
+
+        debug!(%addr, "Another replica fetched recently, waiting for its status");
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(status) = self.get(addr).await {
+                return Claim::Fetched(Box::new(status));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        Claim::Missing
+    }
+
+    /// Runs `command`, `None` if Redis couldn't be reached or failed it.
+    async fn run<T: redis::FromRedisValue>(&self, command: &redis::Cmd) -> Option<T> {
+        let failed_at = *self.failed_at();
+        if failed_at.is_some_and(|at| at.elapsed() < RETRY_AFTER) {
+            return None;
+        }
+        let run = async {
+            let connection = self
+                .connection
+                // Not retrying, as waiting on a Redis that is down holds up the request
+                .get_or_try_init(|| self.client.get_connection_manager_with_backoff(2, 100, 0))
+                .await?;
+            command.query_async(&mut connection.clone()).await
+        };
+        let error = match tokio::time::timeout(COMMAND_TIMEOUT, run).await {
+            Ok(Ok(value)) => return Some(value),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => format!("no answer within {COMMAND_TIMEOUT:?}"),
+        };
+        warn!(error, retry_after = ?RETRY_AFTER, "Redis failed, not sharing statuses for now");
+        *self.failed_at() = Some(Instant::now());
+        None
+    }
+
+    fn failed_at(&self) -> MutexGuard<'_, Option<Instant>> {
+        self.failed_at
+            .lock()
+            .expect("Redis failure lock should not be poisoned")
+    }
+
+    fn key(&self, kind: &str, addr: SocketAddr) -> String {
+        format!("{}{kind}:{addr}", self.prefix)
+    }
+}
+
+impl Entry {
+    fn into_status(self) -> ServerStatus {
+        let age = SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_millis(self.fetched_at_ms))
+            .unwrap_or_default();
+        ServerStatus {
+            fetched_at: Instant::now().checked_sub(age).unwrap_or_else(Instant::now),
+            ..self.status
+        }
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, millis)
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
@@ -0,0 +1,77 @@
+//! Signed responses, for widgets that embed statuses served through caches or proxies they don't
+//! trust. With `MCSTATUS_SIGNING_KEY` set, every response carries a
+//! `X-Mcstatus-Signature: t=<unix timestamp>,v1=<hex>` header, where the hex is the HMAC-SHA256 of
+//! the timestamp, a `.` and the body. Checking the timestamp too keeps old responses from being
+//! replayed once they are signed.
+
+use axum::{
+    body::{self, Body},
+    extract::State,
+    http::{HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{sync::Arc, time::SystemTime};
+use tracing::warn;
+
+use crate::{env_vars::Loader, history::unix_seconds};
+
+const HEADER: &str = "x-mcstatus-signature";
+
+pub struct Key(Vec<u8>);
+
+impl Key {
+    pub fn from_env(vars: &mut Loader) -> Option<Self> {
+        const SIGNING_KEY: &str = "SIGNING_KEY";
+
+        vars.secret(SIGNING_KEY)
+            .filter(|k| !k.is_empty())
+            .map(Self::new)
+    }
+
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        Self(key.into())
+    }
+
+    fn sign(&self, timestamp: u64, body: &[u8]) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC should take keys of any length");
+        mac.update(timestamp.to_string().as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        format!(
+            "t={timestamp},v1={}",
+            hex::encode(mac.finalize().into_bytes())
+        )
+    }
+}
+
+/// Signs the body of every response, which means buffering it, so long polls are only signed once
+/// they are answered.
+pub async fn sign(
+    State(key): State<Option<Arc<Key>>>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    let Some(key) = key else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let body = match body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!(%e, "Failed reading response body to sign");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed signing response").into_response();
+        }
+    };
+    let signature = key.sign(unix_seconds(SystemTime::now()), &body);
+    parts.headers.insert(
+        HEADER,
+        HeaderValue::from_str(&signature).expect("Signatures should be valid header values"),
+    );
+    Response::from_parts(parts, Body::from(body))
+}
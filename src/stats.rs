@@ -0,0 +1,146 @@
+//! `/admin/stats`, how the process is doing right now, for a quick look without a metrics stack.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    Extension, Json,
+};
+use mcstatus_core::mc_monitor;
+use serde::Serialize;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use crate::{admin, jwt, AppState};
+
+/// Counted since the process started.
+pub struct Counters {
+    started_at: Instant,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stale: AtomicU64,
+    in_flight: AtomicU64,
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            hits: AtomicU64::default(),
+            misses: AtomicU64::default(),
+            stale: AtomicU64::default(),
+            in_flight: AtomicU64::default(),
+        }
+    }
+}
+
+impl Counters {
+    pub fn cache_lookup(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stale_served(&self) {
+        self.stale.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a fetch as in flight until the returned guard is dropped.
+    pub fn fetching(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(&self.in_flight)
+    }
+}
+
+pub struct InFlight<'a>(&'a AtomicU64);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Serialize)]
+pub struct Stats {
+    uptime_seconds: f64,
+    /// Unset where it can't be read, which is anywhere but Linux.
+    memory: Option<Memory>,
+    tasks: Tasks,
+    cache: Cache,
+    /// Fetches of servers that haven't finished yet.
+    in_flight_fetches: u64,
+    mc_monitor: mc_monitor::Children,
+}
+
+#[derive(Serialize)]
+struct Memory {
+    resident_bytes: u64,
+    peak_resident_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct Tasks {
+    alive: usize,
+    workers: usize,
+    queued: usize,
+}
+
+#[derive(Serialize)]
+struct Cache {
+    entries: u64,
+    hits: u64,
+    misses: u64,
+    /// Share of lookups that were hits, unset before the first one.
+    hit_ratio: Option<f64>,
+    /// Stale statuses served because fetching failed.
+    stale_served: u64,
+}
+
+/// `GET /admin/stats`, for admins only.
+pub async fn handler(
+    State(state): State<AppState>,
+    claims: Option<Extension<jwt::Claims>>,
+    headers: HeaderMap,
+) -> Result<Json<Stats>, (StatusCode, String)> {
+    admin::authorize(&state, claims.as_ref().map(|c| &c.0), &headers)?;
+
+    let counters = &state.counters;
+    let hits = counters.hits.load(Ordering::Relaxed);
+    let misses = counters.misses.load(Ordering::Relaxed);
+    #[allow(clippy::cast_precision_loss)]
+    let hit_ratio = (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64);
+    let metrics = tokio::runtime::Handle::current().metrics();
+
+    Ok(Json(Stats {
+        uptime_seconds: counters.started_at.elapsed().as_secs_f64(),
+        memory: memory(),
+        tasks: Tasks {
+            alive: metrics.num_alive_tasks(),
+            workers: metrics.num_workers(),
+            queued: metrics.global_queue_depth(),
+        },
+        cache: Cache {
+            entries: state.cache.entry_count().await,
+            hits,
+            misses,
+            hit_ratio,
+            stale_served: counters.stale.load(Ordering::Relaxed),
+        },
+        in_flight_fetches: counters.in_flight.load(Ordering::Relaxed),
+        mc_monitor: mc_monitor::children(),
+    }))
+}
+
+/// From `/proc/self/status`, where sizes are in kilobytes.
+fn memory() -> Option<Memory> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let field = |name: &str| -> Option<u64> {
+        let line = status.lines().find_map(|l| l.strip_prefix(name))?;
+        let kilobytes = line.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+        Some(kilobytes * 1024)
+    };
+    Some(Memory {
+        resident_bytes: field("VmRSS:")?,
+        peak_resident_bytes: field("VmHWM:")?,
+    })
+}
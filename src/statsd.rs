@@ -0,0 +1,127 @@
+//! `StatsD` metrics: counters for fetch outcomes and cache hits, a timer for fetches, and gauges
+//! for the player counts and ping timings of the polled servers.
+
+use std::{collections::BTreeMap, fmt::Write, net::UdpSocket, sync::Arc};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::{
+    config,
+    metrics::{name_component, Cardinality},
+    poller::PollResult,
+};
+
+/// What per server metrics are about.
+pub struct Server<'a> {
+    pub address: &'a str,
+    pub labels: &'a BTreeMap<String, String>,
+}
+
+#[derive(Clone)]
+pub struct Client {
+    socket: Arc<UdpSocket>,
+    prefix: Arc<str>,
+    tags: bool,
+}
+
+impl Client {
+    /// # Errors
+    ///
+    /// If the socket can't be set up, like when the address doesn't resolve.
+    pub fn new(config: &config::StatsD) -> Result<Self, String> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .and_then(|socket| {
+                socket.connect(&config.address)?;
+                // Metrics are best effort, and should never hold up whatever is being measured
+                socket.set_nonblocking(true)?;
+                Ok(socket)
+            })
+            .map_err(|e| {
+                format!(
+                    "failed setting up StatsD socket for {}: {e}",
+                    config.address
+                )
+            })?;
+        info!(address = config.address, "Sending metrics to StatsD");
+
+        Ok(Self {
+            socket: Arc::new(socket),
+            prefix: config.prefix.as_str().into(),
+            tags: config.tags,
+        })
+    }
+
+    pub fn count(&self, name: &str, server: Option<&Server>) {
+        self.send(name, "1", "c", server);
+    }
+
+    pub fn gauge(&self, name: &str, value: f64, server: Option<&Server>) {
+        self.send(name, &value.to_string(), "g", server);
+    }
+
+    pub fn timing(&self, name: &str, milliseconds: f64, server: Option<&Server>) {
+        self.send(name, &milliseconds.to_string(), "ms", server);
+    }
+
+    fn send(&self, name: &str, value: &str, kind: &str, server: Option<&Server>) {
+        let prefix = &self.prefix;
+        let line = match server {
+            Some(server) if self.tags => {
+                let mut tags = format!("server:{}", server.address);
+                for (key, value) in server.labels {
+                    _ = write!(tags, ",{key}:{value}");
+                }
+                format!("{prefix}.{name}:{value}|{kind}|#{tags}")
+            }
+            // Labels need tags, there's nowhere to put them in the name
+            Some(server) => format!(
+                "{prefix}.{}.{name}:{value}|{kind}",
+                name_component(server.address)
+            ),
+            None => format!("{prefix}.{name}:{value}|{kind}"),
+        };
+        if let Err(e) = self.socket.send(line.as_bytes()) {
+            debug!(%e, "Failed sending StatsD metric");
+        }
+    }
+}
+
+/// Sends gauges for every poll result, for the servers and labels `cardinality` lets through.
+pub async fn run(
+    client: Client,
+    cardinality: Cardinality,
+    mut poll_results: broadcast::Receiver<Arc<PollResult>>,
+) {
+    loop {
+        let result = match poll_results.recv().await {
+            Ok(result) => result,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(skipped, "StatsD export fell behind, skipped poll results");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let address = cardinality.server(&result.server);
+        let labels = cardinality.labels(&result.labels);
+        let server = Server {
+            address: &address,
+            labels: &labels,
+        };
+        let server = Some(&server);
+        let status = result.status.as_ref().ok();
+        let output = status.and_then(|s| s.output.as_ref());
+        client.gauge("up", if output.is_some() { 1.0 } else { 0.0 }, server);
+        if let Some(rtt) = status.and_then(|s| s.icmp_rtt_ms) {
+            client.gauge("icmp_rtt_ms", rtt, server);
+        }
+        if let Some(timings) = status.and_then(|s| s.timings.as_ref()) {
+            client.gauge("connect_ms", timings.connect_ms, server);
+            client.gauge("exchange_ms", timings.exchange_ms, server);
+        }
+        if let Some(output) = output {
+            client.gauge("players_online", output.online_player_count.into(), server);
+            client.gauge("max_players", output.max_player_count.into(), server);
+        }
+    }
+}
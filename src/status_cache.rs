@@ -0,0 +1,88 @@
+//! Where fetched statuses are kept until they expire. [`MokaCache`], in memory, is used unless
+//! [`AppStateBuilder::status_cache`](crate::AppStateBuilder::status_cache) sets another, like one
+//! backed by Redis or a disk so statuses survive restarts.
+
+use axum::http::StatusCode;
+use mcstatus_core::ServerStatus;
+use moka::future::{Cache, CacheBuilder};
+use std::{future::Future, net::SocketAddr, pin::Pin, sync::Arc};
+
+use crate::overrides;
+
+pub type Fetched = Result<ServerStatus, (StatusCode, String)>;
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Statuses keyed by the resolved address of their server, each expiring after the cache TTL of
+/// that server.
+pub trait StatusCache: Send + Sync {
+    /// The cached status of `addr`, or the one `fetch` gets if there is none, along with whether
+    /// it was cached. Only successful fetches are kept, and lookups of a server that is already
+    /// being fetched should wait on that fetch instead of starting another.
+    fn get_or_fetch<'a>(
+        &'a self,
+        addr: SocketAddr,
+        fetch: BoxFuture<'a, Fetched>,
+    ) -> BoxFuture<'a, Result<(ServerStatus, bool), (StatusCode, String)>>;
+
+    /// Replaces the status of `addr`, starting its expiry over.
+    fn insert(&self, addr: SocketAddr, status: ServerStatus) -> BoxFuture<'_, ()>;
+
+    /// Every status that hasn't expired yet.
+    fn entries(&self) -> BoxFuture<'_, Vec<(SocketAddr, ServerStatus)>>;
+
+    /// How many statuses there are.
+    fn entry_count(&self) -> BoxFuture<'_, u64>;
+}
+
+/// The default cache, in memory.
+pub struct MokaCache {
+    cache: Cache<SocketAddr, ServerStatus>,
+}
+
+impl MokaCache {
+    pub(crate) fn new(capacity: u64, expiry: overrides::Expiry) -> Self {
+        Self {
+            cache: CacheBuilder::new(capacity).expire_after(expiry).build(),
+        }
+    }
+}
+
+impl StatusCache for MokaCache {
+    fn get_or_fetch<'a>(
+        &'a self,
+        addr: SocketAddr,
+        fetch: BoxFuture<'a, Fetched>,
+    ) -> BoxFuture<'a, Result<(ServerStatus, bool), (StatusCode, String)>> {
+        Box::pin(async move {
+            let entry = self
+                .cache
+                .entry(addr)
+                .or_try_insert_with(fetch)
+                .await
+                .map_err(|e: Arc<(StatusCode, String)>| (*e).clone())?;
+            let hit = !entry.is_fresh();
+            Ok((entry.into_value(), hit))
+        })
+    }
+
+    fn insert(&self, addr: SocketAddr, status: ServerStatus) -> BoxFuture<'_, ()> {
+        Box::pin(self.cache.insert(addr, status))
+    }
+
+    fn entries(&self) -> BoxFuture<'_, Vec<(SocketAddr, ServerStatus)>> {
+        Box::pin(async move {
+            self.cache
+                .iter()
+                .map(|(addr, status)| (*addr, status))
+                .collect()
+        })
+    }
+
+    fn entry_count(&self) -> BoxFuture<'_, u64> {
+        Box::pin(async move {
+            // Only counted once the cache catches up on what was inserted
+            self.cache.run_pending_tasks().await;
+            self.cache.entry_count()
+        })
+    }
+}
@@ -0,0 +1,144 @@
+//! Combined overviews of several servers at once, either every server in the config file or the
+//! members of one of its groups.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+
+use crate::{case::Case, status_error, AppState, ServerStatus};
+
+#[derive(Deserialize)]
+pub struct Params {
+    /// How the fields of the summary are named, see [`Case`].
+    case: Option<Case>,
+}
+
+#[derive(Serialize)]
+pub struct Summary {
+    /// Name of the group, when summarizing one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    servers_total: usize,
+    servers_online: usize,
+    players_online: u32,
+    max_players: u32,
+    servers: Vec<ServerSummary>,
+}
+
+#[derive(Serialize)]
+struct ServerSummary {
+    address: String,
+    online: bool,
+    players_online: Option<u16>,
+    max_players: Option<u16>,
+    version: Option<String>,
+    error: Option<String>,
+}
+
+impl ServerSummary {
+    fn new(address: String, status: Result<ServerStatus, (StatusCode, String)>) -> Self {
+        match status {
+            Ok(status) => {
+                let output = status.output.as_ref();
+                Self {
+                    address,
+                    online: output.is_some(),
+                    players_online: output.map(|o| o.online_player_count),
+                    max_players: output.map(|o| o.max_player_count),
+                    version: output.map(|o| o.version.clone()),
+                    error: status.error,
+                }
+            }
+            Err((_, error)) => Self {
+                address,
+                online: false,
+                players_online: None,
+                max_players: None,
+                version: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+impl Summary {
+    async fn collect(state: &AppState, addresses: Vec<String>, group: Option<String>) -> Self {
+        let mut fetches = JoinSet::new();
+        for (i, address) in addresses.into_iter().enumerate() {
+            let state = state.clone();
+            fetches.spawn(async move {
+                let status = match state.resolve(&address).await {
+                    Ok(addr) => state.cached_status(addr).await,
+                    Err(e) => Err(status_error(e)),
+                };
+                (i, ServerSummary::new(address, status))
+            });
+        }
+
+        let mut servers = Vec::with_capacity(fetches.len());
+        while let Some(result) = fetches.join_next().await {
+            servers.push(result.expect("Summary fetches should not panic"));
+        }
+        // Keep the order of the config file
+        servers.sort_unstable_by_key(|(i, _)| *i);
+        let servers = servers.into_iter().map(|(_, s)| s).collect::<Vec<_>>();
+
+        Self {
+            group,
+            servers_total: servers.len(),
+            servers_online: servers.iter().filter(|s| s.online).count(),
+            players_online: servers
+                .iter()
+                .filter_map(|s| s.players_online)
+                .map(u32::from)
+                .sum(),
+            max_players: servers
+                .iter()
+                .filter_map(|s| s.max_players)
+                .map(u32::from)
+                .sum(),
+            servers,
+        }
+    }
+
+    /// The summary as served, with its fields named in `case`.
+    fn into_response(self, case: Case) -> Response {
+        if case == Case::Snake {
+            return Json(self).into_response();
+        }
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        case.apply(&mut value);
+        Json(value).into_response()
+    }
+}
+
+pub async fn handler(Query(query): Query<Params>, State(state): State<AppState>) -> Response {
+    let addresses = state
+        .config
+        .servers
+        .iter()
+        .map(|s| s.address.clone())
+        .collect();
+    Summary::collect(&state, addresses, None)
+        .await
+        .into_response(Case::pick(query.case, &state.config))
+}
+
+pub async fn group_handler(
+    Path(name): Path<String>,
+    Query(query): Query<Params>,
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, String)> {
+    let Some(group) = state.config.groups.get(&name) else {
+        return Err((StatusCode::NOT_FOUND, format!("No group named {name}")));
+    };
+    let addresses = group.servers.clone();
+    Ok(Summary::collect(&state, addresses, Some(name))
+        .await
+        .into_response(Case::pick(query.case, &state.config)))
+}
@@ -0,0 +1,82 @@
+//! Integration with the systemd service manager: readiness and watchdog notifications for
+//! `Type=notify` units, and listeners handed over through socket activation. Everything here is
+//! a no-op when not running under systemd (or not on Unix at all).
+
+use crate::listener::Listener;
+use color_eyre::Result;
+use tracing::{info, warn};
+
+/// Takes the sockets passed in by systemd socket activation, which may be TCP or Unix sockets.
+pub fn activated_listeners() -> Result<Vec<Listener>> {
+    #[cfg(unix)]
+    {
+        use std::{
+            net::TcpListener,
+            os::{
+                fd::{FromRawFd, IntoRawFd},
+                unix::net::UnixListener,
+            },
+        };
+
+        sd_notify::listen_fds()?
+            .map(|fd| {
+                // SAFETY: systemd guarantees descriptors starting at SD_LISTEN_FDS_START are open
+                // sockets owned by this process, and `listen_fds` unsets the variables so they
+                // can only be claimed once.
+                let tcp = unsafe { TcpListener::from_raw_fd(fd) };
+                // Looking up the local address fails if the socket is not an inet socket
+                let listener = if let Ok(addr) = tcp.local_addr() {
+                    info!(fd, %addr, "Using tcp listener from systemd socket activation");
+                    tcp.set_nonblocking(true)?;
+                    Listener::Tcp(tokio::net::TcpListener::from_std(tcp)?)
+                } else {
+                    // SAFETY: The descriptor was just released from `tcp`, so it is still open and
+                    // owned by nothing else.
+                    let unix = unsafe { UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+                    info!(fd, "Using unix listener from systemd socket activation");
+                    unix.set_nonblocking(true)?;
+                    Listener::Unix(tokio::net::UnixListener::from_std(unix)?)
+                };
+                Ok(listener)
+            })
+            .collect()
+    }
+    #[cfg(not(unix))]
+    Ok(Vec::new())
+}
+
+pub fn notify_ready() {
+    #[cfg(unix)]
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Ready]) {
+        warn!(%e, "Failed notifying systemd of readiness");
+    }
+}
+
+pub fn notify_stopping() {
+    #[cfg(unix)]
+    if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Stopping]) {
+        warn!(%e, "Failed notifying systemd of shutdown");
+    }
+}
+
+/// Starts pinging the systemd watchdog at half the configured `WatchdogSec`, if it is enabled.
+pub fn spawn_watchdog() {
+    #[cfg(unix)]
+    {
+        let mut usec = 0;
+        if !sd_notify::watchdog_enabled(false, &mut usec) {
+            return;
+        }
+        let period = std::time::Duration::from_micros(usec) / 2;
+        info!(?period, "systemd watchdog enabled");
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = sd_notify::notify(false, &[sd_notify::NotifyState::Watchdog]) {
+                    warn!(%e, "Failed pinging systemd watchdog");
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,144 @@
+//! `/:url/players`, `/:url/version`, `/:url/motd` and `/:url/online`, single fields of a status as
+//! bare text, for OBS browser sources, MOTD bots and shell scripts that would rather not parse
+//! JSON. All but `/:url/online` answer 503 with the error while the server is offline.
+//!
+//! For anything else `?format=` on the status routes fills in a template instead, like
+//! `{online}/{max} on {version}`, with `{{` and `}}` for literal braces.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+use mcstatus_core::{MonitorOutput, ServerStatus};
+use tracing::debug;
+
+use crate::{status_error, AppState};
+
+/// Like `12/100`.
+pub async fn players(
+    Path(addr): Path<String>,
+    State(state): State<AppState>,
+) -> Result<String, (StatusCode, String)> {
+    let output = output(&state, &addr, "players").await?;
+    Ok(format!(
+        "{}/{}",
+        output.online_player_count, output.max_player_count
+    ))
+}
+
+pub async fn version(
+    Path(addr): Path<String>,
+    State(state): State<AppState>,
+) -> Result<String, (StatusCode, String)> {
+    Ok(output(&state, &addr, "version").await?.version)
+}
+
+/// Without colors or formatting, lines separated by `\n`.
+pub async fn motd(
+    Path(addr): Path<String>,
+    State(state): State<AppState>,
+) -> Result<String, (StatusCode, String)> {
+    Ok(output(&state, &addr, "motd").await?.motd)
+}
+
+/// `true` or `false`.
+pub async fn online(
+    Path(addr): Path<String>,
+    State(state): State<AppState>,
+) -> Result<&'static str, (StatusCode, String)> {
+    debug!(%addr, field = "online", "Text requested from api");
+
+    let addr = state.resolve(&addr).await.map_err(status_error)?;
+    let status = state.cached_status(addr).await?;
+    Ok(if status.output.is_some() {
+        "true"
+    } else {
+        "false"
+    })
+}
+
+async fn output(
+    state: &AppState,
+    addr: &str,
+    field: &str,
+) -> Result<MonitorOutput, (StatusCode, String)> {
+    debug!(%addr, field, "Text requested from api");
+
+    let addr = state.resolve(addr).await.map_err(status_error)?;
+    let status = state.cached_status(addr).await?;
+    status.output.ok_or_else(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            status
+                .error
+                .unwrap_or_else(|| format!("{} is offline", status.requested_url)),
+        )
+    })
+}
+
+/// What `{...}` can name in a `?format=` template.
+const PLACEHOLDERS: &[&str] = &["online", "max", "version", "motd", "status", "address"];
+
+/// Fills in `template` from `status`, with `address` as what the server is called. Fields an
+/// offline server doesn't report are left empty, `{status}` tells `online` and `offline` apart.
+///
+/// # Errors
+///
+/// If `template` names an unknown placeholder, or has an unmatched brace.
+pub fn format(template: &str, status: &ServerStatus, address: &str) -> Result<String, String> {
+    let output = status.output.as_ref();
+    let mut text = String::with_capacity(template.len());
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let Some((name, after)) = rest.split_once('}') else {
+                    return Err(format!("Format has an unclosed {{ before {rest:?}"));
+                };
+                match name {
+                    "online" => {
+                        if let Some(o) = output {
+                            text.push_str(&o.online_player_count.to_string());
+                        }
+                    }
+                    "max" => {
+                        if let Some(o) = output {
+                            text.push_str(&o.max_player_count.to_string());
+                        }
+                    }
+                    "version" => text.push_str(output.map_or("", |o| &o.version)),
+                    "motd" => text.push_str(output.map_or("", |o| &o.motd)),
+                    "status" => text.push_str(if output.is_some() {
+                        "online"
+                    } else {
+                        "offline"
+                    }),
+                    "address" => text.push_str(address),
+                    _ => {
+                        return Err(format!(
+                            "Unknown placeholder {{{name}}} in format, expected one of {}",
+                            PLACEHOLDERS.join(", ")
+                        ))
+                    }
+                }
+                chars = after.chars();
+            }
+            '}' => {
+                return Err(
+                    "Format has a } that closes nothing, write }} for a literal one".to_owned(),
+                )
+            }
+            c => text.push(c),
+        }
+    }
+    Ok(text)
+}
@@ -0,0 +1,86 @@
+//! Upper bounds on how long a request may take, configurable per route, after which the client
+//! gets a 504 instead of waiting on a slow upstream.
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use std::{collections::HashMap, time::Duration};
+use tracing::warn;
+
+use crate::env_vars::{self, Loader};
+
+pub struct RouteTimeouts {
+    default: Duration,
+    /// Overrides keyed by the route pattern, as in `/:url`.
+    routes: HashMap<String, Duration>,
+}
+
+impl RouteTimeouts {
+    /// The same timeout for every route.
+    pub fn new(default: Duration) -> Self {
+        Self {
+            default,
+            routes: HashMap::new(),
+        }
+    }
+
+    /// Reads the default from `MCSTATUS_HTTP_TIMEOUT`, and overrides from
+    /// `MCSTATUS_HTTP_ROUTE_TIMEOUTS` as a comma separated list of `route=duration`, like
+    /// `/:url=15 seconds,/favicon.ico=1 second`.
+    pub fn from_env(vars: &mut Loader) -> Self {
+        const HTTP_TIMEOUT: &str = "HTTP_TIMEOUT";
+        const HTTP_ROUTE_TIMEOUTS: &str = "HTTP_ROUTE_TIMEOUTS";
+
+        let default = vars.duration(HTTP_TIMEOUT, "15 seconds");
+        let routes = vars.parse(HTTP_ROUTE_TIMEOUTS, "", |routes| {
+            routes
+                .split(',')
+                .filter(|r| !r.trim().is_empty())
+                .map(|r| {
+                    let (route, timeout) = r
+                        .split_once('=')
+                        .ok_or_else(|| format!("entry {r} is not `route=duration`"))?;
+                    Ok((route.trim().to_owned(), env_vars::duration(timeout)?))
+                })
+                .collect::<Result<HashMap<_, _>, String>>()
+        });
+        Self { default, routes }
+    }
+
+    pub fn for_route(&self, route: &str) -> Duration {
+        self.routes.get(route).copied().unwrap_or(self.default)
+    }
+}
+
+#[derive(Serialize)]
+struct TimeoutError {
+    error: &'static str,
+    timeout_seconds: f64,
+}
+
+pub async fn enforce(
+    State(limit): State<Duration>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_owned();
+    if let Ok(response) = tokio::time::timeout(limit, next.run(request)).await {
+        return response;
+    }
+
+    warn!(path, ?limit, "Request timed out");
+    (
+        StatusCode::GATEWAY_TIMEOUT,
+        Json(TimeoutError {
+            error: "Request timed out",
+            timeout_seconds: limit.as_secs_f64(),
+        }),
+    )
+        .into_response()
+}
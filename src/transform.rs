@@ -0,0 +1,54 @@
+//! The [`Transform`] rules of the config file, applied to statuses once they are JSON.
+
+use serde_json::{Map, Value};
+
+use crate::config::Transform;
+
+/// Applies `rules` to `value`. Paths through fields that aren't objects are left alone.
+pub fn apply(rules: &Transform, value: &mut Value) {
+    for (from, to) in &rules.rename {
+        if let Some(field) = take(value, from) {
+            put(value, to, field);
+        }
+    }
+    for path in &rules.drop {
+        take(value, path);
+    }
+    for (path, constant) in &rules.set {
+        put(value, path, constant.clone());
+    }
+}
+
+/// Removes the field at `path`, returning it.
+fn take(value: &mut Value, path: &str) -> Option<Value> {
+    let (parents, name) = split(path);
+    let mut object = value.as_object_mut()?;
+    for key in parents {
+        object = object.get_mut(key)?.as_object_mut()?;
+    }
+    object.remove(name)
+}
+
+/// Puts `field` at `path`, making whatever objects on the way there are missing.
+fn put(value: &mut Value, path: &str, field: Value) {
+    let (parents, name) = split(path);
+    let Some(mut object) = value.as_object_mut() else {
+        return;
+    };
+    for key in parents {
+        let next = object
+            .entry(key)
+            .or_insert_with(|| Value::Object(Map::new()));
+        let Some(next) = next.as_object_mut() else {
+            return;
+        };
+        object = next;
+    }
+    object.insert(name.to_owned(), field);
+}
+
+/// The fields leading to what `path` names, and its name.
+fn split(path: &str) -> (impl Iterator<Item = &str>, &str) {
+    let (parents, name) = path.rsplit_once('.').unwrap_or(("", path));
+    (parents.split('.').filter(|key| !key.is_empty()), name)
+}
@@ -0,0 +1,105 @@
+//! `/:url/wait`, long polling for a change of a server's status, for clients that want to react to
+//! changes without a `WebSocket`. The status is checked again whenever its cache entry expires or a
+//! polled server was polled, so waiting adds no fetches of its own.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use mcstatus_core::ServerStatus;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::{status_error, AppState};
+
+/// How long to wait for a change when no timeout is asked for.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest `?timeout=` accepted, past this proxies in between tend to give up first.
+const MAX_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// Shortest time between two checks, so a flood of poll results can't turn waiting into spinning.
+const MIN_RECHECK: Duration = Duration::from_millis(100);
+
+#[derive(Deserialize)]
+pub struct Options {
+    /// How long to wait, like `60s`.
+    timeout: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Outcome {
+    /// False when the timeout elapsed first, and the status is the one from before.
+    changed: bool,
+    #[serde(flatten)]
+    status: ServerStatus,
+}
+
+pub async fn handler(
+    Path(addr): Path<String>,
+    Query(query): Query<Options>,
+    State(state): State<AppState>,
+) -> Result<Json<Outcome>, (StatusCode, String)> {
+    let timeout = query
+        .timeout
+        .as_deref()
+        .map_or(Ok(DEFAULT_TIMEOUT), |timeout| {
+            parse_duration::parse(timeout).map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("Expected timeout {timeout} to be a duration"),
+                )
+            })
+        })?;
+    if timeout > MAX_TIMEOUT {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("The timeout can be at most {MAX_TIMEOUT:?}"),
+        ));
+    }
+    debug!(%addr, ?timeout, "Waiting for a change from api");
+
+    let addr = state.resolve(&addr).await.map_err(status_error)?;
+    let deadline = Instant::now() + timeout;
+    let mut poll_results = state.poll_results.subscribe();
+    let initial = state.cached_status(addr.clone()).await?;
+    let mut status = initial.clone();
+    let mut checked_at = Instant::now();
+
+    loop {
+        if changed(&initial, &status) {
+            return Ok(Json(Outcome {
+                changed: true,
+                status,
+            }));
+        }
+
+        // Expired entries are fetched again by the next lookup, and polls replace them early
+        let expires_in = status
+            .cache
+            .as_ref()
+            .map_or(0.0, |cache| cache.expires_in_seconds);
+        let recheck_at = Instant::now() + Duration::from_secs_f64(expires_in).max(MIN_RECHECK);
+        tokio::select! {
+            () = tokio::time::sleep_until(recheck_at.min(deadline)) => {}
+            _ = poll_results.recv() => {
+                tokio::time::sleep_until((checked_at + MIN_RECHECK).min(deadline)).await;
+            }
+        }
+        if Instant::now() >= deadline {
+            return Ok(Json(Outcome {
+                changed: false,
+                status: initial,
+            }));
+        }
+        status = state.cached_status(addr.clone()).await?;
+        checked_at = Instant::now();
+    }
+}
+
+/// Whether the server went up or down, or anything it reports changed. Errors of a server that
+/// stays offline are not compared, they differ with every failed fetch.
+fn changed(before: &ServerStatus, after: &ServerStatus) -> bool {
+    before.output != after.output
+}